@@ -0,0 +1,133 @@
+use std::env::consts;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    #[default]
+    X86_64,
+    Aarch64,
+    Riscv64,
+}
+
+impl Arch {
+    pub fn target(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64-unknown-uefi",
+            Arch::Aarch64 => "aarch64-unknown-uefi",
+            Arch::Riscv64 => "riscv64gc-unknown-uefi",
+        }
+    }
+
+    pub fn efi_name(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "BOOTX64.EFI",
+            Arch::Aarch64 => "BOOTAA64.EFI",
+            Arch::Riscv64 => "BOOTRISCV64.EFI",
+        }
+    }
+
+    pub fn pe_machine(&self) -> u16 {
+        match self {
+            Arch::X86_64 => 0x8664,
+            Arch::Aarch64 => 0xaa64,
+            Arch::Riscv64 => 0x5064,
+        }
+    }
+
+    pub fn firmware_hints(&self) -> &'static [&'static str] {
+        match self {
+            Arch::X86_64 => &["ovmf", "x64", "x86"],
+            Arch::Aarch64 => &["aavmf", "aarch64", "arm"],
+            Arch::Riscv64 => &["riscv"],
+        }
+    }
+
+    pub fn all() -> [Arch; 3] {
+        [Arch::X86_64, Arch::Aarch64, Arch::Riscv64]
+    }
+
+    pub fn from_target(target: &str) -> Option<Arch> {
+        Arch::all().into_iter().find(|arch| arch.target() == target)
+    }
+
+    pub fn machine_args(&self) -> &'static [&'static str] {
+        match self {
+            Arch::X86_64 => &["-machine", "q35"],
+            Arch::Aarch64 | Arch::Riscv64 => &["-machine", "virt"],
+        }
+    }
+
+    pub fn default_cpu(&self) -> Option<&'static str> {
+        match self {
+            Arch::Aarch64 => Some("max"),
+            _ => None,
+        }
+    }
+
+    pub fn pflash_files(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Arch::X86_64 => &[("OVMF_CODE.fd", "OVMF_VARS.fd"), ("OVMF_CODE_4M.fd", "OVMF_VARS_4M.fd"),
+                              ("OVMF_CODE.4m.fd", "OVMF_VARS.4m.fd"), ("edk2-x86_64-code.fd", "edk2-i386-vars.fd")],
+            Arch::Aarch64 => &[("AAVMF_CODE.fd", "AAVMF_VARS.fd"),
+                               ("QEMU_EFI-pflash.raw", "vars-template-pflash.raw"),
+                               ("QEMU_CODE.fd", "QEMU_VARS.fd"), ("edk2-aarch64-code.fd", "edk2-arm-vars.fd")],
+            Arch::Riscv64 => &[("RISCV_VIRT_CODE.fd", "RISCV_VIRT_VARS.fd"),
+                               ("edk2-riscv-code.fd", "edk2-riscv-vars.fd")],
+        }
+    }
+
+    pub fn secure_pflash_files(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Arch::X86_64 => &[("OVMF_CODE.secboot.fd", "OVMF_VARS.fd"), ("OVMF_CODE_4M.secboot.fd", "OVMF_VARS_4M.fd"),
+                              ("OVMF_CODE.secboot.4m.fd", "OVMF_VARS.4m.fd"),
+                              ("edk2-x86_64-secure-code.fd", "edk2-i386-vars.fd")],
+            // AAVMF and the RISC-V builds ship with Secure Boot compiled in and need no SMM variant.
+            Arch::Aarch64 | Arch::Riscv64 => self.pflash_files(),
+        }
+    }
+
+    pub fn bios_files(&self) -> &'static [&'static str] {
+        match self {
+            Arch::X86_64 => &["OVMF.fd"],
+            Arch::Aarch64 => &["QEMU_EFI.fd"],
+            Arch::Riscv64 => &["RISCV_VIRT.fd"],
+        }
+    }
+
+    pub fn from_efi_name(name: &str) -> Option<Arch> {
+        Arch::all().into_iter()
+            .find(|arch| arch.efi_name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn qemu_binary(&self) -> String {
+        let system = match self {
+            Arch::X86_64 => "qemu-system-x86_64",
+            Arch::Aarch64 => "qemu-system-aarch64",
+            Arch::Riscv64 => "qemu-system-riscv64",
+        };
+        format!("{}{}", system, consts::EXE_SUFFIX)
+    }
+
+    pub fn accel_on(&self, os: &str, host_arch: &str) -> &'static str {
+        ACCELERATORS.iter()
+            .find(|(host_os, host, guest, _)| *host_os == os && *host == host_arch && guest == self)
+            .map(|(.., accel)| *accel)
+            .unwrap_or("tcg")
+    }
+
+    pub fn accel(&self) -> &'static str {
+        self.accel_on(consts::OS, consts::ARCH)
+    }
+}
+
+// (host OS, host arch, guest arch, accelerator); anything missing falls back to TCG.
+const ACCELERATORS: &[(&str, &str, Arch, &str)] = &[
+    ("linux", "x86_64", Arch::X86_64, "kvm"),
+    ("linux", "aarch64", Arch::Aarch64, "kvm"),
+    ("linux", "riscv64", Arch::Riscv64, "kvm"),
+    ("macos", "x86_64", Arch::X86_64, "hvf"),
+    ("macos", "aarch64", Arch::Aarch64, "hvf"),
+    ("windows", "x86_64", Arch::X86_64, "whpx"),
+    ("windows", "aarch64", Arch::Aarch64, "whpx"),
+];
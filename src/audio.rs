@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::outcome::{TestResult, TestStatus};
+
+const WAV_HEADER: usize = 44;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioModel {
+    #[default]
+    Hda,
+    Ac97,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    None,
+    #[default]
+    Wav,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AudioConfig {
+    #[serde(default)]
+    pub model: AudioModel,
+    #[serde(default)]
+    pub backend: AudioBackend,
+    #[serde(default)]
+    pub expect_sound: bool,
+}
+
+impl AudioConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.expect_sound && self.backend != AudioBackend::Wav {
+            return Err("audio expect_sound needs the wav backend".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn capture(&self, artifacts_dir: &str) -> Option<PathBuf> {
+        (self.backend == AudioBackend::Wav).then(|| Path::new(artifacts_dir).join("audio.wav"))
+    }
+
+    pub fn qemu_args(&self, artifacts_dir: &str) -> Vec<String> {
+        let audiodev = match self.capture(artifacts_dir) {
+            Some(wav) => format!("wav,id=snd0,path={}", wav.display()),
+            None => "none,id=snd0".to_string(),
+        };
+        let mut args = vec!["-audiodev".to_string(), audiodev];
+        match self.model {
+            AudioModel::Hda => args.extend(["-device".to_string(), "ich9-intel-hda".to_string(),
+                                            "-device".to_string(), "hda-output,audiodev=snd0".to_string()]),
+            AudioModel::Ac97 => args.extend(["-device".to_string(), "AC97,audiodev=snd0".to_string()]),
+        }
+        args
+    }
+
+    pub fn verify(&self, artifacts_dir: &str) -> Option<TestResult> {
+        if !self.expect_sound {
+            return None;
+        }
+        let samples = self.capture(artifacts_dir).and_then(|wav| fs::read(wav).ok())
+            .map(|wav| wav.iter().skip(WAV_HEADER).filter(|byte| **byte != 0).count())
+            .unwrap_or(0);
+        Some(TestResult {
+            name: "audio".to_string(),
+            status: if samples > 0 { TestStatus::Passed } else { TestStatus::Failed },
+            duration_ms: None,
+            message: (samples == 0).then(|| "the captured audio is silent".to_string()),
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use regex::Regex;
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::pipeline::{Context, Pipeline};
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl Verdict {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Good => "good",
+            Verdict::Bad => "bad",
+            Verdict::Skip => "skip",
+        }
+    }
+}
+
+fn git(project: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git").args(args).current_dir(project).output()
+        .map_err(|e| format!("Failed to run git {}: {}", args.join(" "), e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}{}", args.join(" "), stdout,
+                           String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(stdout)
+}
+
+pub fn classify(config: &RunnerConfig, failure: &Regex, timeout: Duration) -> Verdict {
+    let matched = Arc::new(AtomicBool::new(false));
+    let control = Arc::new(Control::default());
+    let hooks = {
+        let matched = matched.clone();
+        let control = control.clone();
+        let failure = failure.clone();
+        Hooks::default().on_serial_line(move |line| {
+            if failure.is_match(line) {
+                info!("Failure pattern matched: {}", line);
+                matched.store(true, Ordering::Release);
+                control.request_kill();
+            }
+        })
+    };
+    let done = Arc::new(AtomicBool::new(false));
+    {
+        let control = control.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let start = Instant::now();
+            while !done.load(Ordering::Acquire) {
+                if start.elapsed() > timeout {
+                    info!("Boot timed out after {:?}", timeout);
+                    control.request_kill();
+                    return;
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+        });
+    }
+    let mut ctx = Context::new(config.clone(), Arc::new(hooks), control);
+    let result = Pipeline::standard().run(&mut ctx);
+    done.store(true, Ordering::Release);
+    match result {
+        _ if matched.load(Ordering::Acquire) => Verdict::Bad,
+        Ok(()) => Verdict::Good,
+        Err(e) => {
+            warn!("Candidate could not be tested: {}", e);
+            Verdict::Skip
+        }
+    }
+}
+
+pub fn run(config: &RunnerConfig, good: &str, bad: &str, until: &str, timeout: Duration) -> bool {
+    let failure = match Regex::new(until) {
+        Ok(failure) => failure,
+        Err(e) => {
+            error!("Invalid failure pattern {}: {}", until, e);
+            return false;
+        }
+    };
+    let config = RunnerConfig {
+        auto_build: true,
+        move_binary: false,
+        stdio_serial: false,
+        matrix: None,
+        ..config.clone()
+    };
+    let project = config.project_path.clone();
+    if let Err(e) = git(&project, &["bisect", "start", bad, good]) {
+        error!("{}", e);
+        return false;
+    }
+    let result = loop {
+        let commit = git(&project, &["rev-parse", "--short", "HEAD"]).unwrap_or_default();
+        info!("Testing {}", commit.trim());
+        let verdict = classify(&config, &failure, timeout);
+        info!("{} is {}", commit.trim(), verdict.as_str());
+        match git(&project, &["bisect", verdict.as_str()]) {
+            Ok(output) if output.contains("is the first bad commit") => break Ok(output),
+            Ok(output) if output.contains("only 'skip'ped commits left") => break Ok(output),
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    let _ = git(&project, &["bisect", "reset"]);
+    match result {
+        Ok(output) => {
+            println!("{}", output.trim());
+            true
+        }
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    }
+}
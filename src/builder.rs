@@ -0,0 +1,107 @@
+use crate::arch::Arch;
+use crate::firmware::FirmwareMode;
+use crate::preset::Preset;
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Default)]
+pub struct RunnerConfigBuilder {
+    config: RunnerConfig,
+    arch: Option<Arch>,
+    efi_name: Option<String>,
+    build_cmd: Option<String>,
+    qemu_cmd: Option<String>,
+}
+
+impl RunnerConfig {
+    pub fn builder() -> RunnerConfigBuilder {
+        RunnerConfigBuilder::default()
+    }
+}
+
+impl RunnerConfigBuilder {
+    pub fn project_path(mut self, path: impl Into<String>) -> Self {
+        self.config.project_path = path.into();
+        self
+    }
+
+    pub fn arch(mut self, arch: Arch) -> Self {
+        self.arch = Some(arch);
+        self
+    }
+
+    pub fn auto_build(mut self, auto_build: bool) -> Self {
+        self.config.auto_build = auto_build;
+        self
+    }
+
+    pub fn build_cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.build_cmd = Some(cmd.into());
+        self
+    }
+
+    pub fn binary_path(mut self, path: impl Into<String>) -> Self {
+        self.config.binary_path = path.into();
+        self
+    }
+
+    pub fn efi_name(mut self, name: impl Into<String>) -> Self {
+        self.efi_name = Some(name.into());
+        self
+    }
+
+    pub fn move_binary(mut self, move_binary: bool) -> Self {
+        self.config.move_binary = move_binary;
+        self
+    }
+
+    pub fn qemu_cmd(mut self, cmd: impl Into<String>) -> Self {
+        self.qemu_cmd = Some(cmd.into());
+        self
+    }
+
+    pub fn ovmf_path(mut self, path: impl Into<String>) -> Self {
+        self.config.ovmf_path = path.into();
+        self
+    }
+
+    pub fn firmware_mode(mut self, mode: FirmwareMode) -> Self {
+        self.config.firmware_mode = mode;
+        self
+    }
+
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.config.preset = Some(preset);
+        self
+    }
+
+    pub fn cpu(mut self, cpu: impl Into<String>) -> Self {
+        self.config.cpu = Some(cpu.into());
+        self
+    }
+
+    pub fn memory(mut self, memory: impl Into<String>) -> Self {
+        self.config.memory = Some(memory.into());
+        self
+    }
+
+    pub fn serial(mut self, stdio: bool, log_path: Option<String>) -> Self {
+        self.config.stdio_serial = stdio;
+        self.config.log_serial = log_path.is_some();
+        if let Some(path) = log_path {
+            self.config.log_path = path;
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<RunnerConfig, String> {
+        let mut config = self.config;
+        let arch = self.arch.unwrap_or_default();
+        config.arch = self.arch;
+        config.efi_name = self.efi_name.unwrap_or_else(|| arch.efi_name().to_string());
+        config.build_cmd = self.build_cmd
+            .unwrap_or_else(|| format!("build --target {}", arch.target()));
+        config.qemu_cmd = self.qemu_cmd.unwrap_or_else(|| arch.qemu_binary());
+        config.validate()?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::qmp::{Qmp, ScheduledCommand, Trigger};
+
+pub const RTC_COMMAND: &str = "x-uefapi-rtc";
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_WEEKDAY: u8 = 0x06;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_B: u8 = 0x0b;
+const REG_CENTURY: u8 = 0x32;
+const REG_B_SET: u8 = 0x80;
+const REG_B_BINARY: u8 = 0x04;
+const REG_B_24H: u8 = 0x02;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ClockStep {
+    #[serde(default)]
+    pub after_secs: Option<u64>,
+    #[serde(default)]
+    pub on_serial: Option<String>,
+    #[serde(default)]
+    pub jump_secs: Option<i64>,
+    #[serde(default)]
+    pub set: Option<String>,
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+pub fn parse_time(text: &str) -> Option<i64> {
+    let text = text.trim_end_matches('Z');
+    let (date, time) = text.split_once(['T', ' ']).unwrap_or((text, "00:00:00"));
+    let date = date.split('-').map(|part| part.parse().ok()).collect::<Option<Vec<i64>>>()?;
+    let time = time.split(':').map(|part| part.parse().ok()).collect::<Option<Vec<i64>>>()?;
+    let ([year, month, day], [hour, minute, second]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    if !(1..=12).contains(month) || !(1..=31).contains(day) || *hour > 23 || *minute > 59 || *second > 59 {
+        return None;
+    }
+    Some(days_from_civil(*year, *month, *day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+fn format_time(unix: i64) -> String {
+    let (year, month, day) = civil_from_days(unix.div_euclid(86400));
+    let secs = unix.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn hmp(qmp: &mut Qmp, command: String) -> Result<String, String> {
+    let reply = qmp.execute("human-monitor-command", json!({ "command-line": command }))?;
+    Ok(reply.as_str().unwrap_or_default().to_string())
+}
+
+fn read_cmos(qmp: &mut Qmp, register: u8) -> Result<u8, String> {
+    hmp(qmp, format!("o /b {:#x} {:#x}", CMOS_INDEX, register))?;
+    let reply = hmp(qmp, format!("i /b {:#x}", CMOS_DATA))?;
+    let value = reply.rsplit("0x").next().unwrap_or_default().trim();
+    u8::from_str_radix(value, 16).map_err(|_| format!("Unexpected CMOS read reply {:?}", reply))
+}
+
+fn write_cmos(qmp: &mut Qmp, register: u8, value: u8) -> Result<(), String> {
+    hmp(qmp, format!("o /b {:#x} {:#x}", CMOS_INDEX, register))?;
+    hmp(qmp, format!("o /b {:#x} {:#x}", CMOS_DATA, value))?;
+    Ok(())
+}
+
+struct Encoding {
+    binary: bool,
+    hours_24: bool,
+}
+
+impl Encoding {
+    fn decode(&self, value: u8) -> i64 {
+        i64::from(if self.binary { value } else { (value >> 4) * 10 + (value & 0x0f) })
+    }
+
+    fn encode(&self, value: i64) -> u8 {
+        let value = value as u8;
+        if self.binary { value } else { ((value / 10) << 4) | (value % 10) }
+    }
+
+    fn decode_hours(&self, value: u8) -> i64 {
+        if self.hours_24 {
+            return self.decode(value);
+        }
+        let hour = self.decode(value & 0x7f) % 12;
+        if value & 0x80 != 0 { hour + 12 } else { hour }
+    }
+
+    fn encode_hours(&self, hour: i64) -> u8 {
+        if self.hours_24 {
+            return self.encode(hour);
+        }
+        let twelve = if hour % 12 == 0 { 12 } else { hour % 12 };
+        self.encode(twelve) | if hour >= 12 { 0x80 } else { 0 }
+    }
+}
+
+fn read_rtc(qmp: &mut Qmp, encoding: &Encoding) -> Result<i64, String> {
+    let mut read = |register| read_cmos(qmp, register).map(|value| encoding.decode(value));
+    let (second, minute) = (read(REG_SECONDS)?, read(REG_MINUTES)?);
+    let (day, month, year, century) = (read(REG_DAY)?, read(REG_MONTH)?, read(REG_YEAR)?, read(REG_CENTURY)?);
+    let hour = encoding.decode_hours(read_cmos(qmp, REG_HOURS)?);
+    Ok(days_from_civil(century * 100 + year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+fn write_rtc(qmp: &mut Qmp, encoding: &Encoding, reg_b: u8, unix: i64) -> Result<(), String> {
+    let days = unix.div_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let secs = unix.rem_euclid(86400);
+    write_cmos(qmp, REG_B, reg_b | REG_B_SET)?;
+    let fields = [
+        (REG_SECONDS, encoding.encode(secs % 60)),
+        (REG_MINUTES, encoding.encode(secs / 60 % 60)),
+        (REG_HOURS, encoding.encode_hours(secs / 3600)),
+        (REG_WEEKDAY, encoding.encode((days + 4).rem_euclid(7) + 1)),
+        (REG_DAY, encoding.encode(day)),
+        (REG_MONTH, encoding.encode(month)),
+        (REG_YEAR, encoding.encode(year % 100)),
+        (REG_CENTURY, encoding.encode(year / 100)),
+    ];
+    for (register, value) in fields {
+        write_cmos(qmp, register, value)?;
+    }
+    write_cmos(qmp, REG_B, reg_b & !REG_B_SET)
+}
+
+pub fn apply(qmp: &mut Qmp, arguments: &Value) -> Result<(), String> {
+    qmp.execute("stop", json!({}))?;
+    let result = (|| {
+        let reg_b = read_cmos(qmp, REG_B)?;
+        let encoding = Encoding { binary: reg_b & REG_B_BINARY != 0, hours_24: reg_b & REG_B_24H != 0 };
+        let now = read_rtc(qmp, &encoding)?;
+        let target = match (arguments["set"].as_i64(), arguments["jump_secs"].as_i64()) {
+            (Some(set), _) => set,
+            (None, Some(jump)) => now + jump,
+            (None, None) => return Err("clock step has neither set nor jump_secs".to_string()),
+        };
+        write_rtc(qmp, &encoding, reg_b, target)?;
+        info!("Guest RTC moved from {} to {}", format_time(now), format_time(target));
+        Ok(())
+    })();
+    qmp.execute("cont", json!({}))?;
+    result
+}
+
+impl ClockStep {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.after_secs.is_some() == self.on_serial.is_some() {
+            return Err("clock step needs exactly one of after_secs or on_serial".to_string());
+        }
+        match (&self.jump_secs, &self.set) {
+            (Some(_), None) => Ok(()),
+            (None, Some(set)) => parse_time(set).map(|_| ())
+                .ok_or_else(|| format!("invalid clock set time {}, expected YYYY-MM-DDTHH:MM:SS", set)),
+            _ => Err("clock step needs exactly one of jump_secs or set".to_string()),
+        }
+    }
+
+    pub fn qmp_command(&self) -> ScheduledCommand {
+        let trigger = match (&self.after_secs, &self.on_serial) {
+            (Some(secs), _) => Trigger::After(Duration::from_secs(*secs)),
+            (None, Some(marker)) => Trigger::Serial(marker.clone()),
+            (None, None) => Trigger::After(Duration::ZERO),
+        };
+        let arguments = match &self.set {
+            Some(set) => json!({ "set": parse_time(set) }),
+            None => json!({ "jump_secs": self.jump_secs }),
+        };
+        ScheduledCommand { trigger, command: RTC_COMMAND.to_string(), arguments }
+    }
+}
@@ -0,0 +1,58 @@
+use std::fs;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::{plugins, RunnerConfig};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct CountersConfig {
+    pub plugin: String,
+    #[serde(default)]
+    pub icount_shift: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct GuestCounters {
+    pub instructions: u64,
+    #[serde(default)]
+    pub blocks: Option<u64>,
+}
+
+impl CountersConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.icount_shift.is_some_and(|shift| shift > 10) {
+            return Err("counters icount_shift must be between 0 and 10".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        match self.icount_shift {
+            Some(shift) => vec!["-icount".to_string(), format!("shift={}", shift)],
+            None => Vec::new(),
+        }
+    }
+}
+
+pub fn parse(report: &str) -> Option<GuestCounters> {
+    let line = Regex::new(r"^(vcpu \d+ )?(?:total )?(?:bb's: (\d+), )?insns: (\d+)").expect("Invalid counter regex");
+    let mut total = None;
+    let mut per_vcpu = GuestCounters::default();
+    let mut vcpus = 0;
+    for captures in report.lines().filter_map(|l| line.captures(l.trim())) {
+        let blocks = captures.get(2).and_then(|blocks| blocks.as_str().parse().ok());
+        let instructions = captures[3].parse().ok()?;
+        if captures.get(1).is_some() {
+            vcpus += 1;
+            per_vcpu.instructions += instructions;
+            per_vcpu.blocks = blocks.map(|blocks: u64| per_vcpu.blocks.unwrap_or(0) + blocks);
+        } else {
+            total = Some(GuestCounters { instructions, blocks });
+        }
+    }
+    total.or((vcpus > 0).then_some(per_vcpu))
+}
+
+pub fn collect(config: &RunnerConfig) -> Option<GuestCounters> {
+    config.counters.as_ref()?;
+    parse(&fs::read_to_string(plugins::log_path(config)?).ok()?)
+}
@@ -0,0 +1,391 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use crate::detach;
+use crate::metrics::Metrics;
+use crate::outcome::{RunOutcome, HISTORY_DIR};
+use crate::protocol;
+use crate::serial::LogFollower;
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+struct Request {
+    path: String,
+    query: Vec<(String, String)>,
+    websocket_key: Option<String>,
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let target = line.split_whitespace().nth(1).unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (decode(name), decode(value)))
+        .collect();
+    let path = path.to_string();
+    let mut websocket_key = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                websocket_key = Some(value.trim().to_string());
+            }
+        }
+    }
+    Ok(Request { path, query, websocket_key })
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+           status, content_type, body.len())?;
+    stream.write_all(body)
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn content_type(file: &str) -> &'static str {
+    match file.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("ppm") => "image/x-portable-pixmap",
+        Some("json") => "application/json",
+        Some("wav") => "audio/wav",
+        Some("html") => "text/html",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+fn is_screenshot(file: &str) -> bool {
+    file.ends_with(".png") || file.ends_with(".ppm")
+}
+
+struct RunInfo {
+    name: String,
+    dir: PathBuf,
+    pid: Option<u32>,
+    outcome: Option<String>,
+    success: Option<bool>,
+    tags: Vec<String>,
+    archived: bool,
+}
+
+impl RunInfo {
+    fn load(name: String, dir: PathBuf) -> Option<RunInfo> {
+        if !dir.join("serial.log").exists() && !dir.join("outcome.json").exists() {
+            return None;
+        }
+        let pid = fs::read_to_string(dir.join("runner.pid")).ok()
+            .and_then(|pid| pid.trim().parse::<u32>().ok())
+            .filter(|pid| detach::alive(*pid));
+        let outcome = fs::read_to_string(dir.join("outcome.json")).ok();
+        let parsed = outcome.as_deref().and_then(|outcome| serde_json::from_str::<RunOutcome>(outcome).ok());
+        let success = parsed.as_ref().map(|outcome| outcome.success);
+        let tags = match fs::read_to_string(dir.join("tags")) {
+            Ok(tags) => tags.lines().filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+            Err(_) => parsed.map(|outcome| outcome.tags).unwrap_or_default(),
+        };
+        Some(RunInfo { name, dir, pid, outcome, success, tags, archived: false })
+    }
+
+    fn status(&self) -> String {
+        match (&self.pid, self.success) {
+            (Some(pid), _) => format!("running (pid {})", pid),
+            (None, Some(true)) => "passed".to_string(),
+            (None, Some(false)) => "failed".to_string(),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+
+    fn files(&self) -> Vec<String> {
+        let mut files = fs::read_dir(&self.dir).into_iter().flatten().flatten()
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+}
+
+fn subdirs(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut dirs = fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs
+}
+
+// Every artifacts dir shows its latest run, the records each run left under runs/ make up the history.
+fn runs(root: &Path) -> Vec<RunInfo> {
+    let mut dirs = vec![(".".to_string(), root.to_path_buf())];
+    dirs.extend(subdirs(root).into_iter().filter(|(name, _)| name != HISTORY_DIR));
+    let mut runs = dirs.iter().filter_map(|(name, dir)| RunInfo::load(name.clone(), dir.clone())).collect::<Vec<_>>();
+    let mut history = dirs.iter().flat_map(|(name, dir)| {
+        subdirs(&dir.join(HISTORY_DIR)).into_iter().filter_map(move |(stamp, record)| {
+            let name = format!("{}@{}", name.trim_start_matches('.'), stamp);
+            RunInfo::load(name, record).map(|run| (stamp, RunInfo { pid: None, archived: true, ..run }))
+        })
+    }).collect::<Vec<_>>();
+    history.sort_by(|(a, _), (b, _)| b.cmp(a));
+    runs.extend(history.into_iter().map(|(_, run)| run));
+    runs
+}
+
+fn tag_links(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!("<a href=\"/?tag={}\">{}</a>", escape(&encode(tag)), escape(tag)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn index_page(root: &Path, filter: &[String]) -> String {
+    let (mut active, mut latest, mut history) = (String::new(), String::new(), String::new());
+    for run in runs(root).into_iter().filter(|run| filter.iter().all(|tag| run.tags.contains(tag))) {
+        let row = format!("<tr><td><a href=\"/runs/{0}\">{0}</a></td><td>{1}</td><td>{2}</td></tr>\n",
+                          escape(&run.name), escape(&run.status()), tag_links(&run.tags));
+        match (run.pid, run.archived) {
+            (Some(_), _) => active.push_str(&row),
+            (None, false) => latest.push_str(&row),
+            (None, true) => history.push_str(&row),
+        }
+    }
+    let filter = if filter.is_empty() {
+        String::new()
+    } else {
+        format!("<p>Tagged {} (<a href=\"/\">clear</a>)</p>", tag_links(filter))
+    };
+    format!("<!DOCTYPE html><html><head><title>uefapi-runner</title></head><body>{}\
+             <h1>Active runs</h1><table>{}</table><h1>Latest results</h1><table>{}</table>\
+             <h1>History</h1><table>{}</table></body></html>",
+            filter, active, latest, history)
+}
+
+fn metrics(root: &Path) -> String {
+    let mut metrics = Metrics::default();
+    for run in runs(root) {
+        // The latest result of an artifacts dir is also its newest history record, count that one only.
+        if run.pid.is_some() {
+            metrics.active += 1;
+        } else if let Some(outcome) = run.outcome.as_deref().filter(|_| run.archived)
+            .and_then(|outcome| serde_json::from_str::<RunOutcome>(outcome).ok()) {
+            metrics.record(&outcome);
+        }
+    }
+    metrics.render()
+}
+
+fn run_page(run: &RunInfo) -> String {
+    let files = run.files();
+    let links = files.iter()
+        .map(|file| format!("<li><a href=\"/runs/{0}/files/{1}\">{1}</a></li>", escape(&run.name), escape(file)))
+        .collect::<String>();
+    let screenshots = files.iter().filter(|file| is_screenshot(file))
+        .map(|file| format!("<img src=\"/runs/{}/files/{}\" width=\"320\">", escape(&run.name), escape(file)))
+        .collect::<String>();
+    let serial = fs::read_to_string(run.dir.join("serial.log")).unwrap_or_default();
+    let live = if run.pid.is_some() {
+        format!("<script>\
+                 const serial = document.getElementById('serial');\
+                 serial.textContent = '';\
+                 const ws = new WebSocket(`ws://${{location.host}}/runs/{}/serial`);\
+                 ws.onmessage = e => {{ serial.textContent += e.data + '\\n'; window.scrollTo(0, document.body.scrollHeight); }};\
+                 </script>", escape(&run.name))
+    } else {
+        String::new()
+    };
+    format!("<!DOCTYPE html><html><head><title>{0}</title></head><body>\
+             <p><a href=\"/\">All runs</a></p><h1>{0}</h1><p>Status: {1}</p><p>Tags: {7}</p>\
+             <h2>Outcome</h2><pre>{2}</pre><h2>Screenshots</h2>{3}<h2>Artifacts</h2><ul>{4}</ul>\
+             <h2>Serial</h2><pre id=\"serial\">{5}</pre>{6}</body></html>",
+            escape(&run.name), escape(&run.status()), escape(run.outcome.as_deref().unwrap_or("")),
+            screenshots, links, escape(&serial), live, tag_links(&run.tags))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, add) in h.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+    let mut digest = [0u8; 20];
+    for (i, value) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+fn send_frame(stream: &mut TcpStream, text: &str) -> io::Result<()> {
+    let mut frame = vec![0x81];
+    match text.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=65535 => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+    stream.write_all(&frame)
+}
+
+fn accept_key(key: &str) -> String {
+    protocol::encode_base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+fn stream_serial(stream: &mut TcpStream, run: &RunInfo, key: &str) -> io::Result<()> {
+    let accept = accept_key(key);
+    write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                    Connection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept)?;
+    let mut follower = LogFollower::new(run.dir.join("serial.log"));
+    loop {
+        let finished = run.pid.is_none_or(|pid| !detach::alive(pid));
+        for line in follower.poll() {
+            send_frame(stream, &line)?;
+        }
+        if finished {
+            return stream.write_all(&[0x88, 0]);
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn handle(mut stream: TcpStream, root: &Path) -> io::Result<()> {
+    let request = read_request(&stream)?;
+    let parts = request.path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let run = match parts.as_slice() {
+        ["runs", name, ..] => runs(root).into_iter().find(|run| run.name == *name),
+        _ => None,
+    };
+    match (parts.as_slice(), run) {
+        ([""], _) => {
+            let filter = request.query.iter()
+                .filter(|(name, _)| name == "tag")
+                .map(|(_, tag)| tag.clone())
+                .collect::<Vec<_>>();
+            respond(&mut stream, "200 OK", "text/html", index_page(root, &filter).as_bytes())
+        }
+        (["metrics"], _) => respond(&mut stream, "200 OK", "text/plain; version=0.0.4",
+                                    metrics(root).as_bytes()),
+        (["runs", _], Some(run)) =>
+            respond(&mut stream, "200 OK", "text/html", run_page(&run).as_bytes()),
+        (["runs", _, "serial"], Some(run)) => match &request.websocket_key {
+            Some(key) => stream_serial(&mut stream, &run, key),
+            None => respond(&mut stream, "400 Bad Request", "text/plain", b"websocket required"),
+        },
+        (["runs", _, "files", file], Some(run)) if run.files().iter().any(|f| f == file) =>
+            match fs::read(run.dir.join(file)) {
+                Ok(body) => respond(&mut stream, "200 OK", content_type(file), &body),
+                Err(e) => respond(&mut stream, "500 Internal Server Error", "text/plain",
+                                  e.to_string().as_bytes()),
+            },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+pub fn serve(root: &str, port: u16) -> bool {
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("Failed to bind dashboard on port {}: {}", port, e);
+            return false;
+        }
+    };
+    info!("Dashboard for {} at http://127.0.0.1:{}/", root, port);
+    let root = PathBuf::from(root);
+    for stream in listener.incoming().flatten() {
+        let root = root.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &root) {
+                warn!("Dashboard request failed: {}", e);
+            }
+        });
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_rfc6455() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn sha1_known_answers() {
+        let hex = |digest: [u8; 20]| digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::warn;
+use crate::handle::Control;
+use crate::outcome::ExitReason;
+use crate::RunnerConfig;
+
+pub fn enabled(config: &RunnerConfig) -> bool {
+    config.timeout_secs.is_some() || config.idle_timeout_secs.is_some()
+}
+
+pub fn watch(config: &RunnerConfig, serial_log: Option<&Path>, control: &Control, done: &AtomicBool)
+             -> Option<ExitReason> {
+    let (timeout, idle) = (config.timeout_secs, config.idle_timeout_secs.filter(|_| serial_log.is_some()));
+    let start = Instant::now();
+    let (mut last_size, mut last_output) = (0, start);
+    while !done.load(Ordering::Acquire) {
+        if let Some(secs) = timeout.filter(|secs| start.elapsed() > Duration::from_secs(*secs)) {
+            warn!("Run exceeded timeout_secs = {}, killing QEMU", secs);
+            control.request_kill();
+            return Some(ExitReason::Timeout { secs });
+        }
+        let size = serial_log.and_then(|log| fs::metadata(log).ok()).map(|m| m.len()).unwrap_or(0);
+        if size != last_size {
+            (last_size, last_output) = (size, Instant::now());
+        }
+        if let Some(secs) = idle.filter(|secs| last_output.elapsed() > Duration::from_secs(*secs)) {
+            warn!("No serial output for {}s, killing QEMU", secs);
+            control.request_kill();
+            return Some(ExitReason::Idle { secs });
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    None
+}
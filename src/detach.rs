@@ -0,0 +1,218 @@
+// The supervisor is driven over a Unix domain socket, which other hosts lack.
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use log::{error, info, warn};
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::metrics::Metrics;
+use crate::pipeline::{Context, Pipeline};
+use crate::qemu;
+use crate::serial::LogFollower;
+use crate::{load_config, RunnerConfig};
+
+const UNSUPPORTED: &str = "Detached runs are controlled over a Unix domain socket, which this host does not support";
+
+fn pidfile(dir: &Path) -> PathBuf {
+    dir.join("runner.pid")
+}
+
+fn socket(dir: &Path) -> PathBuf {
+    dir.join("control.sock")
+}
+
+fn serial_log(dir: &Path) -> PathBuf {
+    dir.join("serial.log")
+}
+
+fn read_pid(dir: &Path) -> Option<u32> {
+    fs::read_to_string(pidfile(dir)).ok()?.trim().parse().ok()
+}
+
+// Signal 0 only checks that the process exists, kill(1) spares binding libc for it and works beyond Linux.
+#[cfg(unix)]
+pub fn alive(pid: u32) -> bool {
+    Command::new("kill").args(["-0", &pid.to_string()]).stdout(Stdio::null()).stderr(Stdio::null())
+        .status().is_ok_and(|status| status.success())
+}
+
+#[cfg(not(unix))]
+pub fn alive(pid: u32) -> bool {
+    let filter = format!("PID eq {}", pid);
+    let pid = pid.to_string();
+    Command::new("tasklist").args(["/FI", &filter, "/NH"]).stderr(Stdio::null()).output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == pid))
+}
+
+pub fn spawn(config: &RunnerConfig) -> Result<u32, String> {
+    if cfg!(not(unix)) {
+        return Err(UNSUPPORTED.to_string());
+    }
+    let dir = PathBuf::from(&config.artifacts_dir);
+    if let Some(pid) = read_pid(&dir).filter(|pid| alive(*pid)) {
+        return Err(format!("A detached run (pid {}) is already active in {}", pid, dir.display()));
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let config_path = dir.join("config.toml");
+    let resolved = toml::to_string_pretty(config).map_err(|e| format!("Failed to serialize config: {}", e))?;
+    fs::write(&config_path, resolved).map_err(|e| format!("Failed to write {}: {}", config_path.display(), e))?;
+    let stderr = File::create(dir.join("runner.log"))
+        .map_err(|e| format!("Failed to create runner log: {}", e))?;
+    let exe = std::env::current_exe().map_err(|e| format!("Failed to locate runner: {}", e))?;
+    let child = qemu::new_process_group(&mut Command::new(exe))
+        .arg("supervise").arg(&config_path)
+        .args(config.tags.iter().flat_map(|tag| ["--tag", tag]))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr)
+        .spawn().map_err(|e| format!("Failed to start supervisor: {}", e))?;
+    info!("Detached run started (pid {}), artifacts in {}", child.id(), dir.display());
+    Ok(child.id())
+}
+
+#[cfg(unix)]
+fn serve(listener: UnixListener, control: Arc<Control>) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Control socket error: {}", e);
+                continue;
+            }
+        };
+        let mut line = String::new();
+        if BufReader::new(&stream).read_line(&mut line).is_err() {
+            continue;
+        }
+        let reply = match line.trim() {
+            "stop" => {
+                control.request_shutdown();
+                "ok".to_string()
+            }
+            "kill" => {
+                control.request_kill();
+                "ok".to_string()
+            }
+            "status" => format!("{:?}", control.state()),
+            other => format!("unknown command {}", other),
+        };
+        let _ = writeln!(stream, "{}", reply);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn supervise(_config_path: &str, _tags: &[String]) -> bool {
+    error!("{}", UNSUPPORTED);
+    false
+}
+
+#[cfg(unix)]
+pub fn supervise(config_path: &str, tags: &[String]) -> bool {
+    let mut config = load_config(config_path);
+    config.tags = tags.to_vec();
+    let dir = PathBuf::from(&config.artifacts_dir);
+    if let Err(e) = fs::write(dir.join("tags"), tags.join("\n")) {
+        warn!("Failed to write tags: {}", e);
+    }
+    config.stdio_serial = false;
+    config.log_serial = true;
+    config.log_path = serial_log(&dir).display().to_string();
+    let _ = fs::remove_file(&config.log_path);
+    let _ = fs::remove_file(socket(&dir));
+    if let Err(e) = fs::write(pidfile(&dir), std::process::id().to_string()) {
+        error!("Failed to write pidfile: {}", e);
+        return false;
+    }
+    let listener = match UnixListener::bind(socket(&dir)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to bind control socket: {}", e);
+            return false;
+        }
+    };
+    let control = Arc::new(Control::default());
+    let serve_control = control.clone();
+    thread::spawn(move || serve(listener, serve_control));
+    let mut ctx = Context::new(config, Arc::new(Hooks::default()), control);
+    let result = Pipeline::standard().run(&mut ctx);
+    let passed = match &result {
+        Ok(()) => ctx.outcome.as_ref().is_some_and(|outcome| {
+            info!("QEMU exited with {}", outcome.describe());
+            outcome.success
+        }),
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    };
+    if let Some(gateway) = &ctx.config.metrics_gateway {
+        let mut metrics = Metrics::default();
+        metrics.record_context(&ctx);
+        metrics.push(gateway, &ctx.config.artifacts_dir);
+    }
+    let _ = fs::remove_file(socket(&dir));
+    let _ = fs::remove_file(pidfile(&dir));
+    passed
+}
+
+#[cfg(not(unix))]
+fn request(_dir: &Path, _command: &str) -> Result<String, String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(unix)]
+fn request(dir: &Path, command: &str) -> Result<String, String> {
+    let mut stream = UnixStream::connect(socket(dir))
+        .map_err(|e| format!("No detached run in {}: {}", dir.display(), e))?;
+    writeln!(stream, "{}", command).map_err(|e| format!("Failed to send {}: {}", command, e))?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)
+        .map_err(|e| format!("Failed to read reply: {}", e))?;
+    Ok(reply.trim().to_string())
+}
+
+pub fn stop(dir: &str, kill: bool) -> bool {
+    let dir = Path::new(dir);
+    match request(dir, if kill { "kill" } else { "stop" }) {
+        Ok(reply) => {
+            info!("Stop requested: {}", reply);
+            true
+        }
+        Err(e) => {
+            error!("{}", e);
+            false
+        }
+    }
+}
+
+pub fn attach(dir: &str) -> bool {
+    let dir = Path::new(dir);
+    let Some(pid) = read_pid(dir).filter(|pid| alive(*pid)) else {
+        error!("No detached run in {}", dir.display());
+        return false;
+    };
+    match request(dir, "status") {
+        Ok(state) => info!("Attached to pid {}: {}", pid, state),
+        Err(e) => warn!("{}", e),
+    }
+    let mut follower = LogFollower::new(serial_log(dir));
+    loop {
+        let finished = !alive(pid);
+        for line in follower.poll() {
+            println!("{}", line);
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    info!("Detached run finished, outcome in {}", dir.join("outcome.json").display());
+    true
+}
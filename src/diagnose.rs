@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use log::{info, warn};
+use serde::Serialize;
+use crate::arch::Arch;
+use crate::firmware::Firmware;
+use crate::qemu;
+use crate::RunnerConfig;
+
+const EFI_APPLICATION: u16 = 10;
+const TRIAGE_EXCEPTIONS: usize = 8;
+const TRIAGE_ERRORS: usize = 16;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Verdict {
+    Ok,
+    Suspect,
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+fn check(verdict: Verdict, detail: String) -> Check {
+    Check { verdict, detail }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn pe_header(bytes: &[u8]) -> Option<usize> {
+    u32_at(bytes, 0x3c).map(|offset| offset as usize)
+        .filter(|offset| bytes.starts_with(b"MZ") && bytes.get(*offset..*offset + 4) == Some(b"PE\0\0"))
+}
+
+pub fn image_base(bytes: &[u8]) -> Option<u64> {
+    let optional = pe_header(bytes)? + 24;
+    match u16_at(bytes, optional)? {
+        0x20b => Some(u64::from(u32_at(bytes, optional + 24)?) | u64::from(u32_at(bytes, optional + 28)?) << 32),
+        0x10b => u32_at(bytes, optional + 28).map(u64::from),
+        _ => None,
+    }
+}
+
+fn check_efi_name(config: &RunnerConfig) -> Check {
+    match Arch::from_efi_name(&config.efi_name) {
+        Some(arch) => check(Verdict::Ok, format!("efi_name {} is the {:?} removable-media boot path", config.efi_name, arch)),
+        None => check(Verdict::Suspect, format!(
+            "efi_name {} is not a default boot name, the firmware only autoboots {}", config.efi_name,
+            Arch::all().map(|arch| arch.efi_name()).join(", "))),
+    }
+}
+
+fn check_qemu(config: &RunnerConfig, arch: Arch) -> Check {
+    let system = arch.qemu_binary();
+    let system = system.trim_end_matches(std::env::consts::EXE_SUFFIX);
+    if config.qemu_cmd.contains(system) {
+        check(Verdict::Ok, format!("qemu_cmd {} emulates {:?}", config.qemu_cmd, arch))
+    } else {
+        check(Verdict::Suspect, format!("qemu_cmd {} does not look like {}, which {} needs",
+                                        config.qemu_cmd, system, config.efi_name))
+    }
+}
+
+fn check_binary(image: &Path, arch: Arch) -> Check {
+    let Ok(bytes) = fs::read(image) else {
+        return check(Verdict::Suspect, format!("staged binary {} is missing", image.display()));
+    };
+    let Some(pe) = pe_header(&bytes) else {
+        return check(Verdict::Suspect, format!("{} is not a PE image", image.display()));
+    };
+    let machine = u16_at(&bytes, pe + 4).unwrap_or(0);
+    let subsystem = u16_at(&bytes, pe + 24 + 68).unwrap_or(0);
+    if machine != arch.pe_machine() {
+        let built_for = Arch::all().into_iter().find(|arch| arch.pe_machine() == machine);
+        return check(Verdict::Suspect, match built_for {
+            Some(built_for) => format!("binary is built for {:?} but efi_name targets {:?}", built_for, arch),
+            None => format!("binary has unknown PE machine type {:#06x}", machine),
+        });
+    }
+    if subsystem != EFI_APPLICATION {
+        return check(Verdict::Suspect, format!(
+            "binary has PE subsystem {}, not an EFI application ({})", subsystem, EFI_APPLICATION));
+    }
+    check(Verdict::Ok, format!("binary is a {:?} EFI application", arch))
+}
+
+fn check_firmware(firmware: Option<&Firmware>, arch: Arch) -> Check {
+    let Some(firmware) = firmware else {
+        return check(Verdict::Unknown, "firmware was not resolved".to_string());
+    };
+    let name = firmware.code.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    let hinted = |arch: &Arch| arch.firmware_hints().iter().any(|hint| name.contains(hint));
+    match Arch::all().into_iter().find(|other| *other != arch && hinted(other)) {
+        Some(other) if !hinted(&arch) => check(Verdict::Suspect, format!(
+            "firmware {} looks like a {:?} build, the guest is {:?}", firmware.code.display(), other, arch)),
+        _ => check(Verdict::Ok, format!("firmware {} does not look like another architecture's",
+                                        firmware.code.display())),
+    }
+}
+
+fn check_console(config: &RunnerConfig) -> Check {
+    let Some(log) = qemu::firmware_log(config) else {
+        return check(Verdict::Unknown, "set firmware_log = true to tell whether the firmware itself started".to_string());
+    };
+    match fs::metadata(&log).map(|m| m.len()).unwrap_or(0) {
+        0 => check(Verdict::Suspect, "the firmware debug log is empty too, the firmware never started".to_string()),
+        bytes => check(Verdict::Suspect, format!(
+            "the firmware wrote {} byte(s) of debug log, so it booted but the app never printed to the serial console",
+            bytes)),
+    }
+}
+
+pub fn no_serial_output(config: &RunnerConfig, image: &Path, firmware: Option<&Firmware>) -> Vec<Check> {
+    let arch = config.arch();
+    vec![
+        check_efi_name(config),
+        check_qemu(config, arch),
+        check_binary(image, arch),
+        check_firmware(firmware, arch),
+        check_console(config),
+    ]
+}
+
+pub fn report(checks: &[Check]) {
+    warn!("The guest produced no serial output. Checks performed:");
+    for check in checks {
+        let mark = match check.verdict {
+            Verdict::Ok => "ok",
+            Verdict::Suspect => "!!",
+            Verdict::Unknown => "??",
+        };
+        warn!("    [{}] {}", mark, check.detail);
+    }
+    warn!("Run `uefapi-runner selftest` to check QEMU and the firmware with a known-good binary");
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CrashTriage {
+    pub triple_fault: bool,
+    pub resets: u32,
+    pub last_exceptions: Vec<String>,
+    pub guest_errors: Vec<String>,
+}
+
+fn is_exception(line: &str) -> bool {
+    line.starts_with("check_exception") || line.starts_with("Taking exception") || line.contains(": v=")
+}
+
+fn is_guest_error(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+    ["invalid", "unimplemented", "bad "].iter().any(|marker| line.contains(marker))
+}
+
+pub fn triage(debug_log: &Path) -> Option<CrashTriage> {
+    let file = File::open(debug_log).ok()?;
+    let mut triage = CrashTriage::default();
+    let mut exceptions = VecDeque::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if line.contains("Triple fault") {
+            triage.triple_fault = true;
+        } else if line.starts_with("CPU Reset") {
+            triage.resets += 1;
+        } else if is_exception(&line) {
+            if exceptions.len() == TRIAGE_EXCEPTIONS {
+                exceptions.pop_front();
+            }
+            exceptions.push_back(line.trim().to_string());
+        } else if is_guest_error(&line) && triage.guest_errors.len() < TRIAGE_ERRORS {
+            triage.guest_errors.push(line.trim().to_string());
+        }
+    }
+    triage.last_exceptions = exceptions.into();
+    Some(triage)
+}
+
+pub fn write_triage(triage: &CrashTriage, artifacts_dir: &Path) -> Result<(), String> {
+    if triage.triple_fault {
+        warn!("The guest triple-faulted");
+    }
+    for exception in &triage.last_exceptions {
+        warn!("    {}", exception);
+    }
+    for error in &triage.guest_errors {
+        warn!("    guest error: {}", error);
+    }
+    let path = artifacts_dir.join("triage.json");
+    let json = serde_json::to_string_pretty(triage).expect("Failed to serialize crash triage");
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!("Crash triage written to {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::dashboard::escape;
+use crate::outcome::{TestResult, TestStatus};
+use crate::pipeline::Context;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFormat {
+    Markdown,
+    Html,
+}
+
+pub struct Entry {
+    pub result: TestResult,
+    pub artifacts: Vec<PathBuf>,
+}
+
+impl Entry {
+    pub fn from_run(index: usize, label: &str, ctx: &Context, error: Option<&str>) -> Entry {
+        let outcome = ctx.outcome.as_ref();
+        let status = match (error, outcome) {
+            (Some(_), _) => TestStatus::Failed,
+            (None, Some(outcome)) if outcome.exit_reason.as_ref().is_some_and(|reason| reason.is_timeout()) =>
+                TestStatus::Timeout,
+            (None, Some(outcome)) if !outcome.success => TestStatus::Failed,
+            (None, _) => TestStatus::Passed,
+        };
+        let mut artifacts = Vec::new();
+        if let Some(log) = ctx.serial_log.as_ref().filter(|log| log.exists()) {
+            let dir = Path::new(&ctx.config.artifacts_dir).join("digest");
+            let copy = dir.join(format!("{}-serial.log", index));
+            match fs::create_dir_all(&dir).and_then(|_| fs::copy(log, &copy)) {
+                Ok(_) => artifacts.push(copy),
+                Err(e) => warn!("Failed to keep the serial log of {}: {}", label, e),
+            }
+        }
+        Entry {
+            result: TestResult {
+                name: label.to_string(),
+                status,
+                duration_ms: outcome.and_then(|outcome| outcome.timings.run_ms),
+                message: error.map(str::to_string).or_else(|| outcome.map(|outcome| outcome.describe())),
+            },
+            artifacts,
+        }
+    }
+}
+
+fn mark(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Passed => "PASS",
+        TestStatus::Failed => "FAIL",
+        TestStatus::Skipped => "SKIP",
+        TestStatus::Timeout => "TIMEOUT",
+    }
+}
+
+fn duration(ms: Option<u64>) -> String {
+    ms.map(|ms| format!("{:.1}s", ms as f64 / 1000.0)).unwrap_or_else(|| "-".to_string())
+}
+
+fn link(path: &Path, dir: &Path) -> String {
+    path.strip_prefix(dir).unwrap_or(path).display().to_string()
+}
+
+fn headline(entries: &[Entry]) -> String {
+    let passed = entries.iter().filter(|entry| entry.result.status == TestStatus::Passed).count();
+    let total = entries.iter().filter_map(|entry| entry.result.duration_ms).sum::<u64>();
+    format!("{} of {} run(s) passed, {} failed, {} in total", passed, entries.len(), entries.len() - passed,
+            duration(Some(total)))
+}
+
+fn markdown(title: &str, entries: &[Entry], dir: &Path, generated: u64) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace('\n', " ");
+    let mut out = format!("# {}\n\n{}\n\nGenerated at {} (unix time), artifacts in `{}`\n\n\
+                           | Result | Run | Duration | Details | Artifacts |\n|---|---|---|---|---|\n",
+                          title, headline(entries), generated, dir.display());
+    for entry in entries {
+        let links = entry.artifacts.iter()
+            .map(|path| format!("[{}]({})", path.file_name().unwrap_or_default().to_string_lossy(), link(path, dir)))
+            .collect::<Vec<_>>().join(" ");
+        out.push_str(&format!("| {} | {} | {} | {} | {} |\n", mark(entry.result.status), cell(&entry.result.name),
+                              duration(entry.result.duration_ms),
+                              cell(entry.result.message.as_deref().unwrap_or("")), links));
+    }
+    out
+}
+
+fn html(title: &str, entries: &[Entry], dir: &Path, generated: u64) -> String {
+    let rows = entries.iter().map(|entry| {
+        let links = entry.artifacts.iter()
+            .map(|path| format!("<a href=\"{}\">{}</a>", escape(&link(path, dir)),
+                                escape(&path.file_name().unwrap_or_default().to_string_lossy())))
+            .collect::<Vec<_>>().join(" ");
+        format!("<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                mark(entry.result.status).to_ascii_lowercase(), mark(entry.result.status),
+                escape(&entry.result.name), duration(entry.result.duration_ms),
+                escape(entry.result.message.as_deref().unwrap_or("")), links)
+    }).collect::<Vec<_>>().join("\n");
+    format!("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title>\
+             <style>td,th{{padding:2px 8px;text-align:left}} .pass{{color:green}} .fail,.timeout{{color:red}}</style>\
+             </head><body><h1>{0}</h1><p>{1}</p><p>Generated at {2} (unix time), artifacts in {3}</p>\n\
+             <table><tr><th>Result</th><th>Run</th><th>Duration</th><th>Details</th><th>Artifacts</th></tr>\n\
+             {4}\n</table></body></html>\n",
+            escape(title), escape(&headline(entries)), generated, escape(&dir.display().to_string()), rows)
+}
+
+pub fn write(format: DigestFormat, title: &str, entries: &[Entry], artifacts_dir: &str) -> Result<PathBuf, String> {
+    let dir = Path::new(artifacts_dir);
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let generated = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (path, text) = match format {
+        DigestFormat::Markdown => (dir.join("summary.md"), markdown(title, entries, dir, generated)),
+        DigestFormat::Html => (dir.join("summary.html"), html(title, entries, dir, generated)),
+    };
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!("Run summary written to {}", path.display());
+    Ok(path)
+}
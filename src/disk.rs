@@ -0,0 +1,228 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::rng::SplitMix64;
+
+const SECTOR_SIZE: u64 = 512;
+const MAX_SAMPLED_RULES: u64 = 4096;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DiskConfig {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub source: DiskSource,
+    #[serde(default = "default_interface")]
+    pub interface: String,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub readonly: bool,
+    #[serde(default)]
+    pub inject: Vec<ErrorInjection>,
+    #[serde(default)]
+    pub throttle: Option<Throttle>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Throttle {
+    #[serde(default)]
+    pub iops: Option<u64>,
+    #[serde(default)]
+    pub iops_read: Option<u64>,
+    #[serde(default)]
+    pub iops_write: Option<u64>,
+    #[serde(default)]
+    pub bps: Option<u64>,
+    #[serde(default)]
+    pub bps_read: Option<u64>,
+    #[serde(default)]
+    pub bps_write: Option<u64>,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+}
+
+impl Throttle {
+    pub fn drive_options(&self) -> String {
+        let mut iops = self.iops;
+        if let Some(latency) = self.latency_ms.filter(|latency| *latency > 0) {
+            let limit = (1000 / latency).max(1);
+            info!("Emulating {}ms latency as a limit of {} requests per second", latency, limit);
+            iops = Some(iops.map_or(limit, |iops| iops.min(limit)));
+        }
+        let limits = [
+            ("iops-total", iops),
+            ("iops-read", self.iops_read),
+            ("iops-write", self.iops_write),
+            ("bps-total", self.bps),
+            ("bps-read", self.bps_read),
+            ("bps-write", self.bps_write),
+        ];
+        limits.iter()
+            .filter_map(|(key, value)| value.map(|value| format!(",throttling.{}={}", key, value)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ErrorInjection {
+    #[serde(default = "default_event")]
+    pub event: String,
+    #[serde(default = "default_errno")]
+    pub errno: u32,
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub probability_ppm: Option<u32>,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub once: bool,
+}
+
+fn default_event() -> String {
+    "read_aio".to_string()
+}
+
+fn default_errno() -> u32 {
+    5
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum DiskSource {
+    File {
+        path: String,
+    },
+    Nbd {
+        host: String,
+        #[serde(default)]
+        port: Option<u16>,
+        #[serde(default)]
+        export: Option<String>,
+    },
+    Iscsi {
+        portal: String,
+        target: String,
+        #[serde(default)]
+        lun: u32,
+        #[serde(default)]
+        user: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+fn default_interface() -> String {
+    "virtio-blk-pci".to_string()
+}
+
+pub fn escape(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+impl DiskSource {
+    pub fn file_spec(&self) -> String {
+        match self {
+            DiskSource::File { path } => escape(path),
+            DiskSource::Nbd { host, port, export } => format!(
+                "nbd://{}:{}/{}", host, port.unwrap_or(10809), export.as_deref().unwrap_or("")),
+            DiskSource::Iscsi { portal, target, lun, user, password } => {
+                let auth = match (user, password) {
+                    (Some(user), Some(password)) => format!("{}%{}@", user, password),
+                    (Some(user), None) => format!("{}@", user),
+                    _ => String::new(),
+                };
+                format!("iscsi://{}{}/{}/{}", auth, portal, target, lun)
+            }
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            DiskSource::File { path } => path.clone(),
+            DiskSource::Nbd { host, port, export } => format!(
+                "nbd {}:{} export {}", host, port.unwrap_or(10809), export.as_deref().unwrap_or("")),
+            DiskSource::Iscsi { portal, target, lun, .. } =>
+                format!("iscsi {} {} lun {}", portal, target, lun),
+        }
+    }
+}
+
+impl ErrorInjection {
+    fn rule(&self, out: &mut String, sector: Option<u64>) {
+        let _ = writeln!(out, "[inject-error]");
+        let _ = writeln!(out, "event = \"{}\"", self.event);
+        let _ = writeln!(out, "errno = \"{}\"", self.errno);
+        if let Some(sector) = sector {
+            let _ = writeln!(out, "sector = \"{}\"", sector);
+        }
+        if self.once {
+            let _ = writeln!(out, "once = \"on\"");
+        }
+        out.push('\n');
+    }
+
+    fn render(&self, out: &mut String, size: Option<u64>) {
+        match (self.offset, self.probability_ppm) {
+            (Some(offset), _) => self.rule(out, Some(offset / SECTOR_SIZE)),
+            (None, Some(ppm)) => {
+                let Some(size) = size else {
+                    warn!("Probabilistic error injection needs a local disk image, skipping");
+                    return;
+                };
+                let sectors = size / SECTOR_SIZE;
+                let count = (sectors * ppm as u64 / 1_000_000).min(MAX_SAMPLED_RULES);
+                if count == MAX_SAMPLED_RULES {
+                    warn!("Error injection capped at {} faulty sectors", MAX_SAMPLED_RULES);
+                }
+                let mut rng = SplitMix64::new(self.seed);
+                for _ in 0..count {
+                    self.rule(out, Some(rng.below(sectors)));
+                }
+            }
+            (None, None) => self.rule(out, None),
+        }
+    }
+}
+
+impl DiskConfig {
+    fn blkdebug_config(&self, id: &str, run_dir: &Path) -> Result<String, String> {
+        let size = match &self.source {
+            DiskSource::File { path } => fs::metadata(path).ok().map(|m| m.len()),
+            _ => None,
+        };
+        let mut rules = String::new();
+        for injection in &self.inject {
+            injection.render(&mut rules, size);
+        }
+        let path = run_dir.join(format!("{}.blkdebug", id));
+        fs::write(&path, rules).map_err(|e| format!("Failed to write blkdebug config {}: {}", path.display(), e))?;
+        info!("Injecting {} error rule(s) into disk {}", self.inject.len(), id);
+        Ok(format!("blkdebug:{}:{}", escape(&path.display().to_string()), self.source.file_spec()))
+    }
+
+    pub fn qemu_args(&self, index: usize, run_dir: &Path) -> Result<Vec<String>, String> {
+        let id = self.id.clone().unwrap_or_else(|| format!("disk{}", index));
+        info!("Attaching disk {} from {}", id, self.source.describe());
+        let file = if self.inject.is_empty() {
+            self.source.file_spec()
+        } else {
+            self.blkdebug_config(&id, run_dir)?
+        };
+        let mut drive = format!("if=none,id={},file={},format={}",
+                                id, file, self.format.as_deref().unwrap_or("raw"));
+        if self.readonly {
+            drive.push_str(",readonly=on");
+        }
+        if let Some(throttle) = &self.throttle {
+            drive.push_str(&throttle.drive_options());
+        }
+        Ok(vec![
+            "-drive".to_string(), drive,
+            "-device".to_string(), format!("{},drive={}", self.interface, id),
+        ])
+    }
+}
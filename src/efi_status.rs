@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::outcome::{TestResult, TestStatus};
+
+const ERROR_BIT: u64 = 1 << 63;
+
+const STATUSES: &[(u64, &str, &str)] = &[
+    (0, "SUCCESS", "Success"),
+    (ERROR_BIT | 1, "LOAD_ERROR", "Load Error"),
+    (ERROR_BIT | 2, "INVALID_PARAMETER", "Invalid Parameter"),
+    (ERROR_BIT | 3, "UNSUPPORTED", "Unsupported"),
+    (ERROR_BIT | 4, "BAD_BUFFER_SIZE", "Bad Buffer Size"),
+    (ERROR_BIT | 5, "BUFFER_TOO_SMALL", "Buffer Too Small"),
+    (ERROR_BIT | 6, "NOT_READY", "Not Ready"),
+    (ERROR_BIT | 7, "DEVICE_ERROR", "Device Error"),
+    (ERROR_BIT | 8, "WRITE_PROTECTED", "Write Protected"),
+    (ERROR_BIT | 9, "OUT_OF_RESOURCES", "Out of Resources"),
+    (ERROR_BIT | 10, "VOLUME_CORRUPTED", "Volume Corrupt"),
+    (ERROR_BIT | 11, "VOLUME_FULL", "Volume Full"),
+    (ERROR_BIT | 12, "NO_MEDIA", "No Media"),
+    (ERROR_BIT | 13, "MEDIA_CHANGED", "Media changed"),
+    (ERROR_BIT | 14, "NOT_FOUND", "Not Found"),
+    (ERROR_BIT | 15, "ACCESS_DENIED", "Access Denied"),
+    (ERROR_BIT | 16, "NO_RESPONSE", "No Response"),
+    (ERROR_BIT | 17, "NO_MAPPING", "No mapping"),
+    (ERROR_BIT | 18, "TIMEOUT", "Time out"),
+    (ERROR_BIT | 19, "NOT_STARTED", "Not started"),
+    (ERROR_BIT | 20, "ALREADY_STARTED", "Already started"),
+    (ERROR_BIT | 21, "ABORTED", "Aborted"),
+    (ERROR_BIT | 22, "ICMP_ERROR", "ICMP Error"),
+    (ERROR_BIT | 23, "TFTP_ERROR", "TFTP Error"),
+    (ERROR_BIT | 24, "PROTOCOL_ERROR", "Protocol Error"),
+    (ERROR_BIT | 25, "INCOMPATIBLE_VERSION", "Incompatible Version"),
+    (ERROR_BIT | 26, "SECURITY_VIOLATION", "Security Violation"),
+    (ERROR_BIT | 27, "CRC_ERROR", "CRC Error"),
+    (ERROR_BIT | 28, "END_OF_MEDIA", "End of Media"),
+    (ERROR_BIT | 31, "END_OF_FILE", "End of File"),
+    (ERROR_BIT | 32, "INVALID_LANGUAGE", "Invalid Language"),
+    (ERROR_BIT | 33, "COMPROMISED_DATA", "Compromised Data"),
+];
+
+const MARKERS: &[&str] = &["Image Return Status = ", "StartImage failed: "];
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct EfiStatus {
+    pub code: u64,
+    pub name: String,
+}
+
+impl EfiStatus {
+    pub fn lookup(status: &str) -> Option<EfiStatus> {
+        let status = status.trim();
+        let code = status.strip_prefix("0x").and_then(|hex| u64::from_str_radix(hex, 16).ok());
+        let name = status.trim_start_matches("EFI_").replace(' ', "_").to_ascii_uppercase();
+        STATUSES.iter()
+            .find(|(value, canonical, text)| Some(*value) == code || *canonical == name
+                || text.eq_ignore_ascii_case(status))
+            .map(|(code, canonical, _)| EfiStatus { code: *code, name: format!("EFI_{}", canonical) })
+    }
+
+    pub fn success(&self) -> bool {
+        self.code & ERROR_BIT == 0
+    }
+}
+
+pub fn parse_line(line: &str) -> Option<EfiStatus> {
+    MARKERS.iter().find_map(|marker| {
+        let status = &line[line.find(marker)? + marker.len()..];
+        EfiStatus::lookup(status)
+    })
+}
+
+pub fn scan(logs: &[&Path]) -> Option<EfiStatus> {
+    logs.iter()
+        .filter_map(|log| fs::read_to_string(log).ok())
+        .find_map(|log| log.lines().find_map(parse_line))
+}
+
+pub fn check(expected: &str, actual: Option<&EfiStatus>) -> TestResult {
+    let wanted = EfiStatus::lookup(expected);
+    let passed = wanted.is_some() && wanted.as_ref() == actual;
+    TestResult {
+        name: "efi-status".to_string(),
+        status: if passed { TestStatus::Passed } else { TestStatus::Failed },
+        duration_ms: None,
+        message: (!passed).then(|| match actual {
+            Some(actual) => format!("expected {} but the app returned {}", expected, actual.name),
+            None => format!("expected {} but no EFI status was reported", expected),
+        }),
+    }
+}
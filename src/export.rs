@@ -0,0 +1,142 @@
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use log::{info, warn};
+use crate::firmware::FirmwareProvider;
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::linux::KernelDelivery;
+use crate::pipeline::{Context, Launching, Pipeline, Step};
+use crate::staging::VOLUME_TAG;
+use crate::{firmware_provider, qemu, RunnerConfig};
+
+fn quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !text.is_empty() && text.chars().all(plain) {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn word(arg: &str, dirs: &[(String, &str)]) -> String {
+    let mut out = String::new();
+    let mut rest = arg;
+    while !rest.is_empty() {
+        let next = dirs.iter()
+            .filter_map(|(dir, var)| rest.find(dir.as_str()).map(|at| (at, dir.len(), *var)))
+            .min_by_key(|(at, len, _)| (*at, usize::MAX - len));
+        let Some((at, len, var)) = next else {
+            out.push_str(&quote(rest));
+            break;
+        };
+        if at > 0 {
+            out.push_str(&quote(&rest[..at]));
+        }
+        out.push_str(&format!("\"${}\"", var));
+        rest = &rest[at + len..];
+    }
+    if out.is_empty() { "''".to_string() } else { out }
+}
+
+fn line(args: &[String], dirs: &[(String, &str)]) -> String {
+    args.iter().map(|arg| word(arg, dirs)).collect::<Vec<_>>().join(" ")
+}
+
+fn unsupported(config: &RunnerConfig) -> Vec<&'static str> {
+    let secure_boot = config.secure_boot.as_ref();
+    [
+        (config.esp_image.is_some(), "esp_image"),
+        (config.nvram_fuzz.is_some(), "nvram_fuzz"),
+        (secure_boot.is_some_and(|secure_boot| secure_boot.sign.is_some()), "secure_boot.sign"),
+        (secure_boot.is_some_and(|secure_boot| secure_boot.enrolls()), "secure_boot key enrollment"),
+        (config.test.is_some(), "test"),
+        (config.qmp.is_some(), "qmp"),
+        (config.provision.is_some(), "provision"),
+        (!config.extensions.is_empty(), "extensions"),
+        (!config.log_transforms.is_empty(), "log_transforms"),
+    ].into_iter().filter(|(set, _)| *set).map(|(_, name)| name).collect()
+}
+
+pub fn script(config: &RunnerConfig) -> Result<String, String> {
+    let mut ctx = Context::new(config.clone(), Arc::new(Hooks::default()), Arc::new(Control::default()));
+    ctx.dry_run = true;
+    Pipeline::preparation().run(&mut ctx)?;
+    Launching.dry_run(&mut ctx)?;
+    let config = &ctx.config;
+    let workspace = ctx.workspace()?;
+    let dirs = [
+        (workspace.work_dir().display().to_string(), "ESP"),
+        (workspace.run_dir().display().to_string(), "RUN"),
+    ];
+    let mut out = String::from("#!/bin/sh\n# Generated by uefapi-runner export-script\nset -e\n\n\
+                                WORK=$(mktemp -d)\ntrap 'rm -rf \"$WORK\"' EXIT\n\
+                                ESP=\"$WORK/esp\"\nRUN=\"$WORK/run\"\n");
+    out.push_str(&format!("mkdir -p \"$ESP/EFI/BOOT\" \"$RUN\" {}\n", quote(&config.artifacts_dir)));
+    let skipped = unsupported(config);
+    if !skipped.is_empty() {
+        warn!("The exported script does not reproduce {}", skipped.join(", "));
+        out.push_str(&format!("# Not reproduced: {}\n", skipped.join(", ")));
+    }
+    if config.auto_build {
+        out.push_str(&format!("\n(cd {} && cargo {})\n", quote(&config.project_path), line(
+            &config.build_cmd.split_whitespace().map(str::to_string).collect::<Vec<_>>(), &dirs)));
+    }
+    if config.boot_image.is_none() {
+        out.push('\n');
+        let mut copies = config.binaries.iter().map(|binary| ("cp", binary.path.as_str(), binary.dest()))
+            .collect::<Vec<_>>();
+        let boot_path = config.boot_path();
+        if !copies.iter().any(|(_, source, dest)| *source == config.binary_path && *dest == boot_path) {
+            copies.push(("cp", &config.binary_path, &boot_path));
+        }
+        if let Some(linux) = config.linux.as_ref().filter(|linux| linux.delivery == KernelDelivery::Esp) {
+            let files = [(Some(&linux.kernel), &linux.kernel_name), (linux.initrd.as_ref(), &linux.initrd_name)];
+            copies.extend(files.into_iter().filter_map(|(source, name)| Some(("cp", source?.as_str(), name.as_str()))));
+        }
+        copies.extend(config.extra_files.iter().map(|file| ("cp -R", file.source.as_str(), file.dest.as_str())));
+        for (cp, source, dest) in copies {
+            let dest = quote(dest.trim_start_matches('/'));
+            out.push_str(&format!("mkdir -p \"$(dirname \"$ESP\"/{0})\"\n{1} {2} \"$ESP\"/{0}\n",
+                                  dest, cp, quote(source)));
+        }
+        let mapping = config.efi_shell_mapping.as_deref();
+        if let Some(startup) = &config.startup_nsh {
+            if let Some(script) = startup.render(&config.boot_path(), mapping) {
+                out.push_str(&format!("printf '%s' {} > \"$ESP/startup.nsh\"\n", quote(&script)));
+            }
+            if startup.tags_volume(mapping) {
+                out.push_str(&format!(": > \"$ESP/{}\"\n", VOLUME_TAG));
+            }
+        }
+    }
+    let template = match config.secure_boot.as_ref().and_then(|secure_boot| secure_boot.enrolled_vars.clone()) {
+        Some(enrolled) => Some(enrolled.into()),
+        None => firmware_provider(config, true).resolve().ok().and_then(|firmware| firmware.vars),
+    };
+    if let (Some(template), Some(vars)) = (template, &ctx.firmware()?.vars) {
+        let vars = word(&vars.display().to_string(), &dirs);
+        out.push_str(&format!("\n[ -e {0} ] || {{ cp {1} {0} && chmod u+w {0}; }}\n",
+                              vars, quote(&template.display().to_string())));
+    }
+    if let Some(tpm) = &config.tpm {
+        let swtpm = qemu::cmdline(&tpm.command(workspace.run_dir(), &config.artifacts_dir));
+        let state = word(&tpm.state(workspace.run_dir()).display().to_string(), &dirs);
+        out.push_str(&format!("\nmkdir -p {}\n{} &\nSWTPM=$!\ntrap 'kill $SWTPM; rm -rf \"$WORK\"' EXIT\n\
+                               while [ ! -S \"$RUN/swtpm.sock\" ]; do sleep 0.1; done\n", state, line(&swtpm, &dirs)));
+    }
+    let launch = ctx.launch.as_ref().ok_or("Launch step has not run")?;
+    out.push_str(&format!("\n{}\n", line(&launch.cmdline(), &dirs)));
+    Ok(out)
+}
+
+pub fn write(config: &RunnerConfig, output: &Path) -> Result<(), String> {
+    let script = script(config)?;
+    fs::write(output, script).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    #[cfg(unix)]
+    fs::set_permissions(output, fs::Permissions::from_mode(0o755))
+        .map_err(|e| format!("Failed to make {} executable: {}", output.display(), e))?;
+    info!("Run script written to {}", output.display());
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::outcome::RunOutcome;
+use crate::pipeline::Context;
+use crate::RunnerConfig;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+const PREFIX: &str = "uefapi-runner-";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Staged,
+    Launch,
+    Exit,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Staged => "staged",
+            Event::Launch => "launch",
+            Event::Exit => "exit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ExtensionConfig {
+    pub name: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub events: Vec<Event>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    protocol: u32,
+    event: Event,
+    config: &'a RunnerConfig,
+    esp_dir: Option<&'a Path>,
+    run_dir: Option<&'a Path>,
+    artifacts_dir: &'a str,
+    serial_log: Option<&'a Path>,
+    outcome: Option<&'a RunOutcome>,
+}
+
+#[derive(Default, Deserialize)]
+struct Response {
+    #[serde(default)]
+    qemu_args: Vec<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl ExtensionConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("invalid extension name {:?}, use letters, digits, - and _", self.name));
+        }
+        Ok(())
+    }
+
+    fn program(&self) -> String {
+        self.path.clone().unwrap_or_else(|| format!("{}{}", PREFIX, self.name))
+    }
+
+    fn handles(&self, event: Event) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    fn call(&self, event: Event, request: &str) -> Result<Response, String> {
+        let program = self.program();
+        let mut child = Command::new(&program).arg(event.name())
+            .env("UEFAPI_EXTENSION_PROTOCOL", PROTOCOL_VERSION.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn().map_err(|e| format!("Failed to run extension {}: {}", program, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(request.as_bytes());
+        }
+        let output = child.wait_with_output().map_err(|e| format!("Failed to wait for extension {}: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!("Extension {} failed on {} with {}", self.name, event.name(), output.status));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(Response::default());
+        }
+        serde_json::from_str(&stdout).map_err(|e| format!("Extension {} sent an invalid response: {}", self.name, e))
+    }
+}
+
+pub fn notify(ctx: &Context, event: Event, outcome: Option<&RunOutcome>) -> Result<Vec<String>, String> {
+    let extensions = ctx.config.extensions.iter().filter(|extension| extension.handles(event)).collect::<Vec<_>>();
+    if extensions.is_empty() {
+        return Ok(Vec::new());
+    }
+    if ctx.dry_run {
+        for extension in &extensions {
+            info!("[dry-run] would run extension {} on {}", extension.program(), event.name());
+        }
+        return Ok(Vec::new());
+    }
+    let workspace = ctx.workspace.as_ref();
+    let request = Request {
+        protocol: PROTOCOL_VERSION,
+        event,
+        config: &ctx.config,
+        esp_dir: workspace.filter(|_| ctx.config.boot_image.is_none()).map(|workspace| workspace.work_dir()),
+        run_dir: workspace.map(|workspace| workspace.run_dir()),
+        artifacts_dir: &ctx.config.artifacts_dir,
+        serial_log: ctx.serial_log.as_deref(),
+        outcome,
+    };
+    let request = serde_json::to_string(&request).expect("Failed to serialize extension request");
+    let mut qemu_args = Vec::new();
+    for extension in extensions {
+        match extension.call(event, &request) {
+            Ok(response) => {
+                if let Some(message) = &response.message {
+                    info!("Extension {}: {}", extension.name, message);
+                }
+                qemu_args.extend(response.qemu_args);
+            }
+            Err(e) if extension.required => return Err(e),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    Ok(qemu_args)
+}
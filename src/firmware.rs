@@ -0,0 +1,552 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::arch::Arch;
+use crate::privilege::which;
+use crate::provenance::sha256;
+
+const SEARCH_DIRS: &[&str] = &[
+    "/usr/share/OVMF",
+    "/usr/share/edk2/ovmf",
+    "/usr/share/edk2/x64",
+    "/usr/share/edk2-ovmf/x64",
+    "/usr/share/edk2-ovmf",
+    "/usr/share/AAVMF",
+    "/usr/share/edk2/aarch64",
+    "/usr/share/qemu-efi-aarch64",
+    "/usr/share/edk2/riscv",
+    "/usr/share/edk2/riscv64",
+    "/usr/share/qemu-efi-riscv64",
+    "/usr/share/qemu",
+    "/opt/homebrew/share/qemu",
+    "/usr/local/share/qemu",
+    "/opt/local/share/qemu",
+    "/run/current-system/sw/share/qemu",
+];
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirmwareMode {
+    #[default]
+    Pflash,
+    Bios,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Firmware {
+    pub code: PathBuf,
+    pub vars: Option<PathBuf>,
+}
+
+impl Firmware {
+    pub fn exists(&self) -> bool {
+        self.code.exists() && self.vars.as_ref().is_none_or(|vars| vars.exists())
+    }
+}
+
+pub trait FirmwareProvider {
+    fn name(&self) -> String;
+
+    fn cache_key(&self) -> Option<String> {
+        None
+    }
+
+    fn resolve(&self) -> Result<Firmware, String>;
+}
+
+pub struct ExplicitPaths {
+    pub dir: PathBuf,
+    pub mode: FirmwareMode,
+    pub arch: Arch,
+    pub secure_boot: bool,
+}
+
+impl FirmwareProvider for ExplicitPaths {
+    fn name(&self) -> String {
+        format!("path {}", self.dir.display())
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let dir = self.dir.canonicalize()
+            .map_err(|e| format!("Failed to canonicalize {}: {}", self.dir.display(), e))?;
+        let candidates = match self.mode {
+            FirmwareMode::Pflash => {
+                let files = if self.secure_boot { self.arch.secure_pflash_files() } else { self.arch.pflash_files() };
+                files.iter()
+                    .map(|(code, vars)| Firmware { code: dir.join(code), vars: Some(dir.join(vars)) })
+                    .collect::<Vec<_>>()
+            }
+            FirmwareMode::Bios if dir.is_file() => vec![Firmware { code: dir.clone(), vars: None }],
+            FirmwareMode::Bios => self.arch.bios_files().iter()
+                .map(|code| Firmware { code: dir.join(code), vars: None })
+                .collect(),
+        };
+        candidates.iter().find(|firmware| firmware.exists()).cloned()
+            .ok_or_else(|| format!("{:?} firmware files missing, looked for {:?}", self.arch, candidates))
+    }
+}
+
+pub struct SystemSearch {
+    pub qemu_cmd: String,
+    pub mode: FirmwareMode,
+    pub arch: Arch,
+    pub secure_boot: bool,
+}
+
+impl SystemSearch {
+    fn dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = SEARCH_DIRS.iter().map(PathBuf::from).collect::<Vec<_>>();
+        if let Some(bin) = which(&self.qemu_cmd).and_then(|qemu| qemu.canonicalize().ok())
+            .and_then(|qemu| qemu.parent().map(Path::to_path_buf)) {
+            dirs.extend([bin.join("../share/qemu"), bin.join("share")]);
+        }
+        dirs.retain(|dir| dir.is_dir());
+        dirs
+    }
+}
+
+impl FirmwareProvider for SystemSearch {
+    fn name(&self) -> String {
+        "system search".to_string()
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let dirs = self.dirs();
+        dirs.iter()
+            .find_map(|dir| {
+                ExplicitPaths { dir: dir.clone(), mode: self.mode, arch: self.arch, secure_boot: self.secure_boot }
+                    .resolve().ok()
+            })
+            .ok_or_else(|| format!("no {:?} firmware in {:?}", self.arch, dirs))
+    }
+}
+
+// Lowest priority first, a file in a later directory replaces one with the same name in an earlier one.
+const DESCRIPTOR_DIRS: &[&str] = &["/usr/share/qemu/firmware", "/etc/qemu/firmware"];
+const CONFIDENTIAL_FEATURES: &[&str] = &["amd-sev", "amd-sev-es", "amd-sev-snp", "intel-tdx"];
+
+pub struct Descriptors {
+    pub mode: FirmwareMode,
+    pub arch: Arch,
+    pub secure_boot: bool,
+}
+
+fn qemu_arch(arch: Arch) -> &'static str {
+    match arch {
+        Arch::X86_64 => "x86_64",
+        Arch::Aarch64 => "aarch64",
+        Arch::Riscv64 => "riscv64",
+    }
+}
+
+fn descriptor_files() -> Vec<PathBuf> {
+    let mut dirs = DESCRIPTOR_DIRS.iter().map(PathBuf::from).collect::<Vec<_>>();
+    let config = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    dirs.extend(config.map(|config| config.join("qemu/firmware")));
+    let mut files = BTreeMap::new();
+    for dir in dirs {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.insert(entry.file_name(), path);
+            }
+        }
+    }
+    files.into_values().collect()
+}
+
+impl Descriptors {
+    fn select(&self, doc: &Value) -> Option<Firmware> {
+        let strings = |value: &Value| value.as_array().into_iter().flatten().filter_map(Value::as_str)
+            .map(str::to_string).collect::<Vec<_>>();
+        if !strings(&doc["interface-types"]).iter().any(|kind| kind == "uefi") {
+            return None;
+        }
+        let arch = qemu_arch(self.arch);
+        if !doc["targets"].as_array()?.iter().any(|target| target["architecture"] == arch) {
+            return None;
+        }
+        let features = strings(&doc["features"]);
+        if features.iter().any(|feature| CONFIDENTIAL_FEATURES.contains(&feature.as_str()))
+            || features.iter().any(|feature| feature == "secure-boot") != self.secure_boot {
+            return None;
+        }
+        let mapping = &doc["mapping"];
+        let raw = |file: &Value| file["format"].as_str().is_none_or(|format| format == "raw");
+        let filename = |file: &Value| file["filename"].as_str().map(PathBuf::from);
+        match (self.mode, mapping["device"].as_str()?) {
+            (FirmwareMode::Pflash, "flash") => {
+                let (code, vars) = (&mapping["executable"], &mapping["nvram-template"]);
+                if mapping["mode"].as_str().is_some_and(|mode| mode != "split") || !raw(code) || !raw(vars) {
+                    return None;
+                }
+                Some(Firmware { code: filename(code)?, vars: Some(filename(vars)?) })
+            }
+            (FirmwareMode::Bios, "memory") => Some(Firmware { code: filename(mapping)?, vars: None }),
+            _ => None,
+        }
+    }
+}
+
+impl FirmwareProvider for Descriptors {
+    fn name(&self) -> String {
+        "QEMU firmware descriptors".to_string()
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let files = descriptor_files();
+        files.iter()
+            .find_map(|path| {
+                let doc = fs::read_to_string(path).ok().and_then(|text| serde_json::from_str(&text).ok())?;
+                let firmware = self.select(&doc).filter(Firmware::exists)?;
+                debug!("Firmware descriptor {} matches", path.display());
+                Some(firmware)
+            })
+            .ok_or_else(|| format!("no {:?} {:?} descriptor among {} file(s)", self.arch, self.mode, files.len()))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Edk2Build {
+    pub source: String,
+    #[serde(default)]
+    pub platform: Option<String>,
+    #[serde(default = "default_toolchain")]
+    pub toolchain: String,
+    #[serde(default = "default_build_target")]
+    pub target: String,
+    #[serde(default)]
+    pub defines: Vec<String>,
+}
+
+fn default_toolchain() -> String {
+    "GCC5".to_string()
+}
+
+fn default_build_target() -> String {
+    "RELEASE".to_string()
+}
+
+struct Platform {
+    dsc: &'static str,
+    arch: &'static str,
+    code: &'static str,
+    vars: &'static str,
+    bios: Option<&'static str>,
+    pflash_size: Option<u64>,
+}
+
+fn platform(arch: Arch) -> Platform {
+    match arch {
+        Arch::X86_64 => Platform { dsc: "OvmfPkg/OvmfPkgX64.dsc", arch: "X64", code: "OVMF_CODE.fd",
+                                   vars: "OVMF_VARS.fd", bios: Some("OVMF.fd"), pflash_size: None },
+        Arch::Aarch64 => Platform { dsc: "ArmVirtPkg/ArmVirtQemu.dsc", arch: "AARCH64", code: "QEMU_EFI.fd",
+                                    vars: "QEMU_VARS.fd", bios: Some("QEMU_EFI.fd"), pflash_size: Some(64 << 20) },
+        Arch::Riscv64 => Platform { dsc: "OvmfPkg/RiscVVirt/RiscVVirtQemu.dsc", arch: "RISCV64",
+                                    code: "RISCV_VIRT_CODE.fd", vars: "RISCV_VIRT_VARS.fd", bios: None,
+                                    pflash_size: Some(32 << 20) },
+    }
+}
+
+impl Edk2Build {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.source.is_empty() {
+            return Err("firmware_build needs the source of an edk2 checkout".to_string());
+        }
+        if let Some(define) = self.defines.iter().find(|define| !define.contains('=')) {
+            return Err(format!("firmware_build define {} is not NAME=VALUE", define));
+        }
+        Ok(())
+    }
+}
+
+pub struct Builder {
+    pub spec: Edk2Build,
+    pub mode: FirmwareMode,
+    pub arch: Arch,
+    pub secure_boot: bool,
+    pub offline: bool,
+}
+
+fn git(source: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new("git").arg("-C").arg(source).args(args).output().ok()?;
+    out.status.success().then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+// QEMU maps pflash images at a fixed size on Arm and RISC-V, the build emits them unpadded.
+fn pad(from: &Path, to: &Path, size: u64) -> std::io::Result<()> {
+    fs::copy(from, to)?;
+    let file = OpenOptions::new().write(true).open(to)?;
+    if file.metadata()?.len() < size {
+        file.set_len(size)?;
+    }
+    Ok(())
+}
+
+impl Builder {
+    fn dsc(&self) -> String {
+        self.spec.platform.clone().unwrap_or_else(|| platform(self.arch).dsc.to_string())
+    }
+
+    fn defines(&self) -> Vec<String> {
+        let mut defines = self.spec.defines.clone();
+        if self.secure_boot && self.arch == Arch::X86_64 {
+            defines.extend(["SECURE_BOOT_ENABLE=TRUE", "SMM_REQUIRE=TRUE"].map(String::from));
+        }
+        defines
+    }
+
+    fn output_dir(&self, dsc: &str) -> Result<PathBuf, String> {
+        let path = Path::new(&self.spec.source).join(dsc);
+        let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let dir = text.lines()
+            .find_map(|line| line.trim().strip_prefix("OUTPUT_DIRECTORY")?.trim().strip_prefix('='))
+            .ok_or_else(|| format!("{} has no OUTPUT_DIRECTORY", path.display()))?
+            .trim().replace("$(ARCH)", platform(self.arch).arch);
+        Ok(Path::new(&self.spec.source).join(dir)
+            .join(format!("{}_{}", self.spec.target, self.spec.toolchain)).join("FV"))
+    }
+}
+
+impl FirmwareProvider for Builder {
+    fn name(&self) -> String {
+        format!("edk2 build {}", self.spec.source)
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        let commit = git(&self.spec.source, &["rev-parse", "HEAD"])?;
+        // A dirty tree has no stable identity, rebuild it every time.
+        if !git(&self.spec.source, &["status", "--porcelain"])?.is_empty() {
+            return None;
+        }
+        let recipe = format!("{}|{}|{}|{}|{:?}|{:?}|{}", commit, self.dsc(), self.spec.toolchain,
+                             self.spec.target, self.arch, self.mode, self.defines().join(","));
+        Some(format!("edk2-{}", &format!("{:x}", Sha256::digest(recipe.as_bytes()))[..16]))
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        if self.offline {
+            return Err(format!("{} is not cached, not building in dry-run mode", self.spec.source));
+        }
+        let (dsc, platform) = (self.dsc(), platform(self.arch));
+        let mut args = vec!["-a".to_string(), platform.arch.to_string(), "-p".to_string(), dsc.clone(),
+                            "-t".to_string(), self.spec.toolchain.clone(), "-b".to_string(), self.spec.target.clone()];
+        args.extend(self.defines().into_iter().flat_map(|define| ["-D".to_string(), define]));
+        info!("Building {} firmware from {}", dsc, self.spec.source);
+        let status = Command::new("bash")
+            .args(["-c", "source ./edksetup.sh >/dev/null && exec build \"$@\"", "build"]).args(&args)
+            .current_dir(&self.spec.source)
+            .status().map_err(|e| format!("Failed to run the edk2 build: {}", e))?;
+        if !status.success() {
+            return Err(format!("edk2 build of {} failed with {}", dsc, status));
+        }
+        let fv = self.output_dir(&dsc)?;
+        let firmware = match self.mode {
+            FirmwareMode::Pflash => Firmware { code: fv.join(platform.code), vars: Some(fv.join(platform.vars)) },
+            FirmwareMode::Bios => match platform.bios {
+                Some(bios) => Firmware { code: fv.join(bios), vars: None },
+                None => return Err(format!("{:?} has no -bios firmware image, use firmware_mode pflash", self.arch)),
+            },
+        };
+        if !firmware.exists() {
+            return Err(format!("edk2 build did not produce {:?}", firmware));
+        }
+        match (self.mode, platform.pflash_size, &firmware.vars) {
+            (FirmwareMode::Pflash, Some(size), Some(vars)) => {
+                let padded = fv.join("pflash");
+                fs::create_dir_all(&padded).map_err(|e| format!("Failed to create {}: {}", padded.display(), e))?;
+                let target = Firmware { code: padded.join(platform.code), vars: Some(padded.join(platform.vars)) };
+                pad(&firmware.code, &target.code, size)
+                    .and_then(|_| pad(vars, &padded.join(platform.vars), size))
+                    .map_err(|e| format!("Failed to pad pflash images: {}", e))?;
+                Ok(target)
+            }
+            _ => Ok(firmware),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FirmwareDownload {
+    pub code_url: String,
+    pub code_sha256: String,
+    #[serde(default)]
+    pub vars_url: Option<String>,
+    #[serde(default)]
+    pub vars_sha256: Option<String>,
+}
+
+fn valid_sha256(digest: &str) -> bool {
+    digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl FirmwareDownload {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.vars_url.is_some() != self.vars_sha256.is_some() {
+            return Err("firmware_download needs both vars_url and vars_sha256".to_string());
+        }
+        match [Some(&self.code_sha256), self.vars_sha256.as_ref()].into_iter().flatten().find(|d| !valid_sha256(d)) {
+            Some(digest) => Err(format!("firmware_download digest {} is not a sha256", digest)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Download {
+    pub spec: FirmwareDownload,
+    pub offline: bool,
+}
+
+fn fetch(url: &str, digest: &str, offline: bool) -> Result<PathBuf, String> {
+    let digest = digest.to_ascii_lowercase();
+    let name = url.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("firmware.fd");
+    let path = cache_root().join("downloads").join(&digest).join(name);
+    if path.exists() {
+        return Ok(path);
+    }
+    if offline {
+        return Err(format!("{} is not cached, not downloading in dry-run mode", url));
+    }
+    let dir = path.parent().expect("Download path has no parent");
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let partial = path.with_extension("partial");
+    info!("Downloading firmware from {}", url);
+    let status = Command::new("curl").args(["-fsSL", "--retry", "3", "-o"]).arg(&partial).arg(url).status()
+        .map_err(|e| format!("Failed to run curl: {}", e))?;
+    if !status.success() {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("Downloading {} failed with {}", url, status));
+    }
+    let actual = sha256(&partial).map_err(|e| format!("Failed to hash {}: {}", partial.display(), e))?;
+    if actual != digest {
+        let _ = fs::remove_file(&partial);
+        return Err(format!("{} has sha256 {}, expected {}", url, actual, digest));
+    }
+    fs::rename(&partial, &path).map_err(|e| format!("Failed to store {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+impl FirmwareProvider for Download {
+    fn name(&self) -> String {
+        format!("download {}", self.spec.code_url)
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let code = fetch(&self.spec.code_url, &self.spec.code_sha256, self.offline)?;
+        let vars = match (&self.spec.vars_url, &self.spec.vars_sha256) {
+            (Some(url), Some(digest)) => Some(fetch(url, digest, self.offline)?),
+            _ => None,
+        };
+        Ok(Firmware { code, vars })
+    }
+}
+
+pub fn cache_root() -> PathBuf {
+    if let Some(dir) = env::var_os("UEFAPI_RUNNER_CACHE") {
+        return PathBuf::from(dir);
+    }
+    let base = env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .unwrap_or_else(env::temp_dir);
+    base.join("uefapi-runner")
+}
+
+pub struct Cached<P> {
+    pub inner: P,
+    pub root: PathBuf,
+}
+
+impl<P: FirmwareProvider> Cached<P> {
+    pub fn new(inner: P) -> Self {
+        Cached { inner, root: cache_root().join("firmware") }
+    }
+
+    fn entry(&self, key: &str) -> Firmware {
+        let dir = self.root.join(key);
+        Firmware { code: dir.join("CODE.fd"), vars: Some(dir.join("VARS.fd")) }
+    }
+}
+
+fn store(from: &Firmware, to: &Firmware) -> std::io::Result<Firmware> {
+    if let Some(dir) = to.code.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(&from.code, &to.code)?;
+    let vars = match (&from.vars, &to.vars) {
+        (Some(from), Some(to)) => {
+            fs::copy(from, to)?;
+            Some(to.clone())
+        }
+        _ => None,
+    };
+    Ok(Firmware { code: to.code.clone(), vars })
+}
+
+impl<P: FirmwareProvider> FirmwareProvider for Cached<P> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn cache_key(&self) -> Option<String> {
+        self.inner.cache_key()
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let Some(key) = self.inner.cache_key() else {
+            return self.inner.resolve();
+        };
+        let cached = self.entry(&key);
+        if cached.code.exists() {
+            info!("Using cached firmware {} from {}", key, self.root.display());
+            return Ok(Firmware { vars: cached.vars.filter(|vars| vars.exists()), ..cached });
+        }
+        let firmware = self.inner.resolve()?;
+        match store(&firmware, &cached) {
+            Ok(cached) => Ok(cached),
+            Err(e) => {
+                warn!("Failed to cache firmware {}: {}", key, e);
+                Ok(firmware)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Chain {
+    pub providers: Vec<Box<dyn FirmwareProvider>>,
+}
+
+impl Chain {
+    pub fn with(mut self, provider: impl FirmwareProvider + 'static) -> Self {
+        self.providers.push(Box::new(Cached::new(provider)));
+        self
+    }
+}
+
+impl FirmwareProvider for Chain {
+    fn name(&self) -> String {
+        self.providers.iter().map(|p| p.name()).collect::<Vec<_>>().join(", ")
+    }
+
+    fn resolve(&self) -> Result<Firmware, String> {
+        let mut errors = Vec::new();
+        for provider in &self.providers {
+            match provider.resolve() {
+                Ok(firmware) => {
+                    info!("Firmware resolved by {}", provider.name());
+                    return Ok(firmware);
+                }
+                Err(e) => errors.push(format!("{}: {}", provider.name(), e)),
+            }
+        }
+        Err(errors.join("; "))
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::modules::{self, ModuleKind};
+use crate::serial::LogFollower;
+use crate::{diagnose, RunnerConfig};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct GdbConfig {
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_true")]
+    pub wait: bool,
+    #[serde(default = "default_true")]
+    pub script: bool,
+    #[serde(default)]
+    pub symbols: Option<String>,
+}
+
+fn default_port() -> u16 {
+    1234
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for GdbConfig {
+    fn default() -> Self {
+        GdbConfig { port: default_port(), wait: true, script: true, symbols: None }
+    }
+}
+
+impl GdbConfig {
+    pub fn qemu_args(&self) -> Vec<String> {
+        let mut args = vec!["-gdb".to_string(), format!("tcp::{}", self.port)];
+        if self.wait {
+            args.push("-S".to_string());
+        }
+        args
+    }
+
+    pub fn symbol_file(&self, config: &RunnerConfig, work_dir: &Path) -> PathBuf {
+        match &self.symbols {
+            Some(symbols) => PathBuf::from(symbols),
+            None if Path::new(&config.binary_path).exists() => PathBuf::from(&config.binary_path),
+            None => work_dir.join("EFI/BOOT").join(&config.efi_name),
+        }
+    }
+
+    fn write_startup(&self, dir: &Path, symbols: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let dir = fs::canonicalize(dir).unwrap_or(dir.to_path_buf());
+        let gdbinit = format!("set pagination off\ntarget remote localhost:{}\n\
+                               define uefi-symbols\n  source {}\nend\n",
+                              self.port, dir.join("gdb-symbols").display());
+        let lldb = format!("target create {}\ngdb-remote localhost:{}\n\
+                            command alias uefi-symbols command source {}\n",
+                           symbols.display(), self.port, dir.join("lldb-symbols").display());
+        for (name, script) in [("gdbinit", gdbinit), ("uefapi.lldb", lldb)] {
+            fs::write(dir.join(name), script).map_err(|e| format!("Failed to write {} script: {}", name, e))?;
+        }
+        info!("Debugger scripts written, run gdb -x {} or lldb -s {}",
+              dir.join("gdbinit").display(), dir.join("uefapi.lldb").display());
+        Ok(())
+    }
+
+    fn write_symbols(&self, dir: &Path, symbols: &Path, base: u64) -> Result<(), String> {
+        let image_base = fs::read(symbols).ok().and_then(|bytes| diagnose::image_base(&bytes)).unwrap_or(0);
+        let offset = base.wrapping_sub(image_base);
+        let gdb = format!("add-symbol-file {} -o {:#x}\n", symbols.display(), offset);
+        let lldb = format!("target modules load --file {} --slide {:#x}\n",
+                           symbols.file_name().unwrap_or_default().to_string_lossy(), offset);
+        for (name, script) in [("gdb-symbols", gdb), ("lldb-symbols", lldb)] {
+            fs::write(dir.join(name), script).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn watch(&self, logs: &[PathBuf], efi_name: &str, symbols: &Path, dir: &Path, done: &AtomicBool) {
+        if !self.script {
+            return;
+        }
+        if let Err(e) = self.write_startup(dir, symbols) {
+            warn!("{}", e);
+            return;
+        }
+        let binary_name = symbols.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let matches = |name: &String| name.eq_ignore_ascii_case(efi_name) || name.eq_ignore_ascii_case(&binary_name);
+        let mut followers = logs.iter().map(LogFollower::new).collect::<Vec<_>>();
+        while !done.load(Ordering::Acquire) {
+            let lines = followers.iter_mut().flat_map(|follower| follower.poll()).collect::<Vec<_>>();
+            let loaded = modules::parse(&lines.join("\n")).into_iter()
+                .find(|module| module.kind == ModuleKind::Driver && module.name.as_ref().is_some_and(matches));
+            if let Some(module) = loaded {
+                match self.write_symbols(dir, symbols, module.base) {
+                    Ok(()) => info!("{} loaded at {:#x}, run uefi-symbols in the debugger to load its symbols",
+                                    efi_name, module.base),
+                    Err(e) => warn!("{}", e),
+                }
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
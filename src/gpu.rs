@@ -0,0 +1,85 @@
+use std::path::Path;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::qmp::{ScheduledCommand, Trigger};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Screenshot {
+    #[serde(default)]
+    pub after_secs: Option<u64>,
+    #[serde(default)]
+    pub on_serial: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct GpuConfig {
+    #[serde(default = "default_heads")]
+    pub heads: u32,
+    #[serde(default)]
+    pub resolution: Option<String>,
+    #[serde(default)]
+    pub png: bool,
+    #[serde(default)]
+    pub screenshots: Vec<Screenshot>,
+}
+
+fn default_heads() -> u32 {
+    1
+}
+
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let (width, height) = resolution.split_once('x')?;
+    Some((width.parse().ok().filter(|w| *w > 0)?, height.parse().ok().filter(|h| *h > 0)?))
+}
+
+impl GpuConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.heads == 0 {
+            return Err("gpu heads must be at least 1".to_string());
+        }
+        if let Some(resolution) = self.resolution.as_ref().filter(|r| parse_resolution(r).is_none()) {
+            return Err(format!("invalid gpu resolution {}, expected WIDTHxHEIGHT", resolution));
+        }
+        if self.screenshots.iter().any(|shot| shot.after_secs.is_some() == shot.on_serial.is_some()) {
+            return Err("screenshot needs exactly one of after_secs or on_serial".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        let mut args = vec!["-vga".to_string(), "none".to_string()];
+        for head in 0..self.heads {
+            let mut device = format!("virtio-gpu-pci,id=gpu{}", head);
+            if let Some((width, height)) = self.resolution.as_deref().and_then(parse_resolution) {
+                device.push_str(&format!(",xres={},yres={}", width, height));
+            }
+            args.extend(["-device".to_string(), device]);
+        }
+        args
+    }
+
+    pub fn qmp_commands(&self, dir: &Path) -> Vec<ScheduledCommand> {
+        let extension = if self.png { "png" } else { "ppm" };
+        let mut commands = Vec::new();
+        for (index, shot) in self.screenshots.iter().enumerate() {
+            let trigger = match (&shot.after_secs, &shot.on_serial) {
+                (Some(secs), _) => Trigger::After(Duration::from_secs(*secs)),
+                (None, Some(marker)) => Trigger::Serial(marker.clone()),
+                (None, None) => Trigger::After(Duration::ZERO),
+            };
+            let name = shot.name.clone().unwrap_or_else(|| (index + 1).to_string());
+            for head in 0..self.heads {
+                let file = dir.join(format!("screen-{}-head{}.{}", name, head, extension));
+                let mut arguments = json!({ "filename": file.display().to_string(), "device": format!("gpu{}", head) });
+                if self.png {
+                    arguments["format"] = json!("png");
+                }
+                commands.push(ScheduledCommand { trigger: trigger.clone(), command: "screendump".to_string(), arguments });
+            }
+        }
+        commands
+    }
+}
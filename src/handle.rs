@@ -0,0 +1,140 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use log::warn;
+use crate::hooks::Hooks;
+use crate::outcome::RunOutcome;
+use crate::pipeline::{Context, Pipeline};
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RunState {
+    Preparing,
+    Running { pid: u32 },
+    ShuttingDown { pid: u32 },
+    Exited(Box<RunOutcome>),
+    Failed(String),
+}
+
+pub struct Control {
+    shutdown: AtomicBool,
+    kill: AtomicBool,
+    state: Mutex<RunState>,
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Control {
+            shutdown: AtomicBool::new(false),
+            kill: AtomicBool::new(false),
+            state: Mutex::new(RunState::Preparing),
+        }
+    }
+}
+
+impl Control {
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    pub fn request_kill(&self) {
+        self.kill.store(true, Ordering::Release);
+    }
+
+    pub fn reset(&self) {
+        self.shutdown.store(false, Ordering::Release);
+        self.kill.store(false, Ordering::Release);
+    }
+
+    pub fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    pub fn kill_requested(&self) -> bool {
+        self.kill.load(Ordering::Acquire)
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn set_state(&self, state: RunState) {
+        *self.state.lock().unwrap() = state;
+    }
+}
+
+static CONTROLS: Mutex<Vec<Weak<Control>>> = Mutex::new(Vec::new());
+static SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn register(control: &Arc<Control>) {
+    let mut controls = CONTROLS.lock().unwrap();
+    controls.retain(|control| control.strong_count() > 0);
+    controls.push(Arc::downgrade(control));
+}
+
+pub fn interrupted() -> bool {
+    SIGNALS.load(Ordering::Acquire) > 0
+}
+
+pub fn install_signal_handler() {
+    let result = ctrlc::set_handler(|| {
+        let count = SIGNALS.fetch_add(1, Ordering::AcqRel) + 1;
+        let controls = CONTROLS.lock().unwrap().iter().filter_map(Weak::upgrade).collect::<Vec<_>>();
+        if controls.is_empty() || count > 2 {
+            process::exit(130);
+        } else if count == 1 {
+            warn!("Interrupted, asking the guest to power down (interrupt again to kill QEMU)");
+            controls.iter().for_each(|control| control.request_shutdown());
+        } else {
+            warn!("Interrupted again, killing QEMU");
+            controls.iter().for_each(|control| control.request_kill());
+        }
+    });
+    if let Err(e) = result {
+        warn!("Failed to install the interrupt handler: {}", e);
+    }
+}
+
+pub struct RunHandle {
+    control: Arc<Control>,
+    thread: JoinHandle<Result<RunOutcome, String>>,
+}
+
+impl RunHandle {
+    pub fn spawn(config: RunnerConfig, hooks: Hooks) -> RunHandle {
+        let control = Arc::new(Control::default());
+        let thread_control = control.clone();
+        let thread = thread::spawn(move || {
+            let mut ctx = Context::new(config, Arc::new(hooks), thread_control.clone());
+            let result = Pipeline::standard().run(&mut ctx)
+                .and_then(|_| ctx.outcome.take().ok_or_else(|| "Run produced no outcome".to_string()));
+            match &result {
+                Ok(outcome) => thread_control.set_state(RunState::Exited(Box::new(outcome.clone()))),
+                Err(e) => thread_control.set_state(RunState::Failed(e.clone())),
+            }
+            result
+        });
+        RunHandle { control, thread }
+    }
+
+    pub fn status(&self) -> RunState {
+        self.control.state()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub fn shutdown(&self) {
+        self.control.request_shutdown();
+    }
+
+    pub fn kill(&self) {
+        self.control.request_kill();
+    }
+
+    pub fn wait(self) -> Result<RunOutcome, String> {
+        self.thread.join().map_err(|_| "Run thread panicked".to_string())?
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::arch::Arch;
+use crate::handle::Control;
+use crate::outcome::{ExitReason, RunOutcome, TestResult, TestStatus};
+use crate::serial::LogFollower;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TestConfig {
+    #[serde(default)]
+    pub success_marker: Option<String>,
+    #[serde(default)]
+    pub failure_marker: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default = "default_debug_exit")]
+    pub debug_exit: bool,
+    #[serde(default = "default_iobase")]
+    pub debug_exit_iobase: u16,
+    #[serde(default = "default_success_code")]
+    pub success_code: u32,
+    #[serde(default = "default_isolate")]
+    pub isolate: bool,
+}
+
+fn default_debug_exit() -> bool {
+    true
+}
+
+fn default_iobase() -> u16 {
+    0xf4
+}
+
+fn default_success_code() -> u32 {
+    0x10
+}
+
+fn default_isolate() -> bool {
+    true
+}
+
+impl Default for TestConfig {
+    fn default() -> Self {
+        TestConfig {
+            success_marker: None,
+            failure_marker: None,
+            timeout_secs: None,
+            debug_exit: default_debug_exit(),
+            debug_exit_iobase: default_iobase(),
+            success_code: default_success_code(),
+            isolate: default_isolate(),
+        }
+    }
+}
+
+impl TestConfig {
+    pub fn qemu_args(&self, arch: Arch) -> Vec<String> {
+        if !self.debug_exit {
+            return Vec::new();
+        }
+        if arch != Arch::X86_64 {
+            warn!("isa-debug-exit only exists on x86 machines, relying on serial markers");
+            return Vec::new();
+        }
+        vec!["-device".to_string(), format!("isa-debug-exit,iobase={:#x},iosize=0x04", self.debug_exit_iobase)]
+    }
+
+    pub fn watch(&self, serial_log: Option<&Path>, control: &Control, done: &AtomicBool) -> bool {
+        let timeout = self.timeout_secs.map(Duration::from_secs);
+        let markers = [&self.success_marker, &self.failure_marker].into_iter().flatten().collect::<Vec<_>>();
+        let mut follower = serial_log.filter(|_| !markers.is_empty()).map(LogFollower::new);
+        let start = Instant::now();
+        while !done.load(Ordering::Acquire) {
+            if let Some(follower) = &mut follower {
+                let lines = follower.poll();
+                if let Some(line) = lines.iter().find(|line| markers.iter().any(|m| line.contains(m.as_str()))) {
+                    info!("Test marker seen: {}", line);
+                    control.request_kill();
+                    return false;
+                }
+            }
+            if timeout.is_some_and(|timeout| start.elapsed() > timeout) {
+                warn!("Test timed out after {:?}", timeout.unwrap_or_default());
+                control.request_kill();
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    pub fn debug_exit_status(&self, code: i32) -> Option<(TestStatus, Option<String>)> {
+        if !self.debug_exit || code % 2 != 1 {
+            return None;
+        }
+        Some(match code as u32 == self.success_code << 1 | 1 {
+            true => (TestStatus::Passed, None),
+            false => (TestStatus::Failed, Some(format!("guest exited via isa-debug-exit with {:#x}", code >> 1))),
+        })
+    }
+
+    pub fn evaluate(&self, outcome: &RunOutcome, serial_log: Option<&Path>, timed_out: bool) -> TestResult {
+        let serial = serial_log.and_then(|log| fs::read_to_string(log).ok()).unwrap_or_default();
+        let seen = |marker: &Option<String>| marker.as_ref().and_then(|marker| serial.find(marker.as_str()));
+        let failed = |message: String| (TestStatus::Failed, Some(message));
+        let (status, message) = match (seen(&self.success_marker), seen(&self.failure_marker)) {
+            _ if outcome.exit_reason == Some(ExitReason::GuestPanic) => failed("guest panicked".to_string()),
+            _ if timed_out => (TestStatus::Timeout, Some(match &outcome.exit_reason {
+                Some(reason) if reason.is_timeout() => reason.describe(),
+                _ => format!("no verdict within {}s", self.timeout_secs.unwrap_or(0)),
+            })),
+            (Some(success), Some(failure)) if failure < success => failed("failure marker seen".to_string()),
+            (None, Some(_)) => failed("failure marker seen".to_string()),
+            (Some(_), _) => (TestStatus::Passed, None),
+            _ => match outcome.exit_code.and_then(|code| self.debug_exit_status(code)) {
+                Some(verdict) => verdict,
+                None if self.success_marker.is_some() => failed("success marker never seen".to_string()),
+                None if outcome.success => (TestStatus::Passed, None),
+                None => failed(outcome.describe()),
+            },
+        };
+        TestResult { name: "harness".to_string(), status, duration_ms: outcome.timings.run_ms, message }
+    }
+}
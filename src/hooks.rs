@@ -0,0 +1,57 @@
+use std::time::Duration;
+use crate::outcome::RunOutcome;
+
+type BuildHook = Box<dyn Fn(Duration) + Send + Sync>;
+type SpawnHook = Box<dyn Fn(u32, &[String]) + Send + Sync>;
+type SerialHook = Box<dyn Fn(&str) + Send + Sync>;
+type ExitHook = Box<dyn Fn(&RunOutcome) + Send + Sync>;
+
+#[derive(Default)]
+pub struct Hooks {
+    build_complete: Vec<BuildHook>,
+    qemu_spawn: Vec<SpawnHook>,
+    serial_line: Vec<SerialHook>,
+    exit: Vec<ExitHook>,
+}
+
+impl Hooks {
+    pub fn on_build_complete(mut self, hook: impl Fn(Duration) + Send + Sync + 'static) -> Self {
+        self.build_complete.push(Box::new(hook));
+        self
+    }
+
+    pub fn on_qemu_spawn(mut self, hook: impl Fn(u32, &[String]) + Send + Sync + 'static) -> Self {
+        self.qemu_spawn.push(Box::new(hook));
+        self
+    }
+
+    pub fn on_serial_line(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.serial_line.push(Box::new(hook));
+        self
+    }
+
+    pub fn on_exit(mut self, hook: impl Fn(&RunOutcome) + Send + Sync + 'static) -> Self {
+        self.exit.push(Box::new(hook));
+        self
+    }
+
+    pub fn wants_serial(&self) -> bool {
+        !self.serial_line.is_empty()
+    }
+
+    pub fn build_complete(&self, duration: Duration) {
+        self.build_complete.iter().for_each(|hook| hook(duration));
+    }
+
+    pub fn qemu_spawn(&self, pid: u32, cmdline: &[String]) {
+        self.qemu_spawn.iter().for_each(|hook| hook(pid, cmdline));
+    }
+
+    pub fn serial_line(&self, line: &str) {
+        self.serial_line.iter().for_each(|hook| hook(line));
+    }
+
+    pub fn exit(&self, outcome: &RunOutcome) {
+        self.exit.iter().for_each(|hook| hook(outcome));
+    }
+}
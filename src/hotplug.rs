@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::disk::escape;
+use crate::qmp::{ScheduledCommand, Trigger};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HotplugAction {
+    Add,
+    Del,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct HotplugStep {
+    pub action: HotplugAction,
+    pub id: String,
+    #[serde(default)]
+    pub driver: Option<String>,
+    #[serde(default)]
+    pub after_secs: Option<u64>,
+    #[serde(default)]
+    pub on_serial: Option<String>,
+    #[serde(default)]
+    pub file: Option<String>,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+impl HotplugStep {
+    fn drive_id(&self) -> String {
+        format!("{}-drive", self.id)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.after_secs.is_some() == self.on_serial.is_some() {
+            return Err(format!("hotplug step {} needs exactly one of after_secs or on_serial", self.id));
+        }
+        if self.action == HotplugAction::Add && self.driver.is_none() {
+            return Err(format!("hotplug add of {} needs a driver", self.id));
+        }
+        Ok(())
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        match &self.file {
+            Some(file) if self.action == HotplugAction::Add => vec![
+                "-drive".to_string(),
+                format!("if=none,id={},file={},format=raw", self.drive_id(), escape(file)),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn qmp_command(&self) -> ScheduledCommand {
+        let trigger = match (&self.after_secs, &self.on_serial) {
+            (Some(secs), _) => Trigger::After(Duration::from_secs(*secs)),
+            (None, Some(marker)) => Trigger::Serial(marker.clone()),
+            (None, None) => Trigger::After(Duration::ZERO),
+        };
+        match self.action {
+            HotplugAction::Add => {
+                let mut arguments = json!({
+                    "driver": self.driver.as_deref().unwrap_or_default(),
+                    "id": self.id,
+                });
+                if self.file.is_some() {
+                    arguments["drive"] = Value::String(self.drive_id());
+                }
+                for (key, value) in &self.options {
+                    arguments[key] = Value::String(value.clone());
+                }
+                ScheduledCommand { trigger, command: "device_add".to_string(), arguments }
+            }
+            HotplugAction::Del => ScheduledCommand {
+                trigger,
+                command: "device_del".to_string(),
+                arguments: json!({ "id": self.id }),
+            },
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use fatfs::{Dir, FileSystem, FormatVolumeOptions, FsOptions};
+use gpt::disk::LogicalBlockSize;
+use gpt::mbr::ProtectiveMBR;
+use gpt::GptConfig;
+use log::info;
+use serde::{Deserialize, Serialize};
+
+const MIB: u64 = 1024 * 1024;
+const SECTOR: u64 = 512;
+const MIN_ESP_MIB: u64 = 64;
+const ALIGNMENT_LBA: u64 = MIB / SECTOR;
+const GPT_RESERVED: u64 = 2 * MIB;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct EspImage {
+    #[serde(default)]
+    pub size_mb: Option<u64>,
+    #[serde(default)]
+    pub export: Option<String>,
+}
+
+struct Slice {
+    file: File,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl Read for Slice {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let max = buf.len().min(self.len.saturating_sub(self.pos) as usize);
+        let read = self.file.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for Slice {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let max = buf.len().min(self.len.saturating_sub(self.pos) as usize);
+        if max == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "write past the end of the partition"));
+        }
+        let written = self.file.write(&buf[..max])?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for Slice {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        }.filter(|pos| *pos <= self.len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek outside the partition"))?;
+        self.file.seek(SeekFrom::Start(self.start + pos))?;
+        self.pos = pos;
+        Ok(pos)
+    }
+}
+
+fn tree_bytes(dir: &Path) -> io::Result<u64> {
+    let mut bytes = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        bytes += if metadata.is_dir() { tree_bytes(&entry.path())? } else { metadata.len() };
+    }
+    Ok(bytes)
+}
+
+fn copy_tree<T: Read + Write + Seek>(from: &Path, to: &Dir<T>) -> io::Result<u64> {
+    let mut bytes = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+            bytes += copy_tree(&entry.path(), &to.create_dir(&name)?)?;
+        } else {
+            let mut file = to.create_file(&name)?;
+            file.truncate()?;
+            bytes += io::copy(&mut File::open(entry.path())?, &mut file)?;
+        }
+    }
+    Ok(bytes)
+}
+
+impl EspImage {
+    fn esp_bytes(&self, tree: &Path) -> io::Result<u64> {
+        let size_mb = match self.size_mb {
+            Some(size_mb) => size_mb,
+            None => (tree_bytes(tree)? * 5 / 4).div_ceil(MIB).max(MIN_ESP_MIB),
+        };
+        Ok(size_mb * MIB)
+    }
+
+    pub fn build(&self, tree: &Path, image: &Path) -> Result<u64, String> {
+        let fail = |e: io::Error| format!("Failed to build ESP image {}: {}", image.display(), e);
+        let esp_bytes = self.esp_bytes(tree).map_err(fail)?;
+        let total = esp_bytes + GPT_RESERVED;
+        let mut file = File::options().read(true).write(true).create(true).truncate(true)
+            .open(image).map_err(fail)?;
+        file.set_len(total).map_err(fail)?;
+        let last_lba = u32::try_from(total / SECTOR - 1).unwrap_or(u32::MAX);
+        ProtectiveMBR::with_lb_size(last_lba).overwrite_lba0(&mut file).map_err(fail)?;
+        let mut disk = GptConfig::new().writable(true).initialized(false)
+            .logical_block_size(LogicalBlockSize::Lb512)
+            .create_from_device(Box::new(file.try_clone().map_err(fail)?), None).map_err(fail)?;
+        disk.update_partitions(BTreeMap::new()).map_err(fail)?;
+        let id = disk.add_partition("EFI System Partition", esp_bytes, gpt::partition_types::EFI,
+                                    0, Some(ALIGNMENT_LBA)).map_err(fail)?;
+        let partition = disk.partitions()[&id].clone();
+        disk.write().map_err(fail)?;
+        let start = partition.bytes_start(LogicalBlockSize::Lb512).map_err(fail)?;
+        let len = partition.bytes_len(LogicalBlockSize::Lb512).map_err(fail)?;
+        file.seek(SeekFrom::Start(start)).map_err(fail)?;
+        let mut slice = Slice { file, start, len, pos: 0 };
+        fatfs::format_volume(&mut slice, FormatVolumeOptions::new().volume_label(*b"EFI        "))
+            .map_err(fail)?;
+        slice.seek(SeekFrom::Start(0)).map_err(fail)?;
+        let volume = FileSystem::new(slice, FsOptions::new()).map_err(fail)?;
+        let bytes = copy_tree(tree, &volume.root_dir()).map_err(fail)?;
+        volume.unmount().map_err(fail)?;
+        info!("Built {} MiB GPT image {} with {} byte(s) on the ESP", total / MIB, image.display(), bytes);
+        if let Some(export) = &self.export {
+            if let Some(parent) = Path::new(export).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            fs::copy(image, export).map_err(|e| format!("Failed to export ESP image to {}: {}", export, e))?;
+            info!("Exported ESP image to {}", export);
+        }
+        Ok(bytes)
+    }
+}
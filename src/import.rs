@@ -0,0 +1,322 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use crate::arch::Arch;
+use crate::firmware::FirmwareMode;
+use crate::staging::ExtraFile;
+use crate::{version, RunnerConfig};
+
+const MAKEFILES: &[&str] = &["justfile", "Justfile", ".justfile", "Makefile", "makefile", "GNUmakefile"];
+const MANAGED: &[&str] = &["-serial", "-monitor", "-qmp", "-chardev", "-pidfile", "-daemonize", "-nographic"];
+
+#[derive(Default)]
+struct Findings {
+    sources: Vec<String>,
+    notes: Vec<String>,
+    arch: Option<Arch>,
+    package: Option<String>,
+    qemu_cmd: Option<String>,
+    ovmf_path: Option<String>,
+    firmware_mode: Option<FirmwareMode>,
+    memory: Option<String>,
+    cpu: Option<String>,
+    smp: Option<u32>,
+    accel: Option<String>,
+    extra_qemu_args: Vec<String>,
+    extra_files: Vec<ExtraFile>,
+}
+
+fn split(line: &str) -> Vec<String> {
+    let (mut words, mut word, mut quote, mut escaped, mut started) = (Vec::new(), String::new(), None, false, false);
+    for c in line.chars() {
+        match (quote, c) {
+            _ if escaped => {
+                word.push(c);
+                escaped = false;
+            }
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('"'), '\\') | (None, '\\') => escaped = true,
+            (Some(_), _) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                started = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if started || !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                started = false;
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    if started || !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn templated(value: &str) -> bool {
+    value.contains('$') || value.contains("{{")
+}
+
+fn option(value: &str, key: &str) -> Option<String> {
+    value.split(',').find_map(|part| part.strip_prefix(key).and_then(|part| part.strip_prefix('='))).map(str::to_string)
+}
+
+impl Findings {
+    fn qemu(&mut self, program: &str, args: &[String]) {
+        if templated(program) {
+            self.notes.push(format!("QEMU binary {} uses a variable, set qemu_cmd by hand", program));
+        } else {
+            self.arch = self.arch.or(Arch::all().into_iter().find(|arch| program.ends_with(&arch.qemu_binary())));
+            self.qemu_cmd = Some(program.to_string());
+        }
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            let value = args.get(i + 1).filter(|value| !value.starts_with('-')).cloned();
+            i += 1 + value.is_some() as usize;
+            match (arg, value) {
+                ("-bios", Some(bios)) => {
+                    self.ovmf_path = Some(bios);
+                    self.firmware_mode = Some(FirmwareMode::Bios);
+                }
+                ("-drive", Some(drive)) if drive.contains("if=pflash") => {
+                    let Some(file) = option(&drive, "file").filter(|_| self.ovmf_path.is_none()) else {
+                        continue;
+                    };
+                    let dir = Path::new(&file).parent().map(|dir| dir.display().to_string()).unwrap_or_default();
+                    self.ovmf_path = Some(if dir.is_empty() { ".".to_string() } else { dir });
+                    self.firmware_mode = Some(FirmwareMode::Pflash);
+                    let name = Path::new(&file).file_name().unwrap_or_default().to_string_lossy().into_owned();
+                    let files = self.arch.unwrap_or_default().pflash_files();
+                    if !files.iter().any(|(code, _)| *code == name) {
+                        self.notes.push(format!("firmware {} is not a name the runner looks for ({:?}), \
+                                                 rename it or link it next to its vars file", file, files));
+                    }
+                }
+                ("-drive", Some(drive)) if drive.contains("fat:") => {
+                    let dir = drive.rsplit("fat:").next().unwrap_or_default().trim_start_matches("rw:");
+                    self.notes.push(format!("the runner stages its own ESP, {} is no longer needed", dir));
+                }
+                ("-m", Some(memory)) => self.memory = Some(memory),
+                ("-cpu", Some(cpu)) => self.cpu = Some(cpu),
+                ("-smp", Some(smp)) => {
+                    match smp.split(',').next().and_then(|cpus| cpus.trim_start_matches("cpus=").parse().ok()) {
+                        Some(cpus) => self.smp = Some(cpus),
+                        None => self.notes.push(format!("could not read -smp {}, set smp by hand", smp)),
+                    }
+                }
+                ("-accel", Some(accel)) => self.accel = Some(accel),
+                ("-enable-kvm", _) => self.accel = Some("kvm".to_string()),
+                ("-machine" | "-M", Some(machine)) => {
+                    self.notes.push(format!("dropped -machine {}, the runner picks the machine from arch", machine));
+                }
+                (arg, value) if MANAGED.contains(&arg) => {
+                    if arg == "-nographic" && value.is_some() {
+                        i -= 1;
+                    }
+                }
+                (arg, value) => {
+                    self.extra_qemu_args.push(arg.to_string());
+                    self.extra_qemu_args.extend(value);
+                }
+            }
+        }
+        if self.extra_qemu_args.iter().any(|arg| templated(arg)) {
+            self.notes.push("some extra_qemu_args use make or just variables, replace them by hand".to_string());
+        }
+    }
+
+    fn uefi_run(&mut self, args: &[String]) {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-b" | "--bios" => if let Some(bios) = args.next() {
+                    self.ovmf_path = Some(bios.clone());
+                    self.firmware_mode = Some(FirmwareMode::Bios);
+                },
+                "-q" | "--qemu" => if let Some(qemu) = args.next() {
+                    self.qemu_cmd = Some(qemu.clone());
+                },
+                "-f" | "--add-file" => if let Some(file) = args.next() {
+                    let (source, dest) = file.split_once(':').unwrap_or((file, ""));
+                    let dest = if dest.is_empty() {
+                        Path::new(source).file_name().unwrap_or_default().to_string_lossy().into_owned()
+                    } else {
+                        dest.to_string()
+                    };
+                    self.extra_files.push(ExtraFile { source: source.to_string(), dest });
+                },
+                "-s" | "--size" => {
+                    args.next();
+                }
+                "--" => {
+                    let rest = args.by_ref().cloned().collect::<Vec<_>>();
+                    let qemu = self.qemu_cmd.clone().unwrap_or_else(|| self.arch.unwrap_or_default().qemu_binary());
+                    self.qemu(&qemu, &rest);
+                }
+                _ => {}
+            }
+        }
+        if self.ovmf_path.is_none() {
+            self.ovmf_path = Some("OVMF.fd".to_string());
+            self.firmware_mode = Some(FirmwareMode::Bios);
+        }
+    }
+
+    fn cargo_config(&mut self, root: &Path) {
+        let Some((path, text)) = [".cargo/config.toml", ".cargo/config"].iter()
+            .find_map(|file| fs::read_to_string(root.join(file)).ok().map(|text| (*file, text))) else {
+            return;
+        };
+        let Ok(table) = text.parse::<toml::Table>() else {
+            warn!("Failed to parse {}, skipping it", path);
+            return;
+        };
+        let target = table.get("build").and_then(|build| build.get("target")).and_then(|target| target.as_str());
+        self.arch = self.arch.or(target.and_then(Arch::from_target));
+        let runners = table.get("target").and_then(|targets| targets.as_table()).into_iter().flatten()
+            .filter_map(|(triple, target)| Some((triple, target.get("runner")?)));
+        for (triple, runner) in runners {
+            let words = match runner {
+                toml::Value::String(runner) => split(runner),
+                toml::Value::Array(runner) => runner.iter().filter_map(|word| word.as_str().map(str::to_string)).collect(),
+                _ => continue,
+            };
+            let Some((program, args)) = words.split_first() else {
+                continue;
+            };
+            if program.ends_with("uefi-run") {
+                self.arch = Arch::from_target(triple).or(self.arch);
+                self.uefi_run(args);
+                self.sources.push(format!("uefi-run runner in {}", path));
+            } else if program.contains("qemu-system-") {
+                self.arch = Arch::from_target(triple).or(self.arch);
+                self.qemu(program, args);
+                self.sources.push(format!("QEMU runner in {}", path));
+            }
+        }
+    }
+
+    fn cargo_toml(&mut self, root: &Path) {
+        let Some(table) = fs::read_to_string(root.join("Cargo.toml")).ok()
+            .and_then(|text| text.parse::<toml::Table>().ok()) else {
+            return;
+        };
+        self.package = table.get("package").and_then(|package| package.get("name"))
+            .and_then(|name| name.as_str()).map(str::to_string);
+        let prebuilt = ["dependencies", "dev-dependencies", "build-dependencies"].iter()
+            .filter_map(|section| table.get(*section))
+            .any(|dependencies| dependencies.get("ovmf-prebuilt").is_some());
+        if prebuilt {
+            self.sources.push("ovmf-prebuilt dependency in Cargo.toml".to_string());
+            self.notes.push("ovmf-prebuilt fetched firmware at build time, the runner searches the system for OVMF; \
+                             pin a release with firmware_download for reproducible runs".to_string());
+        }
+    }
+
+    fn makefile(&mut self, root: &Path) {
+        for file in MAKEFILES {
+            let Ok(text) = fs::read_to_string(root.join(file)) else {
+                continue;
+            };
+            let joined = text.replace("\\\r\n", " ").replace("\\\n", " ");
+            let Some(line) = joined.lines().find(|line| line.contains("qemu-system-")) else {
+                continue;
+            };
+            let words = split(line.trim().trim_start_matches(['@', '-']));
+            let Some(start) = words.iter().position(|word| word.contains("qemu-system-")) else {
+                continue;
+            };
+            let end = words[start..].iter().position(|word| matches!(word.as_str(), "|" | "||" | "&&" | ";" | ">"))
+                .map_or(words.len(), |end| start + end);
+            self.qemu(&words[start], &words[start + 1..end]);
+            self.sources.push(format!("QEMU rule in {}", file));
+            return;
+        }
+    }
+
+    fn config(&self) -> Result<RunnerConfig, String> {
+        let arch = self.arch.unwrap_or_default();
+        let name = self.package.as_deref().unwrap_or("your_bin_name");
+        let mut builder = RunnerConfig::builder()
+            .arch(arch)
+            .binary_path(format!("target/{}/debug/{}.efi", arch.target(), name));
+        if let Some(qemu_cmd) = &self.qemu_cmd {
+            builder = builder.qemu_cmd(qemu_cmd);
+        }
+        if let Some(ovmf_path) = &self.ovmf_path {
+            builder = builder.ovmf_path(ovmf_path).firmware_mode(self.firmware_mode.unwrap_or_default());
+        }
+        if let Some(memory) = &self.memory {
+            builder = builder.memory(memory);
+        }
+        if let Some(cpu) = &self.cpu {
+            builder = builder.cpu(cpu);
+        }
+        let mut config = builder.build()?;
+        config.required_version = Some(version::CURRENT.to_string());
+        config.smp = self.smp;
+        config.accel = self.accel.clone();
+        config.extra_qemu_args = self.extra_qemu_args.clone();
+        config.extra_files = self.extra_files.clone();
+        Ok(config)
+    }
+}
+
+pub fn run(dir: &str, force: bool) -> Result<PathBuf, String> {
+    let root = Path::new(dir);
+    let output = root.join("uefapi-runner.toml");
+    if output.exists() && !force {
+        return Err(format!("{} already exists, pass --force to replace it", output.display()));
+    }
+    let mut findings = Findings::default();
+    findings.cargo_toml(root);
+    findings.cargo_config(root);
+    if findings.qemu_cmd.is_none() {
+        findings.makefile(root);
+    }
+    if findings.sources.is_empty() {
+        return Err(format!("No uefi-run runner, ovmf-prebuilt dependency or QEMU rule found in {}", root.display()));
+    }
+    let config = toml::to_string_pretty(&findings.config()?)
+        .map_err(|e| format!("Failed to serialize runner config: {}", e))?;
+    let mut text = findings.sources.iter().map(|source| format!("# Imported from the {}\n", source)).collect::<String>();
+    for note in &findings.notes {
+        warn!("{}", note);
+        text.push_str(&format!("# Note: {}\n", note));
+    }
+    text.push('\n');
+    text.push_str(&config);
+    fs::write(&output, text).map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    info!("Wrote {} from the {}", output.display(), findings.sources.join(", "));
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_honours_quotes() {
+        assert_eq!(split("qemu-system-x86_64  -m 256M -drive 'file=a b.img'"),
+                   ["qemu-system-x86_64", "-m", "256M", "-drive", "file=a b.img"]);
+        assert_eq!(split(r#"-append "console=ttyS0 quiet""#), ["-append", "console=ttyS0 quiet"]);
+        assert_eq!(split(r#"--name 'it"s'"#), ["--name", "it\"s"]);
+    }
+
+    #[test]
+    fn split_keeps_empty_quoted_words() {
+        assert_eq!(split(r#"-a "" -b ''"#), ["-a", "", "-b", ""]);
+        assert!(split("   ").is_empty());
+    }
+
+    #[test]
+    fn split_handles_escapes() {
+        assert_eq!(split(r"a\ b c"), ["a b", "c"]);
+        assert_eq!(split(r#""say \"hi\"""#), [r#"say "hi""#]);
+        assert_eq!(split(r"'C:\path'"), [r"C:\path"]);
+    }
+}
@@ -0,0 +1,72 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::arch::Arch;
+
+const USB_CONTROLLER: &str = "input-xhci";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputDevice {
+    Ps2,
+    UsbKeyboard,
+    UsbMouse,
+    UsbTablet,
+    VirtioKeyboard,
+    VirtioMouse,
+    VirtioTablet,
+}
+
+impl InputDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputDevice::Ps2 => "ps2",
+            InputDevice::UsbKeyboard => "usb-keyboard",
+            InputDevice::UsbMouse => "usb-mouse",
+            InputDevice::UsbTablet => "usb-tablet",
+            InputDevice::VirtioKeyboard => "virtio-keyboard",
+            InputDevice::VirtioMouse => "virtio-mouse",
+            InputDevice::VirtioTablet => "virtio-tablet",
+        }
+    }
+
+    fn qemu_device(&self) -> Option<String> {
+        let usb = |driver| format!("{},bus={}.0", driver, USB_CONTROLLER);
+        match self {
+            InputDevice::Ps2 => None,
+            InputDevice::UsbKeyboard => Some(usb("usb-kbd")),
+            InputDevice::UsbMouse => Some(usb("usb-mouse")),
+            InputDevice::UsbTablet => Some(usb("usb-tablet")),
+            InputDevice::VirtioKeyboard => Some("virtio-keyboard-pci".to_string()),
+            InputDevice::VirtioMouse => Some("virtio-mouse-pci".to_string()),
+            InputDevice::VirtioTablet => Some("virtio-tablet-pci".to_string()),
+        }
+    }
+
+    fn is_usb(&self) -> bool {
+        matches!(self, InputDevice::UsbKeyboard | InputDevice::UsbMouse | InputDevice::UsbTablet)
+    }
+}
+
+pub fn label(devices: &[InputDevice]) -> String {
+    if devices.is_empty() {
+        return "none".to_string();
+    }
+    devices.iter().map(InputDevice::name).collect::<Vec<_>>().join("+")
+}
+
+pub fn qemu_args(devices: &[InputDevice], arch: Arch) -> Vec<String> {
+    let mut args = Vec::new();
+    if arch == Arch::X86_64 && !devices.contains(&InputDevice::Ps2) {
+        args.extend(["-machine".to_string(), "i8042=off".to_string()]);
+    } else if arch != Arch::X86_64 && devices.contains(&InputDevice::Ps2) {
+        warn!("PS/2 input only exists on x86 machines, ignoring it on {:?}", arch);
+    }
+    if devices.iter().any(InputDevice::is_usb) {
+        args.extend(["-device".to_string(), format!("qemu-xhci,id={}", USB_CONTROLLER)]);
+    }
+    for device in devices.iter().filter_map(InputDevice::qemu_device) {
+        args.extend(["-device".to_string(), device]);
+    }
+    info!("Input devices: {}", label(devices));
+    args
+}
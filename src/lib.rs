@@ -0,0 +1,854 @@
+pub mod arch;
+pub mod audio;
+pub mod bisect;
+pub mod builder;
+pub mod clock;
+pub mod counters;
+pub mod dashboard;
+pub mod deadline;
+pub mod detach;
+pub mod diagnose;
+pub mod digest;
+pub mod disk;
+pub mod efi_status;
+pub mod export;
+pub mod extension;
+pub mod firmware;
+pub mod gdb;
+pub mod gpu;
+pub mod handle;
+pub mod harness;
+pub mod hooks;
+pub mod hotplug;
+pub mod image;
+pub mod import;
+pub mod input;
+pub mod libtest;
+pub mod limits;
+pub mod link;
+pub mod linux;
+pub mod matrix;
+pub mod memcheck;
+pub mod metrics;
+pub mod modules;
+pub mod multi;
+pub mod network;
+pub mod numa;
+pub mod nvram;
+pub mod outcome;
+pub mod output;
+pub mod overlay;
+pub mod passthrough;
+pub mod phases;
+pub mod pipeline;
+pub mod plugins;
+pub mod pool;
+pub mod preset;
+pub mod privilege;
+pub mod protocol;
+pub mod provenance;
+pub mod provision;
+pub mod qemu;
+pub mod qmp;
+pub mod quick;
+pub mod reaper;
+pub mod redact;
+pub mod removable;
+pub mod report;
+pub mod retention;
+pub mod rng;
+pub mod runner;
+pub mod scaffold;
+pub mod secureboot;
+pub mod seed;
+pub mod selftest;
+pub mod serial;
+pub mod serial_input;
+pub mod shaping;
+pub mod staging;
+pub mod testfw;
+pub mod tools;
+pub mod tpm;
+pub mod transform;
+pub mod verdict;
+pub mod version;
+pub mod watch;
+pub mod watchdog;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use arch::Arch;
+use audio::AudioConfig;
+use clock::ClockStep;
+use counters::CountersConfig;
+use digest::DigestFormat;
+use disk::DiskConfig;
+use extension::ExtensionConfig;
+use firmware::{Builder, Chain, Descriptors, Download, Edk2Build, ExplicitPaths, FirmwareDownload, FirmwareMode,
+               SystemSearch};
+use gdb::GdbConfig;
+use gpu::GpuConfig;
+use harness::TestConfig;
+use hooks::Hooks;
+use hotplug::HotplugStep;
+use image::EspImage;
+use input::InputDevice;
+use limits::ResourceLimits;
+use linux::LinuxConfig;
+use matrix::Matrix;
+use memcheck::MemcheckConfig;
+use network::NetworkConfig;
+use numa::NumaConfig;
+use nvram::NvramFuzz;
+use output::OutputConfig;
+use passthrough::{ChardevConfig, DeviceConfig};
+use plugins::Plugin;
+use preset::Preset;
+use qemu::Display;
+use privilege::Escalation;
+use protocol::GuestProtocol;
+use provenance::ProvenanceFormat;
+use provision::ProvisionConfig;
+use qmp::QmpConfig;
+use redact::Redactor;
+use removable::RemovableConfig;
+use retention::Retention;
+use secureboot::SecureBootConfig;
+use shaping::SerialShaping;
+use staging::{BinaryConfig, ExtraFile, StartupNsh};
+use tpm::TpmConfig;
+use transform::LogTransform;
+use watchdog::WatchdogConfig;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RunnerConfig {
+    #[serde(default)]
+    pub required_version: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub project_path: String,
+    #[serde(default)]
+    pub arch: Option<Arch>,
+    pub auto_build: bool,
+    #[serde(default)]
+    pub build_cmd: String,
+    #[serde(default)]
+    pub binary_path: String,
+    #[serde(default)]
+    pub boot_image: Option<String>,
+    #[serde(default)]
+    pub efi_name: String,
+    pub move_binary: bool,
+    #[serde(default)]
+    pub work_dir: Option<String>,
+    #[serde(default)]
+    pub keep_esp: bool,
+    #[serde(default)]
+    pub extra_files: Vec<ExtraFile>,
+    #[serde(default)]
+    pub binaries: Vec<BinaryConfig>,
+    #[serde(default)]
+    pub boot_entry: Option<String>,
+    #[serde(default)]
+    pub startup_nsh: Option<StartupNsh>,
+    #[serde(default)]
+    pub efi_shell_mapping: Option<String>,
+    #[serde(default)]
+    pub esp_image: Option<EspImage>,
+    #[serde(default)]
+    pub qemu_cmd: String,
+    #[serde(default)]
+    pub accel: Option<String>,
+    #[serde(default)]
+    pub ovmf_path: String,
+    #[serde(default = "default_firmware_search")]
+    pub firmware_search: bool,
+    #[serde(default)]
+    pub firmware_download: Option<FirmwareDownload>,
+    #[serde(default)]
+    pub firmware_build: Option<Edk2Build>,
+    #[serde(default)]
+    pub firmware_mode: FirmwareMode,
+    #[serde(default)]
+    pub nvram_fuzz: Option<NvramFuzz>,
+    #[serde(default)]
+    pub persist_nvram: bool,
+    #[serde(default)]
+    pub nvram_path: Option<String>,
+    #[serde(default)]
+    pub secure_boot: Option<SecureBootConfig>,
+    #[serde(default)]
+    pub tpm: Option<TpmConfig>,
+    #[serde(default)]
+    pub preset: Option<Preset>,
+    #[serde(default)]
+    pub cpu: Option<String>,
+    #[serde(default)]
+    pub memory: Option<String>,
+    #[serde(default)]
+    pub maxmem: Option<String>,
+    #[serde(default)]
+    pub smp: Option<u32>,
+    #[serde(default)]
+    pub numa: Option<NumaConfig>,
+    #[serde(default)]
+    pub display: Option<Display>,
+    #[serde(default)]
+    pub gpu: Option<GpuConfig>,
+    #[serde(default)]
+    pub qmp: Option<QmpConfig>,
+    #[serde(default)]
+    pub input: Option<Vec<InputDevice>>,
+    #[serde(default)]
+    pub audio: Option<AudioConfig>,
+    #[serde(default)]
+    pub chardev: Vec<ChardevConfig>,
+    #[serde(default)]
+    pub device: Vec<DeviceConfig>,
+    #[serde(default)]
+    pub extra_qemu_args: Vec<String>,
+    #[serde(default)]
+    pub escalation: Escalation,
+    #[serde(default)]
+    pub strict_tools: bool,
+    #[serde(default)]
+    pub launch_retries: u32,
+    #[serde(default)]
+    pub limits: Option<ResourceLimits>,
+    #[serde(default)]
+    pub network: Option<NetworkConfig>,
+    #[serde(default)]
+    pub disks: Vec<DiskConfig>,
+    #[serde(default)]
+    pub base_overlays: bool,
+    #[serde(default)]
+    pub removables: Vec<RemovableConfig>,
+    #[serde(default)]
+    pub hotplug: Vec<HotplugStep>,
+    #[serde(default)]
+    pub rtc_base: Option<String>,
+    #[serde(default)]
+    pub clock: Vec<ClockStep>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub virtio_rng: bool,
+    #[serde(default)]
+    pub watchdog: Option<WatchdogConfig>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub pvpanic: bool,
+    #[serde(default)]
+    pub matrix: Option<Matrix>,
+    #[serde(default)]
+    pub provision: Option<ProvisionConfig>,
+    pub stdio_serial: bool,
+    pub log_serial: bool,
+    pub log_path: String,
+    #[serde(default)]
+    pub serial_input: Option<String>,
+    #[serde(default)]
+    pub serial_input_prompt: Option<String>,
+    #[serde(default)]
+    pub serial_shaping: Option<SerialShaping>,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default = "default_artifacts_dir")]
+    pub artifacts_dir: String,
+    #[serde(default)]
+    pub retention: Retention,
+    #[serde(default)]
+    pub metrics_gateway: Option<String>,
+    #[serde(default)]
+    pub guest_protocol: Option<GuestProtocol>,
+    #[serde(default)]
+    pub linux: Option<LinuxConfig>,
+    #[serde(default)]
+    pub firmware_log: bool,
+    #[serde(default)]
+    pub qemu_debug_log: bool,
+    #[serde(default)]
+    pub counters: Option<CountersConfig>,
+    #[serde(default)]
+    pub plugins: Vec<Plugin>,
+    #[serde(default)]
+    pub extensions: Vec<ExtensionConfig>,
+    #[serde(default)]
+    pub memcheck: Option<MemcheckConfig>,
+    #[serde(default)]
+    pub gdb: Option<GdbConfig>,
+    #[serde(default)]
+    pub expect_efi_status: Option<String>,
+    #[serde(default)]
+    pub verdict_cmd: Option<String>,
+    #[serde(default)]
+    pub redact: Vec<String>,
+    #[serde(default)]
+    pub log_transforms: Vec<LogTransform>,
+    #[serde(default)]
+    pub provenance: Option<ProvenanceFormat>,
+    #[serde(default)]
+    pub digest: Option<DigestFormat>,
+    #[serde(default)]
+    pub report_path: Option<String>,
+    #[serde(default)]
+    pub report_inline_serial: bool,
+    #[serde(default)]
+    pub test: Option<TestConfig>,
+}
+
+impl Default for RunnerConfig {
+    fn default() -> Self {
+        let arch = Arch::default();
+        RunnerConfig {
+            required_version: None,
+            tags: Vec::new(),
+            project_path: ".".to_string(),
+            arch: None,
+            auto_build: true,
+            build_cmd: format!("build --target {}", arch.target()),
+            binary_path: String::new(),
+            boot_image: None,
+            efi_name: arch.efi_name().to_string(),
+            move_binary: false,
+            work_dir: None,
+            keep_esp: false,
+            extra_files: Vec::new(),
+            binaries: Vec::new(),
+            boot_entry: None,
+            startup_nsh: None,
+            efi_shell_mapping: None,
+            esp_image: None,
+            qemu_cmd: arch.qemu_binary(),
+            accel: None,
+            ovmf_path: "/usr/share/OVMF".to_string(),
+            firmware_search: true,
+            firmware_download: None,
+            firmware_build: None,
+            firmware_mode: FirmwareMode::Pflash,
+            nvram_fuzz: None,
+            persist_nvram: false,
+            nvram_path: None,
+            secure_boot: None,
+            tpm: None,
+            preset: None,
+            cpu: None,
+            memory: None,
+            maxmem: None,
+            smp: None,
+            numa: None,
+            display: None,
+            gpu: None,
+            qmp: None,
+            input: None,
+            audio: None,
+            chardev: Vec::new(),
+            device: Vec::new(),
+            extra_qemu_args: Vec::new(),
+            escalation: Escalation::Guidance,
+            strict_tools: false,
+            launch_retries: 0,
+            limits: None,
+            network: None,
+            disks: Vec::new(),
+            base_overlays: false,
+            removables: Vec::new(),
+            hotplug: Vec::new(),
+            rtc_base: None,
+            clock: Vec::new(),
+            seed: None,
+            virtio_rng: false,
+            watchdog: None,
+            timeout_secs: None,
+            idle_timeout_secs: None,
+            pvpanic: false,
+            matrix: None,
+            provision: None,
+            stdio_serial: true,
+            log_serial: false,
+            log_path: "uefapi-runner.log".to_string(),
+            serial_input: None,
+            serial_input_prompt: None,
+            serial_shaping: None,
+            output: OutputConfig::default(),
+            artifacts_dir: default_artifacts_dir(),
+            retention: Retention::default(),
+            metrics_gateway: None,
+            guest_protocol: None,
+            linux: None,
+            firmware_log: false,
+            qemu_debug_log: false,
+            counters: None,
+            plugins: Vec::new(),
+            extensions: Vec::new(),
+            memcheck: None,
+            gdb: None,
+            expect_efi_status: None,
+            verdict_cmd: None,
+            redact: Vec::new(),
+            log_transforms: Vec::new(),
+            provenance: None,
+            digest: None,
+            report_path: None,
+            report_inline_serial: false,
+            test: None,
+        }
+    }
+}
+
+fn default_firmware_search() -> bool {
+    true
+}
+
+fn default_artifacts_dir() -> String {
+    "uefapi-runner-artifacts".to_string()
+}
+
+fn valid_size(size: &str) -> bool {
+    let digits = size.trim_end_matches(|c: char| "kKmMgGtT".contains(c));
+    !digits.is_empty() && digits.len() + 1 >= size.len() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+impl RunnerConfig {
+    pub fn arch(&self) -> Arch {
+        let target = self.build_cmd.split_whitespace()
+            .skip_while(|arg| *arg != "--target")
+            .nth(1);
+        self.arch
+            .or_else(|| target.and_then(Arch::from_target))
+            .or_else(|| Arch::from_efi_name(&self.efi_name))
+            .unwrap_or_default()
+    }
+
+    pub fn fill_arch_defaults(&mut self) {
+        let arch = self.arch();
+        if self.efi_name.is_empty() {
+            self.efi_name = arch.efi_name().to_string();
+        }
+        if self.build_cmd.is_empty() {
+            self.build_cmd = format!("build --target {}", arch.target());
+        }
+        if self.qemu_cmd.is_empty() {
+            self.qemu_cmd = arch.qemu_binary();
+        }
+    }
+
+    pub fn boot_binary(&self) -> Option<&BinaryConfig> {
+        let entry = self.boot_entry.as_deref()?.trim_start_matches('/');
+        self.binaries.iter().find(|binary| binary.dest().eq_ignore_ascii_case(entry))
+    }
+
+    pub fn boot_path(&self) -> String {
+        match self.boot_binary().filter(|_| self.startup_nsh.as_ref().is_some_and(StartupNsh::generates)) {
+            Some(binary) => binary.dest().to_string(),
+            None => format!("EFI/BOOT/{}", self.efi_name),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(required) = &self.required_version {
+            version::check_required(required)?;
+        }
+        if self.work_dir.as_deref().is_some_and(str::is_empty) {
+            return Err("work_dir must be \"tmpfs\" or a directory".to_string());
+        }
+        if self.binary_path.is_empty() && self.boot_image.is_none() && self.boot_entry.is_none() {
+            return Err("binary_path is required".to_string());
+        }
+        if self.boot_image.is_some() && (self.esp_image.is_some() || !self.extra_files.is_empty()
+            || self.startup_nsh.is_some() || !self.binaries.is_empty()) {
+            return Err("boot_image replaces the staged ESP, esp_image, extra_files, binaries and startup_nsh \
+                        do not apply".to_string());
+        }
+        let mut dests = HashSet::new();
+        for binary in &self.binaries {
+            if binary.path.is_empty() || binary.dest().is_empty() {
+                return Err("binaries entries need a path and a dest".to_string());
+            }
+            if !dests.insert(binary.dest().to_ascii_uppercase()) {
+                return Err(format!("binaries dest {} is used twice", binary.dest));
+            }
+        }
+        if let Some(entry) = self.boot_entry.as_ref().filter(|_| self.boot_binary().is_none()) {
+            return Err(format!("boot_entry {} is not the dest of any binaries entry", entry));
+        }
+        if self.startup_nsh.is_some()
+            && self.extra_files.iter().any(|file| file.dest.trim_start_matches('/').eq_ignore_ascii_case("startup.nsh")) {
+            return Err("startup_nsh conflicts with an extra_files entry for startup.nsh".to_string());
+        }
+        if let Some(mapping) = &self.efi_shell_mapping {
+            if mapping.trim_end_matches(':').is_empty()
+                || !mapping.trim_end_matches(':').chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("efi_shell_mapping {} must be a shell mapping like fs1", mapping));
+            }
+            if self.startup_nsh.is_none() {
+                return Err("efi_shell_mapping only applies together with startup_nsh".to_string());
+            }
+        }
+        if !self.efi_name.to_ascii_uppercase().ends_with(".EFI") {
+            return Err(format!("efi_name {} must end in .EFI", self.efi_name));
+        }
+        if self.qemu_cmd.is_empty() {
+            return Err("qemu_cmd is required".to_string());
+        }
+        for size in [&self.memory, &self.maxmem].into_iter().flatten() {
+            if !valid_size(size) {
+                return Err(format!("invalid memory size {}", size));
+            }
+        }
+        if self.retention.keep == Some(0) {
+            return Err("retention keep must be at least 1".to_string());
+        }
+        if self.smp == Some(0) {
+            return Err("smp must be at least 1".to_string());
+        }
+        if self.maxmem.is_some() && self.memory.is_none() {
+            return Err("maxmem requires memory".to_string());
+        }
+        if let Some(numa) = &self.numa {
+            if self.preset == Some(Preset::FragmentedMemory) && !numa.nodes.is_empty() {
+                return Err("numa nodes clash with the nodes 0-3 the fragmented-memory preset defines".to_string());
+            }
+            numa.validate(self.memory.as_deref())?;
+        }
+        for step in &self.hotplug {
+            step.validate()?;
+        }
+        if let Some(gpu) = &self.gpu {
+            gpu.validate()?;
+        }
+        if let Some(qmp) = &self.qmp {
+            qmp.validate()?;
+        }
+        if let Some(audio) = &self.audio {
+            audio.validate()?;
+        }
+        if let Some(counters) = &self.counters {
+            counters.validate()?;
+        }
+        if let Some(memcheck) = &self.memcheck {
+            memcheck.validate()?;
+        }
+        if let Some(network) = &self.network {
+            network.validate()?;
+        }
+        plugins::validate(self)?;
+        passthrough::validate(&self.chardev, &self.device)?;
+        for extension in &self.extensions {
+            extension.validate()?;
+        }
+        if self.timeout_secs == Some(0) || self.idle_timeout_secs == Some(0) {
+            return Err("timeout_secs and idle_timeout_secs must be at least 1".to_string());
+        }
+        for step in &self.clock {
+            step.validate()?;
+        }
+        if let Some(base) = self.rtc_base.as_deref().filter(|base| !matches!(*base, "utc" | "localtime")) {
+            clock::parse_time(base)
+                .ok_or_else(|| format!("invalid rtc_base {}, expected utc, localtime or YYYY-MM-DDTHH:MM:SS", base))?;
+        }
+        if let Some(limits) = &self.limits {
+            limits.validate()?;
+        }
+        if let Some(linux) = &self.linux {
+            linux.validate()?;
+        }
+        if self.verdict_cmd.as_deref().is_some_and(|cmd| cmd.trim().is_empty()) {
+            return Err("verdict_cmd must not be empty".to_string());
+        }
+        if let Some(status) = &self.expect_efi_status {
+            efi_status::EfiStatus::lookup(status)
+                .ok_or_else(|| format!("unknown EFI status {}", status))?;
+        }
+        redact::compile(&self.redact)?;
+        for transform in &self.log_transforms {
+            transform.validate()?;
+        }
+        if self.serial_input_prompt.is_some() && self.serial_input.is_none() {
+            return Err("serial_input_prompt requires serial_input".to_string());
+        }
+        if self.persist_nvram && self.nvram_fuzz.is_some() {
+            return Err("nvram_fuzz works on a throwaway copy and cannot be combined with persist_nvram".to_string());
+        }
+        if self.nvram_path.is_some() && !self.persist_nvram {
+            return Err("nvram_path requires persist_nvram".to_string());
+        }
+        if let Some(download) = &self.firmware_download {
+            download.validate()?;
+        }
+        if let Some(build) = &self.firmware_build {
+            build.validate()?;
+        }
+        if let Some(provision) = &self.provision {
+            provision.validate(self)?;
+        }
+        if let Some(secure_boot) = &self.secure_boot {
+            secure_boot.validate()?;
+            if self.firmware_mode != FirmwareMode::Pflash {
+                return Err("secure_boot needs firmware_mode = \"pflash\" for its vars store".to_string());
+            }
+            if secure_boot.sign.is_some() && self.boot_image.is_some() {
+                return Err("secure_boot sign applies to the staged binary, not to a boot_image".to_string());
+            }
+            if secure_boot.enrolls() && self.nvram_fuzz.is_some() {
+                return Err("secure_boot key enrollment cannot be combined with nvram_fuzz".to_string());
+            }
+        }
+        if let Some(tpm) = &self.tpm {
+            tpm.validate(self.arch())?;
+        }
+        if let Some(shaping) = &self.serial_shaping {
+            shaping.validate()?;
+        }
+        Ok(())
+    }
+}
+
+pub fn example() -> RunnerConfig {
+    RunnerConfig {
+        project_path: ".".to_string(),
+        auto_build: true,
+        build_cmd: "build --target x86_64-unknown-uefi --release".to_string(),
+        binary_path: "target/x86_64-unknown-uefi/debug/your_bin_name.efi".to_string(),
+        efi_name: "BOOTX64.EFI".to_string(),
+        move_binary: true,
+        qemu_cmd: "/path_to_qemu/qemu-system-x86_64".to_string(),
+        ovmf_path: "/path_to_ovmf_files".to_string(),
+        stdio_serial: true,
+        log_serial: true,
+        log_path: "runner-x86_64-release.log".to_string(),
+        ..RunnerConfig::default()
+    }
+}
+
+pub fn firmware_provider(config: &RunnerConfig, offline: bool) -> Chain {
+    let (mode, arch, secure_boot) = (config.firmware_mode, config.arch(), config.secure_boot.is_some());
+    let mut chain = Chain::default();
+    if !config.ovmf_path.is_empty() {
+        chain = chain.with(ExplicitPaths { dir: PathBuf::from(&config.ovmf_path), mode, arch, secure_boot });
+    }
+    if let Some(spec) = &config.firmware_build {
+        chain = chain.with(Builder { spec: spec.clone(), mode, arch, secure_boot, offline });
+    }
+    if config.firmware_search {
+        chain = chain.with(Descriptors { mode, arch, secure_boot });
+        chain = chain.with(SystemSearch { qemu_cmd: config.qemu_cmd.clone(), mode, arch, secure_boot });
+    }
+    if let Some(spec) = &config.firmware_download {
+        chain = chain.with(Download { spec: spec.clone(), offline });
+    }
+    chain
+}
+
+fn merge_table(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge_table(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn read_table(path: &str) -> Result<toml::Table, String> {
+    let config = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+    toml::from_str(&config).map_err(|e| format!("Failed to parse config file {}: {}", path, e))
+}
+
+fn take_section(table: &mut toml::Table, section: &str, path: &str) -> Result<toml::Table, String> {
+    match table.remove(section) {
+        Some(toml::Value::Table(entries)) => Ok(entries),
+        Some(_) => Err(format!("{0} in {1} must be a table of [{0}.<name>] tables", section, path)),
+        None => Ok(toml::Table::new()),
+    }
+}
+
+fn pick(entries: &toml::Table, section: &str, name: &str, path: &str) -> Result<toml::Table, String> {
+    match entries.get(name) {
+        Some(toml::Value::Table(overlay)) => Ok(overlay.clone()),
+        Some(_) => Err(format!("{}.{} in {} is not a table", section, name, path)),
+        None => {
+            let known = entries.keys().cloned().collect::<Vec<_>>();
+            Err(format!("No {} {} in {}, known {}s: {}", section, name, path, section,
+                        if known.is_empty() { "none".to_string() } else { known.join(", ") }))
+        }
+    }
+}
+
+pub fn scenarios(path: &str) -> Result<Vec<(String, Option<String>)>, String> {
+    let scenarios = take_section(&mut read_table(path)?, "scenario", path)?;
+    Ok(scenarios.iter().map(|(name, scenario)| {
+        let description = scenario.get("description").and_then(|description| description.as_str());
+        (name.clone(), description.map(str::to_string))
+    }).collect())
+}
+
+pub fn read_profile(path: &str, profile: Option<&str>) -> Result<RunnerConfig, String> {
+    read_scenario(path, None, profile)
+}
+
+pub fn read_scenario(path: &str, scenario: Option<&str>, profile: Option<&str>) -> Result<RunnerConfig, String> {
+    info!("Loading config from {}", path);
+    let mut table = read_table(path)?;
+    let profiles = take_section(&mut table, "profile", path)?;
+    let scenarios = take_section(&mut table, "scenario", path)?;
+    let mut scenario = scenario.map(|name| pick(&scenarios, "scenario", name, path).map(|overlay| (name, overlay)))
+        .transpose()?;
+    let bundled = match scenario.as_mut().and_then(|(_, overlay)| overlay.remove("profile")) {
+        Some(toml::Value::String(profile)) => Some(profile),
+        Some(_) => return Err(format!("profile of a scenario in {} must be a profile name", path)),
+        None => None,
+    };
+    if let (Some(cli), Some(bundled)) = (profile, &bundled) {
+        info!("Using profile {} instead of the scenario's {}", cli, bundled);
+    }
+    if let Some(name) = profile.or(bundled.as_deref()) {
+        merge_table(&mut table, pick(&profiles, "profile", name, path)?);
+        info!("Using profile {}", name);
+    }
+    if let Some((name, mut overlay)) = scenario {
+        overlay.remove("description");
+        merge_table(&mut table, overlay);
+        info!("Using scenario {}", name);
+    }
+    let config: RunnerConfig = toml::Value::Table(table).try_into()
+        .map_err(|e| format!("Failed to parse config file {}: {}", path, e))?;
+    info!("Config loaded: {}", Redactor::new(&config).redact(&format!("{:?}", config)));
+    Ok(config)
+}
+
+pub fn read_config(path: &str) -> Result<RunnerConfig, String> {
+    read_profile(path, None)
+}
+
+pub fn load_config(path: &str) -> RunnerConfig {
+    read_config(path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn lookup<'a>(root: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.').try_fold(root, |value, part| value.get(part))
+}
+
+pub fn apply_override(config: &RunnerConfig, assignment: &str) -> Result<RunnerConfig, String> {
+    let (key, value) = assignment.split_once('=')
+        .ok_or_else(|| format!("override {} must be key=value", assignment))?;
+    let value = toml::from_str::<toml::Table>(&format!("value = {}", value)).ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    let mut root = toml::Value::try_from(config).map_err(|e| format!("Failed to serialize config: {}", e))?;
+    let (parents, field) = match key.rsplit_once('.') {
+        Some((parents, field)) => (parents.split('.').collect(), field),
+        None => (Vec::new(), key),
+    };
+    let mut table = root.as_table_mut().expect("Config serializes to a table");
+    for part in parents {
+        table = table.entry(part).or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut().ok_or_else(|| format!("{} in override {} is not a table", part, key))?;
+    }
+    table.insert(field.to_string(), value);
+    let config: RunnerConfig = root.try_into().map_err(|e| format!("Invalid override {}: {}", assignment, e))?;
+    let applied = toml::Value::try_from(&config).map_err(|e| format!("Failed to serialize config: {}", e))?;
+    if lookup(&applied, key).is_none() {
+        return Err(format!("unknown config field {}", key));
+    }
+    Ok(config)
+}
+
+pub fn build(config: &RunnerConfig, hooks: &Hooks) -> Result<Option<Duration>, String> {
+    if config.auto_build {
+        info!("Building project");
+        let start = Instant::now();
+        let mut cmd = Command::new("cargo")
+            .args(config.build_cmd.split_whitespace())
+            .args(config.binaries.iter().flat_map(|binary| {
+                let package = binary.package.iter().flat_map(|package| ["-p", package]);
+                package.chain(binary.bin.iter().flat_map(|bin| ["--bin", bin]))
+            }))
+            .args(output::quiet().then_some("--quiet"))
+            .current_dir(&config.project_path)
+            .stdout(config.output.logs.stdio())
+            .spawn().map_err(|e| format!("Failed to run build command: {}", e))?;
+        let status = cmd.wait().map_err(|e| format!("Failed to wait for build command: {}", e))?;
+        if !status.success() {
+            return Err("Build failed".to_string());
+        }
+        info!("Build successful");
+        hooks.build_complete(start.elapsed());
+        return Ok(Some(start.elapsed()));
+    }
+    Ok(None)
+}
+
+enum StagingDir {
+    Temp(TempDir),
+    Persistent(PathBuf),
+}
+
+pub struct Workspace {
+    work_dir: StagingDir,
+    run_dir: TempDir,
+    keep: bool,
+}
+
+impl Workspace {
+    pub fn create(config: &RunnerConfig) -> Result<Workspace, String> {
+        let work_dir = config.work_dir.as_deref();
+        let work_dir = match staging::staging_root(work_dir) {
+            Some(dir) if staging::is_persistent(work_dir) => {
+                fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Failed to create work dir {}: {}", dir.display(), e))?;
+                info!("Staging into persistent work dir {}", dir.display());
+                StagingDir::Persistent(dir)
+            }
+            root => StagingDir::Temp(match root {
+                Some(root) => tempfile::tempdir_in(root),
+                None => tempfile::tempdir(),
+            }.map_err(|e| format!("Failed to create temp dir: {}", e))?),
+        };
+        let run_dir = tempfile::tempdir()
+            .map_err(|e| format!("Failed to create runtime dir: {}", e))?;
+        Ok(Workspace { work_dir, run_dir, keep: config.keep_esp })
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        match &self.work_dir {
+            StagingDir::Temp(dir) => dir.path(),
+            StagingDir::Persistent(dir) => dir,
+        }
+    }
+
+    pub fn run_dir(&self) -> &Path {
+        self.run_dir.path()
+    }
+
+    pub fn esp_image(&self) -> PathBuf {
+        self.run_dir().join("esp.img")
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !self.keep {
+            return;
+        }
+        if let StagingDir::Temp(dir) = &mut self.work_dir {
+            dir.disable_cleanup(true);
+        }
+        self.run_dir.disable_cleanup(true);
+        info!("Kept the ESP tree in {} and the runtime dir in {}", self.work_dir().display(), self.run_dir().display());
+        if self.esp_image().exists() {
+            info!("Kept the ESP image {}", self.esp_image().display());
+        }
+    }
+}
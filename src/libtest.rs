@@ -0,0 +1,52 @@
+use crate::outcome::{TestResult, TestStatus};
+use crate::pipeline::Context;
+use crate::testfw;
+
+pub const WHOLE_BINARY: &str = "harness";
+
+pub fn list(ctx: &mut Context, ignored: bool) -> Result<Vec<String>, String> {
+    if ignored {
+        return Ok(Vec::new());
+    }
+    if ctx.dry_run || !testfw::selectable(ctx) {
+        return Ok(vec![WHOLE_BINARY.to_string()]);
+    }
+    testfw::list(ctx)
+}
+
+pub fn print_list(names: &[String], format: Option<&str>) -> Result<(), String> {
+    let terse = match format.unwrap_or("pretty") {
+        "terse" => true,
+        "pretty" => false,
+        format => return Err(format!("Unsupported test list format {}", format)),
+    };
+    for name in names {
+        println!("{}: test", name);
+    }
+    if !terse {
+        println!("\n{} test(s), 0 benchmarks", names.len());
+    }
+    Ok(())
+}
+
+pub fn print_results(ctx: &Context) {
+    let Some(outcome) = &ctx.outcome else {
+        return;
+    };
+    let tests = match ctx.guest_results.as_slice() {
+        [] => outcome.tests.iter().filter(|test| test.name == WHOLE_BINARY).cloned().collect::<Vec<_>>(),
+        results => results.to_vec(),
+    };
+    let passed = tests.iter().filter(|test| test.status == TestStatus::Passed).count();
+    println!("\nrunning {} test{}", tests.len(), if tests.len() == 1 { "" } else { "s" });
+    for TestResult { name, status, message, .. } in &tests {
+        match (status, message) {
+            (TestStatus::Passed, _) => println!("test {} ... ok", name),
+            (_, Some(message)) => println!("test {} ... FAILED\n    {}", name, message),
+            _ => println!("test {} ... FAILED", name),
+        }
+    }
+    println!("\ntest result: {}. {} passed; {} failed; 0 ignored; 0 measured; 0 filtered out; finished in {:.2}s\n",
+             if passed == tests.len() { "ok" } else { "FAILED" }, passed, tests.len() - passed,
+             outcome.timings.run_ms.unwrap_or(0) as f64 / 1000.0);
+}
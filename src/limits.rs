@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::PathBuf;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::privilege::which;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    #[serde(default)]
+    pub cpus: Option<String>,
+    #[serde(default)]
+    pub nice: Option<i32>,
+    #[serde(default)]
+    pub address_space: Option<String>,
+    #[serde(default)]
+    pub cgroup: Option<CgroupLimits>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct CgroupLimits {
+    #[serde(default = "default_cgroup_parent")]
+    pub parent: String,
+    #[serde(default)]
+    pub memory_max: Option<String>,
+    #[serde(default)]
+    pub cpu_max: Option<String>,
+}
+
+fn default_cgroup_parent() -> String {
+    "/sys/fs/cgroup".to_string()
+}
+
+pub fn size_bytes(size: &str) -> Option<u64> {
+    let (digits, unit) = match size.char_indices().last()? {
+        (index, c) if c.is_ascii_alphabetic() => (&size[..index], c.to_ascii_uppercase()),
+        _ => (size, 'B'),
+    };
+    let shift = match unit {
+        'B' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        'T' => 40,
+        _ => return None,
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+impl ResourceLimits {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(cpus) = &self.cpus {
+            if cpus.is_empty() || !cpus.chars().all(|c| c.is_ascii_digit() || ",-".contains(c)) {
+                return Err(format!("invalid CPU list {}", cpus));
+            }
+        }
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(format!("nice value {} out of range -20..19", nice));
+            }
+        }
+        if let Some(size) = &self.address_space {
+            size_bytes(size).ok_or_else(|| format!("invalid address space limit {}", size))?;
+        }
+        Ok(())
+    }
+
+    pub fn wrapper(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(size) = &self.address_space {
+            args.extend(["prlimit".to_string(),
+                         format!("--as={}", size_bytes(size).unwrap_or(u64::MAX)), "--".to_string()]);
+        }
+        if let Some(nice) = self.nice {
+            if nice < 0 {
+                warn!("Negative niceness needs CAP_SYS_NICE, QEMU may fail to start");
+            }
+            args.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        if let Some(cpus) = &self.cpus {
+            args.extend(["taskset".to_string(), "-c".to_string(), cpus.clone()]);
+        }
+        for tool in ["prlimit", "nice", "taskset"] {
+            if args.iter().any(|arg| arg == tool) && which(tool).is_none() {
+                warn!("{} not found in PATH, resource limits cannot be applied", tool);
+            }
+        }
+        args
+    }
+}
+
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn attach(limits: &CgroupLimits, pid: u32) -> Result<Cgroup, String> {
+        let path = PathBuf::from(&limits.parent).join(format!("uefapi-runner-{}", pid));
+        fs::create_dir(&path)
+            .map_err(|e| format!("Failed to create cgroup {}: {}", path.display(), e))?;
+        let cgroup = Cgroup { path };
+        if let Some(memory) = &limits.memory_max {
+            cgroup.write("memory.max", memory)?;
+        }
+        if let Some(cpu) = &limits.cpu_max {
+            cgroup.write("cpu.max", cpu)?;
+        }
+        cgroup.write("cgroup.procs", &pid.to_string())?;
+        info!("QEMU moved into cgroup {}", cgroup.path.display());
+        Ok(cgroup)
+    }
+
+    fn write(&self, file: &str, value: &str) -> Result<(), String> {
+        fs::write(self.path.join(file), value)
+            .map_err(|e| format!("Failed to write {} to {}/{}: {}", value, self.path.display(), file, e))
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            warn!("Failed to remove cgroup {}: {}", self.path.display(), e);
+        }
+    }
+}
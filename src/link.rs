@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    #[default]
+    Hub,
+    Stream,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LinkConfig {
+    pub name: String,
+    pub vms: Vec<String>,
+    #[serde(default)]
+    pub mode: LinkMode,
+    #[serde(default = "default_nic_model")]
+    pub nic_model: String,
+}
+
+fn default_nic_model() -> String {
+    "virtio-net-pci".to_string()
+}
+
+fn free_udp_port() -> io::Result<u16> {
+    Ok(UdpSocket::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+fn mac(link: usize, vm: usize) -> String {
+    format!("52:54:00:aa:{:02x}:{:02x}", link, vm)
+}
+
+fn spawn_hub(name: String, socket: UdpSocket, peers: Vec<SocketAddr>) {
+    thread::spawn(move || {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Link {} hub stopped: {}", name, e);
+                    return;
+                }
+            };
+            for peer in peers.iter().filter(|peer| **peer != from) {
+                let _ = socket.send_to(&buf[..len], peer);
+            }
+        }
+    });
+}
+
+impl LinkConfig {
+    pub fn validate(&self, known: &[String]) -> Result<(), String> {
+        if let Some(vm) = self.vms.iter().find(|vm| !known.contains(vm)) {
+            return Err(format!("link {} refers to unknown VM {}", self.name, vm));
+        }
+        if self.mode == LinkMode::Stream && self.vms.len() != 2 {
+            return Err(format!("stream link {} needs exactly two VMs", self.name));
+        }
+        Ok(())
+    }
+
+    pub fn setup(&self, index: usize, args: &mut BTreeMap<String, Vec<String>>) -> io::Result<()> {
+        let id = format!("link{}", index);
+        let mut netdevs = Vec::new();
+        match self.mode {
+            LinkMode::Hub => {
+                let hub = UdpSocket::bind("127.0.0.1:0")?;
+                let hub_port = hub.local_addr()?.port();
+                let mut peers = Vec::new();
+                for _ in &self.vms {
+                    let port = free_udp_port()?;
+                    peers.push(SocketAddr::from(([127, 0, 0, 1], port)));
+                    netdevs.push(format!("socket,id={},udp=127.0.0.1:{},localaddr=127.0.0.1:{}",
+                                         id, hub_port, port));
+                }
+                info!("Link {} hub on 127.0.0.1:{} for {}", self.name, hub_port, self.vms.join(", "));
+                spawn_hub(self.name.clone(), hub, peers);
+            }
+            LinkMode::Stream => {
+                let port = crate::qmp::allocate_port()?;
+                netdevs.push(format!("socket,id={},listen=127.0.0.1:{}", id, port));
+                netdevs.push(format!("socket,id={},connect=127.0.0.1:{}", id, port));
+                info!("Link {} streams {} -> {} over 127.0.0.1:{}",
+                      self.name, self.vms[1], self.vms[0], port);
+            }
+        }
+        for (vm_index, (vm, netdev)) in self.vms.iter().zip(netdevs).enumerate() {
+            let vm_args = args.entry(vm.clone()).or_default();
+            vm_args.push("-netdev".to_string());
+            vm_args.push(netdev);
+            vm_args.push("-device".to_string());
+            vm_args.push(format!("{},netdev={},mac={}", self.nic_model, id, mac(index, vm_index)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::outcome::{TestResult, TestStatus};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KernelDelivery {
+    #[default]
+    Esp,
+    FwCfg,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LinuxConfig {
+    pub kernel: String,
+    #[serde(default)]
+    pub initrd: Option<String>,
+    #[serde(default)]
+    pub delivery: KernelDelivery,
+    #[serde(default = "default_kernel_name")]
+    pub kernel_name: String,
+    #[serde(default = "default_initrd_name")]
+    pub initrd_name: String,
+    #[serde(default)]
+    pub cmdline: Option<String>,
+    #[serde(default)]
+    pub marker: Option<String>,
+}
+
+fn default_kernel_name() -> String {
+    "vmlinuz".to_string()
+}
+
+fn default_initrd_name() -> String {
+    "initrd.img".to_string()
+}
+
+impl LinuxConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.delivery == KernelDelivery::FwCfg && (self.kernel_name != default_kernel_name()
+            || self.initrd_name != default_initrd_name()) {
+            return Err("kernel_name and initrd_name only apply to ESP delivery".to_string());
+        }
+        if self.delivery == KernelDelivery::Esp && self.cmdline.is_some() {
+            return Err("cmdline needs fw-cfg delivery, the loader supplies it on the ESP".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn stage(&self, esp: &Path, staged: &mut Vec<PathBuf>) -> Result<u64, String> {
+        let mut bytes = 0;
+        if self.delivery != KernelDelivery::Esp {
+            return Ok(bytes);
+        }
+        let files = [(Some(&self.kernel), &self.kernel_name), (self.initrd.as_ref(), &self.initrd_name)];
+        for (source, name) in files {
+            let Some(source) = source else {
+                continue;
+            };
+            let dest = esp.join(name);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            info!("Staging {} as {}", source, dest.display());
+            bytes += fs::copy(source, &dest).map_err(|e| format!("Failed to stage {}: {}", source, e))?;
+            staged.push(dest);
+        }
+        Ok(bytes)
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        if self.delivery != KernelDelivery::FwCfg {
+            return Vec::new();
+        }
+        let mut args = vec!["-kernel".to_string(), self.kernel.clone()];
+        if let Some(initrd) = &self.initrd {
+            args.extend(["-initrd".to_string(), initrd.clone()]);
+        }
+        if let Some(cmdline) = &self.cmdline {
+            args.extend(["-append".to_string(), cmdline.clone()]);
+        }
+        args
+    }
+
+    pub fn verify(&self, serial_log: Option<&Path>) -> Option<TestResult> {
+        let marker = self.marker.as_ref()?;
+        let serial = serial_log.and_then(|log| fs::read_to_string(log).ok()).unwrap_or_default();
+        let found = serial.contains(marker.as_str());
+        Some(TestResult {
+            name: "linux-handoff".to_string(),
+            status: if found { TestStatus::Passed } else { TestStatus::Failed },
+            duration_ms: None,
+            message: (!found).then(|| format!("serial output never contained {:?}", marker)),
+        })
+    }
+}
@@ -1,120 +1,605 @@
-use std::env::args;
 use std::fs;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::path::Path;
+use std::process::exit;
+use std::sync::Arc;
+use std::time::Duration;
+use clap::{Args, Parser, Subcommand};
 use log::{error, info, warn};
-use serde::{Deserialize, Serialize};
+use uefapi_runner::handle::Control;
+use uefapi_runner::hooks::Hooks;
+use uefapi_runner::metrics::Metrics;
+use uefapi_runner::outcome::{ExitReason, RunOutcome, TestStatus};
+use uefapi_runner::output::{OutputConfig, Stream};
+use uefapi_runner::pipeline::{Build, Configure, Context, Pipeline};
+use uefapi_runner::report::{self, RunReport};
+use uefapi_runner::{apply_override, bisect, dashboard, detach, digest, example, export, handle, import, libtest,
+                    load_config, matrix, multi, output, pool, quick, read_scenario, reaper, scaffold, scenarios,
+                    selftest, testfw, tools, version, watch, RunnerConfig};
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
-pub struct RunnerConfig {
-    pub project_path: String,
-    pub auto_build: bool,
-    pub build_cmd: String,
-    pub binary_path: String,
-    pub efi_name: String,
-    pub move_binary: bool,
-    pub qemu_cmd: String,
-    pub ovmf_path: String,
-    pub stdio_serial: bool,
-    pub log_serial: bool,
-    pub log_path: String,
+const DEFAULT_CONFIG: &str = "uefapi-runner.toml";
+const DEFAULT_ARTIFACTS: &str = "uefapi-runner-artifacts";
+const TIMEOUT_EXIT: i32 = 2;
+const BOOT_IMAGE_EXTENSIONS: &[&str] = &[".img", ".iso", ".qcow2"];
+
+#[derive(Parser)]
+#[command(version, about = "UEFAPI Cargo UEFI Project Runner", args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Only print PASS or FAIL
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build, stage and boot the project
+    Run(RunArgs),
+    /// Run in test mode and exit with the harness verdict
+    Test(RunArgs),
+    /// Only run the configured cargo build
+    Build(RunArgs),
+    /// Boot with QEMU halted for gdb or lldb
+    Debug(RunArgs),
+    /// Rebuild and reboot whenever the project changes
+    Watch {
+        #[command(flatten)]
+        run: RunArgs,
+        /// Kill QEMU on changes instead of asking the guest to power down
+        #[arg(long)]
+        kill: bool,
+    },
+    /// Write a shell script that stages and boots the run without the runner
+    ExportScript {
+        #[command(flatten)]
+        run: RunArgs,
+        #[arg(long, short, default_value = "run.sh")]
+        output: String,
+    },
+    /// Generate uefapi-runner.toml from a uefi-run, ovmf-prebuilt or Makefile setup
+    Import {
+        #[arg(default_value = ".")]
+        dir: String,
+        #[arg(long)]
+        force: bool,
+    },
+    /// List the optional tools the config uses and what runs without them
+    Check(RunArgs),
+    /// Boot a built-in known-good EFI binary to check QEMU, the firmware and the runner
+    Selftest(RunArgs),
+    /// Run a [scenario.<name>] of the config, or list them with `scenario list`
+    Scenario {
+        name: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Clean up after crashed runs
+    Clean {
+        /// Stop QEMU and swtpm processes whose runner is gone
+        #[arg(long)]
+        processes: bool,
+    },
+    /// Write an example uefapi-runner.toml
+    Gen,
+    /// Run a multi-VM scenario
+    Multi {
+        #[arg(default_value = "uefapi-scenario.toml")]
+        scenario: String,
+    },
+    /// Follow the console of a detached run
+    Attach {
+        #[arg(default_value = DEFAULT_ARTIFACTS)]
+        dir: String,
+    },
+    /// Ask a detached run to shut down
+    Stop {
+        #[arg(default_value = DEFAULT_ARTIFACTS)]
+        dir: String,
+    },
+    /// Kill a detached run
+    Kill {
+        #[arg(default_value = DEFAULT_ARTIFACTS)]
+        dir: String,
+    },
+    /// Replace this binary with another release
+    SelfUpdate {
+        version: Option<String>,
+    },
+    /// Bisect a failure between two revisions
+    Bisect {
+        #[arg(long)]
+        good: String,
+        #[arg(long)]
+        bad: String,
+        /// Regex matching the failure on the serial console
+        #[arg(long)]
+        until: String,
+        #[arg(long, default_value_t = 120)]
+        timeout: u64,
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: String,
+    },
+    /// Run many EFI binaries on a pool of reused VMs
+    Pool {
+        inputs: Vec<String>,
+        #[arg(long, default_value_t = 2)]
+        size: usize,
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+        #[arg(long, default_value = DEFAULT_CONFIG)]
+        config: String,
+    },
+    /// Boot EFI binaries without a project
+    Quick {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[arg(long)]
+        compare: bool,
+        #[arg(long)]
+        parallel: bool,
+    },
+    /// Create a new UEFI project
+    New {
+        name: String,
+    },
+    /// Create the guest helper crate
+    GuestCrate {
+        #[arg(default_value = "uefapi-guest")]
+        dir: String,
+    },
+    /// Serve the run dashboard
+    Dashboard {
+        #[arg(default_value = ".")]
+        root: String,
+        #[arg(default_value_t = 8080)]
+        port: u16,
+    },
+    #[command(hide = true)]
+    Supervise {
+        config: String,
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
 }
 
-pub fn example() -> RunnerConfig {
-    RunnerConfig {
-        project_path: ".".to_string(),
-        auto_build: true,
-        build_cmd: "build --target x86_64-unknown-uefi --release".to_string(),
-        binary_path: "target/x86_64-unknown-uefi/debug/your_bin_name.efi".to_string(),
-        efi_name: "BOOTX64.EFI".to_string(),
-        move_binary: true,
-        qemu_cmd: "/path_to_qemu/qemu-system-x86_64".to_string(),
-        ovmf_path: "/path_to_ovmf_files".to_string(),
-        stdio_serial: true,
-        log_serial: true,
-        log_path: "runner-x86_64-release.log".to_string(),
+#[derive(Args, Clone, Default)]
+struct RunArgs {
+    /// Config file, EFI binary or disk image, optionally followed by an EFI binary
+    #[arg(num_args = 0..=2)]
+    inputs: Vec<String>,
+    #[arg(long)]
+    config: Option<String>,
+    /// Apply the [profile.<name>] table of the config file on top of its base fields
+    #[arg(long)]
+    profile: Option<String>,
+    /// Override a config field, e.g. --set test.timeout_secs=30
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
+    #[arg(long)]
+    no_build: bool,
+    /// Leave the staged EFI/BOOT tree and generated image on disk after QEMU exits
+    #[arg(long)]
+    keep_workdir: bool,
+    #[arg(long)]
+    ovmf_path: Option<String>,
+    #[arg(long)]
+    qemu_cmd: Option<String>,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long)]
+    timeout: Option<u64>,
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    /// Write a JSON run report to this path
+    #[arg(long, value_name = "PATH")]
+    report: Option<String>,
+    /// Fail instead of disabling features whose optional tools are missing
+    #[arg(long)]
+    strict: bool,
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long)]
+    stats: bool,
+    #[arg(long)]
+    detach: bool,
+    /// List the tests like a libtest binary, for cargo-nextest running the runner as a target runner
+    #[arg(long, hide = true)]
+    list: bool,
+    #[arg(long, hide = true)]
+    format: Option<String>,
+    /// Run the test named by the second input, in libtest form `<binary> --exact <name>`
+    #[arg(long, hide = true)]
+    exact: bool,
+    #[arg(long, hide = true)]
+    ignored: bool,
+    #[arg(long, hide = true)]
+    nocapture: bool,
+    #[arg(skip)]
+    scenario: Option<String>,
+    #[arg(skip)]
+    selftest: bool,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Mode {
+    Run,
+    Test,
+    Build,
+    Debug,
+}
+
+fn fail(message: &str) -> ! {
+    error!("{}", message);
+    exit(1);
+}
+
+fn check(ok: bool) {
+    if !ok {
+        exit(1);
+    }
+}
+
+fn timed_out(outcome: &RunOutcome) -> bool {
+    outcome.exit_reason.as_ref().is_some_and(ExitReason::is_timeout)
+        || outcome.tests.iter().any(|test| test.status == TestStatus::Timeout)
+}
+
+fn write_report(config: &RunnerConfig, runs: Vec<RunReport>) {
+    if let Some(path) = &config.report_path {
+        if let Err(e) = report::write(Path::new(path), runs) {
+            warn!("{}", e);
+        }
+    }
+}
+
+fn resolve(args: &RunArgs) -> RunnerConfig {
+    let default_config = args.config.clone()
+        .or(std::env::var("UEFAPI_RUNNER_CONFIG").ok())
+        .unwrap_or(DEFAULT_CONFIG.to_string());
+    let lower = args.inputs.first().map(|input| input.to_ascii_lowercase()).unwrap_or_default();
+    let boot_image = args.inputs.first().filter(|_| BOOT_IMAGE_EXTENSIONS.iter().any(|ext| lower.ends_with(ext)));
+    let (config_path, binary) = match args.inputs.as_slice() {
+        [binary, ..] if lower.ends_with(".efi") => (default_config, Some(binary)),
+        [_, ..] if boot_image.is_some() => (default_config, None),
+        [binary, ..] if args.config.is_some() => (default_config, Some(binary)),
+        [config, binary, ..] => (config.clone(), Some(binary)),
+        [config] => (config.clone(), None),
+        [] => (default_config, None),
+    };
+    let standalone = binary.is_some() || boot_image.is_some() || args.selftest;
+    let mut config = if standalone && !Path::new(&config_path).exists() {
+        if let Some(profile) = &args.profile {
+            fail(&format!("Profile {} needs a config file, but {} does not exist", profile, config_path));
+        }
+        if let Some(scenario) = &args.scenario {
+            fail(&format!("Scenario {} needs a config file, but {} does not exist", scenario, config_path));
+        }
+        info!("No {} found, running with the default config", config_path);
+        RunnerConfig::default()
+    } else {
+        read_scenario(&config_path, args.scenario.as_deref(), args.profile.as_deref()).unwrap_or_else(|e| fail(&e))
+    };
+    for assignment in &args.overrides {
+        config = apply_override(&config, assignment).unwrap_or_else(|e| fail(&e));
+        info!("Config override {}", assignment);
     }
+    if args.no_build {
+        config.auto_build = false;
+    }
+    if args.keep_workdir {
+        config.keep_esp = true;
+    }
+    if args.strict {
+        config.strict_tools = true;
+    }
+    if let Some(ovmf_path) = &args.ovmf_path {
+        config.ovmf_path = ovmf_path.clone();
+    }
+    if let Some(qemu_cmd) = &args.qemu_cmd {
+        config.qemu_cmd = qemu_cmd.clone();
+    }
+    if let Some(timeout) = args.timeout {
+        config.timeout_secs = Some(timeout);
+        if let Some(test) = &mut config.test {
+            test.timeout_secs = Some(timeout);
+        }
+    }
+    for tag in &args.tags {
+        if !config.tags.contains(tag) {
+            config.tags.push(tag.clone());
+        }
+    }
+    if let Some(seed) = args.seed {
+        config.seed = Some(seed);
+    }
+    if let Some(report) = &args.report {
+        config.report_path = Some(report.clone());
+    }
+    if let Some(binary) = binary {
+        info!("Running {} built by cargo", binary);
+        config.binary_path = binary.clone();
+        config.auto_build = false;
+        config.move_binary = false;
+    }
+    if let Some(image) = boot_image {
+        config.boot_image = Some(image.clone());
+    }
+    config
 }
 
 fn main() {
-    env_logger::init();
+    let cli = Cli::parse();
+    output::init_logger();
+    handle::install_signal_handler();
+    if cli.quiet {
+        output::set_quiet();
+    }
     info!("UEFAPI Cargo UEFI Project Runner, Version {}", env!("CARGO_PKG_VERSION"));
-    if let Some("gen") = args().nth(1).as_deref() {
-        let config = example();
-        let config = toml::to_string_pretty(&config)
-            .expect("Failed to serialize example config");
-        fs::write("uefapi-runner.toml", config)
-            .expect("Failed to write example config");
-        info!("Example config written to uefapi-runner.toml");
+    match cli.command {
+        None => run(cli.run, Mode::Run),
+        Some(Command::Run(args)) => run(args, Mode::Run),
+        Some(Command::Test(args)) => run(args, Mode::Test),
+        Some(Command::Build(args)) => run(args, Mode::Build),
+        Some(Command::Debug(args)) => run(args, Mode::Debug),
+        Some(Command::Watch { run, kill }) => {
+            let config = resolve(&run);
+            output::apply(&config.output);
+            check(watch::run(&config, kill));
+        }
+        Some(Command::ExportScript { run, output }) => {
+            if let Err(e) = export::write(&resolve(&run), Path::new(&output)) {
+                fail(&e);
+            }
+        }
+        Some(Command::Import { dir, force }) => {
+            if let Err(e) = import::run(&dir, force) {
+                fail(&e);
+            }
+        }
+        Some(Command::Check(args)) => {
+            let mut config = resolve(&args);
+            config.fill_arch_defaults();
+            check(tools::report(&config));
+        }
+        Some(Command::Selftest(mut args)) => {
+            reaper::reap();
+            args.selftest = true;
+            let config = resolve(&args);
+            output::apply(&config.output);
+            check(selftest::run(&config));
+        }
+        Some(Command::Scenario { name, run: args }) if name == "list" => {
+            let path = args.config.clone().or(std::env::var("UEFAPI_RUNNER_CONFIG").ok())
+                .or(args.inputs.first().cloned())
+                .unwrap_or(DEFAULT_CONFIG.to_string());
+            let scenarios = scenarios(&path).unwrap_or_else(|e| fail(&e));
+            if scenarios.is_empty() {
+                info!("No [scenario.<name>] tables in {}", path);
+            }
+            for (name, description) in scenarios {
+                println!("{:<24} {}", name, description.unwrap_or_default());
+            }
+        }
+        Some(Command::Scenario { name, run: mut args }) => {
+            args.scenario = Some(name);
+            run(args, Mode::Test);
+        }
+        Some(Command::Clean { processes }) => {
+            if !processes {
+                fail("Nothing to clean, pass --processes");
+            }
+            if reaper::reap() == 0 {
+                info!("No orphaned helper processes found");
+            }
+        }
+        Some(Command::Gen) => {
+            let config = toml::to_string_pretty(&example())
+                .expect("Failed to serialize example config");
+            fs::write(DEFAULT_CONFIG, config)
+                .expect("Failed to write example config");
+            info!("Example config written to {}", DEFAULT_CONFIG);
+        }
+        Some(Command::Multi { scenario }) => check(multi::run(&scenario)),
+        Some(Command::Attach { dir }) => check(detach::attach(&dir)),
+        Some(Command::Stop { dir }) => check(detach::stop(&dir, false)),
+        Some(Command::Kill { dir }) => check(detach::stop(&dir, true)),
+        Some(Command::SelfUpdate { version }) => {
+            if let Err(e) = version::self_update(version.as_deref()) {
+                fail(&e);
+            }
+        }
+        Some(Command::Bisect { good, bad, until, timeout, config }) => {
+            check(bisect::run(&load_config(&config), &good, &bad, &until, Duration::from_secs(timeout)));
+        }
+        Some(Command::Pool { inputs, size, timeout, config }) => {
+            check(pool::run(&load_config(&config), &inputs, size, Duration::from_secs(timeout)));
+        }
+        Some(Command::Quick { inputs, compare, parallel }) => check(quick::run(&inputs, compare, parallel)),
+        Some(Command::New { name }) => {
+            if let Err(e) = scaffold::create(&name) {
+                fail(&e);
+            }
+        }
+        Some(Command::GuestCrate { dir }) => {
+            if let Err(e) = scaffold::create_guest(&dir) {
+                fail(&e);
+            }
+        }
+        Some(Command::Dashboard { root, port }) => check(dashboard::serve(&root, port)),
+        Some(Command::Supervise { config, tags }) => check(detach::supervise(&config, &tags)),
+    }
+}
+
+fn run(mut args: RunArgs, mode: Mode) {
+    reaper::reap();
+    let filter = if args.exact && args.inputs.len() == 2 { args.inputs.pop() } else { None };
+    let libtest = args.list || args.exact;
+    let mut config = resolve(&args);
+    if libtest {
+        config.output = OutputConfig { serial: Stream::Stderr, logs: Stream::Stderr };
+    }
+    output::apply(&config.output);
+    let test_mode = mode == Mode::Test || libtest;
+    if test_mode {
+        config.test.get_or_insert_default();
+    }
+    if mode == Mode::Debug {
+        config.gdb.get_or_insert_default();
+    }
+    if mode == Mode::Build {
+        let mut ctx = Context::new(config, Arc::new(Hooks::default()), Arc::new(Control::default()));
+        ctx.dry_run = args.dry_run;
+        if let Err(e) = (Pipeline { steps: vec![Box::new(Configure), Box::new(Build)] }).run(&mut ctx) {
+            output::verdict(false, Some(&e));
+            fail(&e);
+        }
+        output::verdict(true, None);
         return;
     }
-    let config_path = args().nth(1).unwrap_or("uefapi-runner.toml".to_string());
-    info!("Loading config from {}", config_path);
-    let config = fs::read_to_string(config_path)
-        .expect("Failed to read config file");
-    let config: RunnerConfig = toml::from_str(&config)
-        .expect("Failed to parse config file");
-    info!("Config loaded: {:?}", config);
-    if !config.auto_build && config.move_binary {
-        warn!("Moving binary away but not auto-building, this may cause issues");
-    }
-    if config.auto_build {
-        info!("Building project");
-        let mut cmd = Command::new("cargo")
-            .args(config.build_cmd.split_whitespace())
-            .current_dir(config.project_path)
-            .stdout(Stdio::inherit())
-            .spawn().expect("Failed to run build command");
-        let status = cmd.wait().expect("Failed to wait for build command");
-        if !status.success() {
-            error!("Build failed");
-            return;
-        }
-        info!("Build successful");
-    }
-    let work_dir = tempfile::tempdir().expect("Failed to create temp dir");
-    let work_dir = work_dir.path();
-    let efi_boot_dir = work_dir.join("EFI/BOOT");
-    fs::create_dir_all(&efi_boot_dir)
-        .expect("Failed to create EFI/BOOT directory");
-    let efi_bin_path = efi_boot_dir.join(&config.efi_name);
-    if config.move_binary {
-        info!("Moving binary to {}", efi_bin_path.display());
-        fs::rename(&config.binary_path, &efi_bin_path)
-            .expect("Failed to move binary");
-    } else {
-        info!("Copying binary to {}", efi_bin_path.display());
-        fs::copy(&config.binary_path, &efi_bin_path)
-            .expect("Failed to copy binary");
-    }
-    let ovmf_path = PathBuf::from(&config.ovmf_path).canonicalize()
-        .expect("Failed to canonicalize OVMF path");
-    let ovmf_code_bin = ovmf_path.join("OVMF_CODE.fd");
-    let ovmf_vars_bin = ovmf_path.join("OVMF_VARS.fd");
-    if !ovmf_code_bin.exists() || !ovmf_vars_bin.exists() {
-        error!("OVMF files not found in path");
-        info!("Hint: This tool needs OVMF_CODE.fd and OVMF_VARS.fd to run");
+    if args.detach {
+        if let Err(e) = detach::spawn(&config) {
+            fail(&e);
+        }
         return;
     }
-    let mut cmd = Command::new(&config.qemu_cmd);
-    let mut cmd = cmd
-        .args(["-machine", "q35"])
-        .arg("-drive")
-        .arg(format!("if=pflash,format=raw,file={}", ovmf_code_bin.display()))
-        .arg("-drive")
-        .arg(format!("if=pflash,format=raw,file={}", ovmf_vars_bin.display()))
-        .arg("-drive")
-        .arg(format!("format=raw,file=fat:rw:{}", work_dir.display()));
-    if config.stdio_serial {
-        cmd = cmd
-            .arg("-chardev")
-            .arg(format!("{}id=char0,logfile={}", 
-                         if config.stdio_serial { "stdio," } else { "" },
-                         config.log_path))
-            .args(["-serial", "chardev:char0"]);
-    }
-    let mut child = cmd.spawn().expect("Failed to run QEMU");
-    info!("QEMU started");
-    let status = child.wait().expect("Failed to wait for QEMU");
-    info!("QEMU exited with status: {}", status);
+    let (dry_run, stats) = (args.dry_run, args.stats);
+    let mut ctx = Context::new(config, Arc::new(Hooks::default()), Arc::new(Control::default()));
+    ctx.dry_run = dry_run;
+    let gateway = ctx.config.metrics_gateway.clone();
+    let mut metrics = Metrics::default();
+    if let Err(e) = Pipeline::preparation().run(&mut ctx) {
+        error!("{}", e);
+        output::verdict(false, Some(&e));
+        write_report(&ctx.config, vec![RunReport::from_context(None, &ctx, Some(&e))]);
+        metrics.record_context(&ctx);
+        if let Some(gateway) = &gateway {
+            metrics.push(gateway, &ctx.config.artifacts_dir);
+        }
+        if test_mode {
+            exit(1);
+        }
+        return;
+    }
+    if args.list {
+        let listed = libtest::list(&mut ctx, args.ignored)
+            .and_then(|names| libtest::print_list(&names, args.format.as_deref()));
+        if let Err(e) = listed {
+            fail(&e);
+        }
+        return;
+    }
+    let mut runs = matrix::expand(&ctx.config);
+    if let Some(filter) = filter.filter(|name| name != libtest::WHOLE_BINARY && testfw::selectable(&ctx)) {
+        runs = testfw::expand(runs, &[filter]);
+    } else if test_mode && !libtest && testfw::enabled(&ctx) {
+        match testfw::list(&mut ctx) {
+            Ok(cases) => runs = testfw::expand(runs, &cases),
+            Err(e) => {
+                output::verdict(false, Some(&e));
+                fail(&e);
+            }
+        }
+    }
+    if let [(_, config)] = &runs[..] {
+        ctx.reset_run(config.clone());
+        let result = Pipeline::execution().run(&mut ctx);
+        match &result {
+            Ok(()) => match &ctx.outcome {
+                Some(outcome) => {
+                    info!("QEMU exited with {}", outcome.describe());
+                    if let Some(seed) = outcome.seed.filter(|_| !outcome.success) {
+                        info!("Reproduce with --seed {}", seed);
+                    }
+                    if let Some(fuzz) = &ctx.config.nvram_fuzz {
+                        let serial = fs::metadata(&ctx.config.log_path).map(|m| m.len()).unwrap_or(0);
+                        info!("NVRAM fuzz seed {}: QEMU {}, {} byte(s) of serial output",
+                              fuzz.seed, if outcome.success { "survived" } else { "failed" }, serial);
+                    }
+                    output::verdict(outcome.success, Some(&outcome.describe()));
+                }
+                None => output::verdict(true, None),
+            },
+            Err(e) => {
+                error!("{}", e);
+                output::verdict(false, Some(e));
+            }
+        }
+        let error = result.as_ref().err().map(String::as_str);
+        write_report(&ctx.config, vec![RunReport::from_context(None, &ctx, error)]);
+        metrics.record_context(&ctx);
+        if let Some(gateway) = &gateway {
+            metrics.push(gateway, &ctx.config.artifacts_dir);
+        }
+        if stats {
+            ctx.print_stats();
+        }
+        if libtest {
+            libtest::print_results(&ctx);
+        }
+        let code = match (&result, &ctx.outcome) {
+            (Err(_), _) => 1,
+            (Ok(()), Some(outcome)) if timed_out(outcome) => TIMEOUT_EXIT,
+            (Ok(()), Some(outcome)) if !outcome.success => 1,
+            _ => 0,
+        };
+        if code != 0 {
+            drop(ctx);
+            exit(code);
+        }
+        return;
+    }
+    let mut results = Vec::new();
+    let mut entries = Vec::new();
+    let mut reports = Vec::new();
+    for (index, (label, config)) in runs.into_iter().enumerate() {
+        if handle::interrupted() {
+            warn!("Interrupted, skipping the remaining matrix runs");
+            break;
+        }
+        info!("Matrix run {}", label);
+        ctx.reset_run(config);
+        let result = Pipeline::execution().run(&mut ctx);
+        let passed = match &result {
+            Ok(()) => match &ctx.outcome {
+                Some(outcome) => {
+                    info!("QEMU exited with {}", outcome.describe());
+                    outcome.success
+                }
+                None => true,
+            },
+            Err(e) => {
+                error!("{}", e);
+                false
+            }
+        };
+        metrics.record_context(&ctx);
+        let error = result.err();
+        if ctx.config.report_path.is_some() {
+            reports.push(RunReport::from_context(Some(&label), &ctx, error.as_deref()));
+        }
+        if ctx.config.digest.is_some() {
+            entries.push(digest::Entry::from_run(index, &label, &ctx, error.as_deref()));
+        }
+        results.push((label, passed));
+    }
+    if let Some(format) = ctx.config.digest {
+        if let Err(e) = digest::write(format, "Matrix summary", &entries, &ctx.config.artifacts_dir) {
+            warn!("{}", e);
+        }
+    }
+    write_report(&ctx.config, reports);
+    if let Some(gateway) = &gateway {
+        metrics.push(gateway, &ctx.config.artifacts_dir);
+    }
+    if stats {
+        ctx.print_stats();
+    }
+    let passed = matrix::report(&results);
+    output::verdict(passed, Some(&format!("{} of {} matrix run(s) failed",
+                                          results.iter().filter(|(_, passed)| !passed).count(), results.len())));
+    if !passed {
+        exit(1);
+    }
 }
@@ -0,0 +1,66 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use crate::input::{self, InputDevice};
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Matrix {
+    #[serde(default)]
+    pub memory: Vec<String>,
+    #[serde(default)]
+    pub cpu: Vec<String>,
+    #[serde(default)]
+    pub input: Vec<Vec<InputDevice>>,
+}
+
+type Runs = Vec<(String, RunnerConfig)>;
+
+fn dimension<T>(runs: Runs, values: &[T],
+                apply: impl Fn(&mut RunnerConfig, &T) -> String) -> Runs {
+    if values.is_empty() {
+        return runs;
+    }
+    let mut expanded = Vec::new();
+    for (label, config) in runs {
+        for value in values {
+            let mut config = config.clone();
+            let part = apply(&mut config, value);
+            let label = if label.is_empty() { part } else { format!("{} {}", label, part) };
+            expanded.push((label, config));
+        }
+    }
+    expanded
+}
+
+pub fn expand(config: &RunnerConfig) -> Runs {
+    let mut runs = vec![(String::new(), config.clone())];
+    let Some(matrix) = &config.matrix else {
+        return runs;
+    };
+    runs = dimension(runs, &matrix.memory, |config, memory| {
+        config.memory = Some(memory.clone());
+        format!("memory={}", memory)
+    });
+    runs = dimension(runs, &matrix.cpu, |config, cpu| {
+        config.cpu = Some(cpu.clone());
+        format!("cpu={}", cpu)
+    });
+    runs = dimension(runs, &matrix.input, |config, devices| {
+        config.input = Some(devices.clone());
+        format!("input={}", input::label(devices))
+    });
+    runs
+}
+
+pub fn report(results: &[(String, bool)]) -> bool {
+    info!("Matrix results:");
+    for (label, passed) in results {
+        info!("    {} {}", if *passed { "PASS" } else { "FAIL" }, label);
+    }
+    let failed = results.iter().filter(|(_, passed)| !passed).collect::<Vec<_>>();
+    if !failed.is_empty() {
+        error!("{} of {} matrix runs failed: {}", failed.len(), results.len(),
+               failed.iter().map(|(label, _)| label.as_str()).collect::<Vec<_>>().join(", "));
+    }
+    failed.is_empty()
+}
@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::outcome::{TestResult, TestStatus};
+use crate::plugins::{self, Plugin};
+use crate::RunnerConfig;
+
+const MAX_FINDINGS: usize = 50;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MemcheckConfig {
+    #[serde(default)]
+    pub ranges: Vec<String>,
+    #[serde(default)]
+    pub plugin: Option<Plugin>,
+    #[serde(default)]
+    pub mmu: bool,
+    #[serde(default)]
+    pub fail_on_findings: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: String,
+    pub address: u64,
+    #[serde(default)]
+    pub pc: Option<u64>,
+    pub in_range: bool,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MemcheckReport {
+    pub total: usize,
+    pub in_range: usize,
+    pub findings: Vec<Finding>,
+}
+
+fn hex(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    if let Some((start, len)) = range.split_once('+') {
+        let start = hex(start)?;
+        return Some((start, start.checked_add(hex(len)?)?));
+    }
+    let (start, end) = range.split_once('-')?;
+    Some((hex(start)?, hex(end)?)).filter(|(start, end)| start < end)
+}
+
+impl MemcheckConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(range) = self.ranges.iter().find(|range| parse_range(range).is_none()) {
+            return Err(format!("invalid memcheck range {}, expected 0xSTART-0xEND or 0xSTART+0xLEN", range));
+        }
+        Ok(())
+    }
+
+    pub fn log_flags(&self) -> &'static str {
+        if self.mmu { "guest_errors,unimp,int,mmu" } else { "guest_errors,unimp,int" }
+    }
+
+    fn in_range(&self, address: u64) -> bool {
+        let mut ranges = self.ranges.iter().filter_map(|range| parse_range(range)).peekable();
+        ranges.peek().is_none() || ranges.any(|(start, end)| (start..end).contains(&address))
+    }
+}
+
+struct Patterns {
+    invalid: Regex,
+    page_fault: Regex,
+    abort: Regex,
+    plugin: Regex,
+}
+
+impl Patterns {
+    fn new() -> Self {
+        let regex = |pattern| Regex::new(pattern).expect("Invalid memcheck regex");
+        Patterns {
+            invalid: regex(r"Invalid (\w+) at addr 0x([0-9A-Fa-f]+)"),
+            page_fault: regex(r"v=0e e=([0-9a-f]+) .*pc=([0-9a-f]+) .*CR2=([0-9a-f]+)"),
+            abort: regex(r"with FAR 0x([0-9a-f]+)"),
+            plugin: regex(r"^memcheck: (\S+) (?:at )?0x([0-9A-Fa-f]+)(?: pc=0x([0-9A-Fa-f]+))?"),
+        }
+    }
+}
+
+fn parse_line(patterns: &Patterns, line: &str, data_abort: &mut bool) -> Option<(String, u64, Option<u64>)> {
+    if line.contains("[Data Abort]") {
+        *data_abort = true;
+        return None;
+    }
+    if let Some(captures) = patterns.invalid.captures(line) {
+        return Some((format!("invalid-{}", &captures[1]), hex(&captures[2])?, None));
+    }
+    if let Some(captures) = patterns.page_fault.captures(line) {
+        let write = hex(&captures[1]).is_some_and(|code| code & 2 != 0);
+        let kind = if write { "page-fault-write" } else { "page-fault-read" };
+        return Some((kind.to_string(), hex(&captures[3])?, hex(&captures[2])));
+    }
+    if let Some(captures) = patterns.abort.captures(line).filter(|_| *data_abort) {
+        *data_abort = false;
+        return Some(("data-abort".to_string(), hex(&captures[1])?, None));
+    }
+    let captures = patterns.plugin.captures(line.trim())?;
+    Some((captures[1].to_string(), hex(&captures[2])?, captures.get(3).and_then(|pc| hex(pc.as_str()))))
+}
+
+pub fn collect(config: &RunnerConfig) -> Option<MemcheckReport> {
+    let memcheck = config.memcheck.as_ref()?;
+    let file = File::open(plugins::log_path(config)?).ok()?;
+    let patterns = Patterns::new();
+    let mut report = MemcheckReport::default();
+    let mut data_abort = false;
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        let Some((kind, address, pc)) = parse_line(&patterns, &line, &mut data_abort) else {
+            continue;
+        };
+        let in_range = memcheck.in_range(address);
+        report.total += 1;
+        report.in_range += in_range as usize;
+        let finding = Finding { kind, address, pc, in_range };
+        if report.findings.len() < MAX_FINDINGS {
+            report.findings.push(finding);
+        } else if let Some(slot) = report.findings.iter_mut().find(|f| in_range && !f.in_range) {
+            *slot = finding;
+        }
+    }
+    Some(report)
+}
+
+pub fn verify(config: &RunnerConfig, report: Option<&MemcheckReport>) -> Option<TestResult> {
+    let memcheck = config.memcheck.as_ref().filter(|memcheck| memcheck.fail_on_findings)?;
+    let message = match report {
+        None => Some("the QEMU log was not written".to_string()),
+        Some(report) if report.in_range > 0 => {
+            let scope = if memcheck.ranges.is_empty() { "" } else { " inside the watched ranges" };
+            Some(format!("{} suspicious memory access(es){}", report.in_range, scope))
+        }
+        Some(_) => None,
+    };
+    Some(TestResult {
+        name: "memcheck".to_string(),
+        status: if message.is_none() { TestStatus::Passed } else { TestStatus::Failed },
+        duration_ms: None,
+        message,
+    })
+}
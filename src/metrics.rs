@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use log::{info, warn};
+use crate::outcome::{ExitReason, RunOutcome};
+use crate::pipeline::Context;
+use crate::protocol;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    pub passed: u64,
+    pub failed: u64,
+    pub active: u64,
+    pub failures: BTreeMap<String, u64>,
+    pub phases: BTreeMap<&'static str, (u64, u64)>,
+    pub guest: BTreeMap<&'static str, (u64, u64)>,
+    pub last_success: Option<bool>,
+    pub last_run_ms: Option<u64>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, outcome: &RunOutcome) {
+        if outcome.success {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+            let reason = outcome.exit_reason.as_ref().map_or("exit-code", ExitReason::kind);
+            *self.failures.entry(reason.to_string()).or_default() += 1;
+        }
+        self.last_success = Some(outcome.success);
+        self.last_run_ms = outcome.timings.run_ms;
+        let timings = &outcome.timings;
+        for (phase, ms) in [("build", timings.build_ms), ("stage", timings.stage_ms),
+                            ("firmware", timings.firmware_ms), ("run", timings.run_ms)] {
+            if let Some(ms) = ms {
+                let (sum, count) = self.phases.entry(phase).or_default();
+                *sum += ms;
+                *count += 1;
+            }
+        }
+        if let Some(counters) = &outcome.counters {
+            for (counter, value) in [("instructions", Some(counters.instructions)), ("blocks", counters.blocks)] {
+                if let Some(value) = value {
+                    let (sum, count) = self.guest.entry(counter).or_default();
+                    *sum += value;
+                    *count += 1;
+                }
+            }
+        }
+    }
+
+    pub fn record_error(&mut self, step: &str) {
+        self.failed += 1;
+        *self.failures.entry(format!("{}-step", step)).or_default() += 1;
+        self.last_success = Some(false);
+        self.last_run_ms = None;
+    }
+
+    pub fn record_context(&mut self, ctx: &Context) {
+        match (&ctx.outcome, ctx.failed_step) {
+            (_, Some(step)) => self.record_error(step),
+            (Some(outcome), None) => self.record(outcome),
+            (None, None) => {}
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP uefapi_runs_total Completed runner invocations by result.\n");
+        out.push_str("# TYPE uefapi_runs_total counter\n");
+        let _ = writeln!(out, "uefapi_runs_total{{result=\"passed\"}} {}", self.passed);
+        let _ = writeln!(out, "uefapi_runs_total{{result=\"failed\"}} {}", self.failed);
+        out.push_str("# HELP uefapi_run_failures_total Failed runs by reason.\n");
+        out.push_str("# TYPE uefapi_run_failures_total counter\n");
+        for (reason, count) in &self.failures {
+            let _ = writeln!(out, "uefapi_run_failures_total{{reason=\"{}\"}} {}",
+                             reason.replace('\\', "\\\\").replace('"', "\\\""), count);
+        }
+        out.push_str("# HELP uefapi_phase_duration_milliseconds Time spent per run phase.\n");
+        out.push_str("# TYPE uefapi_phase_duration_milliseconds summary\n");
+        for (phase, (sum, count)) in &self.phases {
+            let _ = writeln!(out, "uefapi_phase_duration_milliseconds_sum{{phase=\"{}\"}} {}", phase, sum);
+            let _ = writeln!(out, "uefapi_phase_duration_milliseconds_count{{phase=\"{}\"}} {}", phase, count);
+        }
+        if !self.guest.is_empty() {
+            out.push_str("# HELP uefapi_guest_executed Guest instructions and basic blocks executed per run.\n");
+            out.push_str("# TYPE uefapi_guest_executed summary\n");
+            for (counter, (sum, count)) in &self.guest {
+                let _ = writeln!(out, "uefapi_guest_executed_sum{{counter=\"{}\"}} {}", counter, sum);
+                let _ = writeln!(out, "uefapi_guest_executed_count{{counter=\"{}\"}} {}", counter, count);
+            }
+        }
+        out.push_str("# HELP uefapi_active_runs Runs currently in progress.\n");
+        out.push_str("# TYPE uefapi_active_runs gauge\n");
+        let _ = writeln!(out, "uefapi_active_runs {}", self.active);
+        out
+    }
+
+    // The gateway keeps only what was pushed last, so a push carries gauges describing this invocation rather
+    // than counters that would start over with every process.
+    pub fn render_push(&self) -> String {
+        let mut out = String::new();
+        if let Some(success) = self.last_success {
+            out.push_str("# HELP uefapi_last_run_success Whether the last run passed.\n");
+            out.push_str("# TYPE uefapi_last_run_success gauge\n");
+            let _ = writeln!(out, "uefapi_last_run_success {}", u8::from(success));
+        }
+        let finished = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        out.push_str("# HELP uefapi_last_run_timestamp_seconds When the last run finished.\n");
+        out.push_str("# TYPE uefapi_last_run_timestamp_seconds gauge\n");
+        let _ = writeln!(out, "uefapi_last_run_timestamp_seconds {}", finished);
+        if let Some(ms) = self.last_run_ms {
+            out.push_str("# HELP uefapi_last_run_duration_seconds Guest run time of the last run.\n");
+            out.push_str("# TYPE uefapi_last_run_duration_seconds gauge\n");
+            let _ = writeln!(out, "uefapi_last_run_duration_seconds {:.3}", ms as f64 / 1000.0);
+        }
+        out.push_str("# HELP uefapi_invocation_runs Runs of the pushing invocation by result.\n");
+        out.push_str("# TYPE uefapi_invocation_runs gauge\n");
+        let _ = writeln!(out, "uefapi_invocation_runs{{result=\"passed\"}} {}", self.passed);
+        let _ = writeln!(out, "uefapi_invocation_runs{{result=\"failed\"}} {}", self.failed);
+        out
+    }
+
+    // Pushes are grouped by run, named after its artifacts dir, so projects sharing a gateway keep their own series.
+    pub fn push(&self, gateway: &str, run: &str) {
+        match push(gateway, run, &self.render_push()) {
+            Ok(()) => info!("Metrics pushed to {}", gateway),
+            Err(e) => warn!("Failed to push metrics to {}: {}", gateway, e),
+        }
+    }
+}
+
+fn push(gateway: &str, run: &str, body: &str) -> Result<(), String> {
+    let address = gateway.trim_start_matches("http://").trim_end_matches('/');
+    let (host, prefix) = address.split_once('/').map(|(host, path)| (host, format!("/{}", path)))
+        .unwrap_or((address, String::new()));
+    let mut stream = TcpStream::connect(host).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(Duration::from_secs(10))).map_err(|e| e.to_string())?;
+    let run = protocol::encode_base64(run.as_bytes()).replace('+', "-").replace('/', "_");
+    write!(stream, "PUT {}/metrics/job/uefapi-runner/run@base64/{} HTTP/1.1\r\nHost: {}\r\n\
+                    Content-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", prefix, run, host, body.len(), body)
+        .map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    let status = response.split_whitespace().nth(1).unwrap_or("");
+    if !status.starts_with('2') {
+        return Err(format!("gateway responded {}", response.lines().next().unwrap_or("nothing")));
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::Path;
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModuleKind {
+    Peim,
+    Driver,
+    Smm,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LoadedModule {
+    pub name: Option<String>,
+    pub kind: ModuleKind,
+    #[serde(with = "hex")]
+    pub base: u64,
+    #[serde(with = "hex")]
+    pub entry_point: u64,
+}
+
+mod hex {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{:x}", value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        u64::from_str_radix(text.trim_start_matches("0x"), 16).map_err(serde::de::Error::custom)
+    }
+}
+
+pub fn parse(log: &str) -> Vec<LoadedModule> {
+    let pattern = Regex::new(r"Loading (PEIM|SMM driver|driver) at 0x([0-9A-Fa-f]+) EntryPoint=0x([0-9A-Fa-f]+)(?: (\S+\.efi))?")
+        .expect("Invalid module pattern");
+    pattern.captures_iter(log).filter_map(|captures| {
+        Some(LoadedModule {
+            name: captures.get(4).map(|name| name.as_str().to_string()),
+            kind: match &captures[1] {
+                "PEIM" => ModuleKind::Peim,
+                "SMM driver" => ModuleKind::Smm,
+                _ => ModuleKind::Driver,
+            },
+            base: u64::from_str_radix(&captures[2], 16).ok()?,
+            entry_point: u64::from_str_radix(&captures[3], 16).ok()?,
+        })
+    }).collect()
+}
+
+pub fn write_map(logs: &[&Path], artifacts_dir: &Path) -> Result<(), String> {
+    let modules = logs.iter()
+        .filter_map(|log| fs::read_to_string(log).ok())
+        .flat_map(|log| parse(&log))
+        .collect::<Vec<_>>();
+    if modules.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(artifacts_dir)
+        .map_err(|e| format!("Failed to create {}: {}", artifacts_dir.display(), e))?;
+    let path = artifacts_dir.join("modules.json");
+    let json = serde_json::to_string_pretty(&modules).expect("Failed to serialize module map");
+    fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!("Wrote map of {} loaded firmware module(s) to {}", modules.len(), path.display());
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::link::{LinkConfig, LinkMode};
+use crate::pipeline::{Context, Pipeline};
+use crate::serial::LogFollower;
+use crate::load_config;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(default = "default_log_dir")]
+    pub log_dir: String,
+    pub vm: Vec<VmSpec>,
+    #[serde(default)]
+    pub link: Vec<LinkConfig>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct VmSpec {
+    pub name: String,
+    pub config: String,
+    #[serde(default)]
+    pub wait_for: Option<WaitFor>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct WaitFor {
+    pub vm: String,
+    pub serial: String,
+    #[serde(default = "default_wait_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_log_dir() -> String {
+    "uefapi-scenario-logs".to_string()
+}
+
+fn default_wait_secs() -> u64 {
+    60
+}
+
+fn vm_log(log_dir: &Path, name: &str) -> PathBuf {
+    log_dir.join(format!("{}.log", name))
+}
+
+fn wait_for(log_dir: &Path, wait: &WaitFor) -> bool {
+    let start = Instant::now();
+    let mut follower = LogFollower::new(vm_log(log_dir, &wait.vm));
+    while start.elapsed() < Duration::from_secs(wait.timeout_secs) {
+        if follower.poll().iter().any(|line| line.contains(&wait.serial)) || follower.partial().contains(&wait.serial) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    false
+}
+
+fn validate(scenario: &Scenario) -> Result<(), String> {
+    for (index, vm) in scenario.vm.iter().enumerate() {
+        if scenario.vm[..index].iter().any(|other| other.name == vm.name) {
+            return Err(format!("VM name {} is used more than once", vm.name));
+        }
+        if let Some(wait) = &vm.wait_for {
+            if !scenario.vm.iter().any(|other| other.name == wait.vm) || wait.vm == vm.name {
+                return Err(format!("VM {} waits for unknown VM {}", vm.name, wait.vm));
+            }
+        }
+    }
+    let names = scenario.vm.iter().map(|vm| vm.name.clone()).collect::<Vec<_>>();
+    for link in &scenario.link {
+        link.validate(&names)?;
+    }
+    Ok(())
+}
+
+pub fn run(path: &str) -> bool {
+    info!("Loading scenario from {}", path);
+    let scenario = fs::read_to_string(path)
+        .expect("Failed to read scenario file");
+    let scenario: Scenario = toml::from_str(&scenario)
+        .expect("Failed to parse scenario file");
+    if let Err(e) = validate(&scenario) {
+        error!("Invalid scenario: {}", e);
+        return false;
+    }
+    let log_dir = PathBuf::from(&scenario.log_dir);
+    fs::create_dir_all(&log_dir).expect("Failed to create scenario log dir");
+    let mut vms = Vec::new();
+    for vm in &scenario.vm {
+        let mut config = load_config(&vm.config);
+        config.stdio_serial = false;
+        config.log_serial = true;
+        config.log_path = vm_log(&log_dir, &vm.name).display().to_string();
+        let _ = fs::remove_file(&config.log_path);
+        let mut ctx = Context::new(config, Arc::new(Hooks::default()), Arc::new(Control::default()));
+        match Pipeline::preparation().run(&mut ctx) {
+            Ok(()) => vms.push((vm.clone(), ctx)),
+            Err(e) => {
+                error!("Failed to prepare VM {}: {}", vm.name, e);
+                return false;
+            }
+        }
+    }
+    let mut link_args = BTreeMap::new();
+    for (index, link) in scenario.link.iter().enumerate() {
+        if let Err(e) = link.setup(index, &mut link_args) {
+            error!("Failed to set up link {}: {}", link.name, e);
+            return false;
+        }
+    }
+    let connecting = scenario.link.iter()
+        .filter(|link| link.mode == LinkMode::Stream)
+        .map(|link| link.vms[1].clone())
+        .collect::<Vec<_>>();
+    let mut handles = Vec::new();
+    for (vm, mut ctx) in vms {
+        let log_dir = log_dir.clone();
+        ctx.extra_args = link_args.remove(&vm.name).unwrap_or_default();
+        let delayed = connecting.contains(&vm.name) && vm.wait_for.is_none();
+        handles.push(thread::spawn(move || {
+            if let Some(wait) = &vm.wait_for {
+                info!("VM {} waiting for {:?} from {}", vm.name, wait.serial, wait.vm);
+                if !wait_for(&log_dir, wait) {
+                    error!("VM {} timed out waiting for {}", vm.name, wait.vm);
+                    return (vm.name, false);
+                }
+            }
+            if delayed {
+                thread::sleep(Duration::from_secs(1));
+            }
+            info!("Starting VM {}", vm.name);
+            let passed = match Pipeline::execution().run(&mut ctx) {
+                Ok(()) => {
+                    let outcome = ctx.outcome.expect("Execution produced no outcome");
+                    info!("VM {} exited with {}", vm.name, outcome.describe());
+                    outcome.success
+                }
+                Err(e) => {
+                    error!("VM {}: {}", vm.name, e);
+                    false
+                }
+            };
+            (vm.name, passed)
+        }));
+    }
+    let mut followers = scenario.vm.iter()
+        .map(|vm| (vm.name.clone(), LogFollower::new(vm_log(&log_dir, &vm.name))))
+        .collect::<Vec<_>>();
+    let mut combined = File::create(log_dir.join("combined.log"))
+        .expect("Failed to create combined log");
+    loop {
+        let finished = handles.iter().all(|handle| handle.is_finished());
+        for (name, follower) in &mut followers {
+            for line in follower.poll() {
+                println!("[{}] {}", name, line);
+                if let Err(e) = writeln!(combined, "[{}] {}", name, line) {
+                    warn!("Failed to write combined log: {}", e);
+                }
+            }
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let results = handles.into_iter()
+        .map(|handle| handle.join().expect("VM thread panicked"))
+        .collect::<Vec<_>>();
+    info!("Scenario results (logs in {}):", log_dir.display());
+    for (name, passed) in &results {
+        info!("    {} {}", if *passed { "PASS" } else { "FAIL" }, name);
+    }
+    results.iter().all(|(_, passed)| *passed)
+}
@@ -0,0 +1,163 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use crate::privilege::{self, Capability, Escalation};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    #[serde(default = "default_nic_model")]
+    pub nic_model: String,
+    #[serde(default)]
+    pub tap: Option<TapConfig>,
+    #[serde(default)]
+    pub pcap: Option<String>,
+    #[serde(default)]
+    pub hostfwd: Vec<String>,
+    #[serde(default)]
+    pub tftp: Option<String>,
+    #[serde(default)]
+    pub bootfile: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TapConfig {
+    pub bridge: String,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+fn default_nic_model() -> String {
+    "virtio-net-pci".to_string()
+}
+
+pub struct TapDevice {
+    pub name: String,
+    escalation: Escalation,
+}
+
+impl NetworkConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tap.is_some() && (!self.hostfwd.is_empty() || self.tftp.is_some() || self.bootfile.is_some()) {
+            return Err("network hostfwd, tftp and bootfile need user networking and conflict with tap".to_string());
+        }
+        let valid = |rule: &str| rule.split_once(':').is_some_and(|(protocol, ports)| {
+            matches!(protocol, "tcp" | "udp") && ports.split_once('-').is_some_and(|(host, guest)| {
+                [host, guest].iter().all(|side| side.rsplit(':').next().is_some_and(|port| port.parse::<u16>().is_ok()))
+            })
+        });
+        if let Some(rule) = self.hostfwd.iter().find(|rule| !valid(rule)) {
+            return Err(format!("invalid network hostfwd {}, expected tcp:[HOSTADDR]:PORT-[GUESTADDR]:PORT", rule));
+        }
+        if let Some(dir) = self.tftp.as_ref().filter(|dir| !Path::new(dir).is_dir()) {
+            return Err(format!("network tftp directory {} does not exist", dir));
+        }
+        let url = self.bootfile.as_ref().is_some_and(|file| file.contains("://"));
+        if self.bootfile.is_some() && self.tftp.is_none() && !url {
+            return Err("network bootfile needs a tftp directory unless it is an http:// URL".to_string());
+        }
+        Ok(())
+    }
+
+    fn user_netdev(&self) -> String {
+        let mut netdev = "user,id=net0".to_string();
+        for rule in &self.hostfwd {
+            netdev.push_str(&format!(",hostfwd={}", rule));
+        }
+        if let Some(dir) = &self.tftp {
+            netdev.push_str(&format!(",tftp={}", dir));
+        }
+        if let Some(file) = &self.bootfile {
+            netdev.push_str(&format!(",bootfile={}", file));
+        }
+        netdev
+    }
+}
+
+impl TapConfig {
+    pub fn device_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("uefapi{}", std::process::id()))
+    }
+}
+
+impl TapDevice {
+    pub fn create(config: &TapConfig, escalation: Escalation) -> Result<TapDevice, String> {
+        let name = config.device_name();
+        let user = env::var("USER").unwrap_or_else(|_| "root".to_string());
+        info!("Creating tap device {} on bridge {}", name, config.bridge);
+        ip(&format!("Creating tap device {}", name), escalation,
+           &["tuntap", "add", "dev", &name, "mode", "tap", "user", &user])?;
+        let device = TapDevice { name, escalation };
+        ip(&format!("Attaching {} to {}", device.name, config.bridge), escalation,
+           &["link", "set", &device.name, "master", &config.bridge])?;
+        ip(&format!("Bringing up {}", device.name), escalation,
+           &["link", "set", &device.name, "up"])?;
+        Ok(device)
+    }
+}
+
+impl Drop for TapDevice {
+    fn drop(&mut self) {
+        info!("Removing tap device {}", self.name);
+        let step = format!("Removing tap device {}", self.name);
+        if let Err(e) = ip(&step, self.escalation, &["link", "del", &self.name]) {
+            error!("Failed to remove tap device {}: {}", self.name, e);
+        }
+    }
+}
+
+// Unprivileged PATHs often leave out sbin, where the setcap advice points.
+const IP_FALLBACKS: &[&str] = &["/usr/sbin/ip", "/sbin/ip", "/usr/bin/ip", "/bin/ip"];
+
+fn ip(step: &str, escalation: Escalation, args: &[&str]) -> Result<(), String> {
+    let program = privilege::which("ip")
+        .or_else(|| IP_FALLBACKS.iter().map(PathBuf::from).find(|path| path.is_file()))
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|| "ip".to_string());
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    privilege::run_helper(step, &program, &args, Capability::NetAdmin, escalation)
+}
+
+pub struct Network {
+    pub args: Vec<String>,
+    pub tap: Option<TapDevice>,
+}
+
+pub fn setup(config: &NetworkConfig, escalation: Escalation, artifacts_dir: &str,
+             dry_run: bool) -> Result<Network, String> {
+    let mut args = Vec::new();
+    let mut tap = None;
+    if let Some(tap_config) = &config.tap {
+        let name = if dry_run {
+            info!("Would create tap device {} on bridge {}", tap_config.device_name(), tap_config.bridge);
+            tap_config.device_name()
+        } else {
+            let device = TapDevice::create(tap_config, escalation)?;
+            let name = device.name.clone();
+            tap = Some(device);
+            name
+        };
+        args.push("-netdev".to_string());
+        args.push(format!("tap,id=net0,ifname={},script=no,downscript=no", name));
+    } else {
+        if let Some(dir) = &config.tftp {
+            info!("Serving {} over TFTP{}", dir,
+                  config.bootfile.as_deref().map(|file| format!(", boot file {}", file)).unwrap_or_default());
+        }
+        args.push("-netdev".to_string());
+        args.push(config.user_netdev());
+    }
+    args.push("-device".to_string());
+    args.push(format!("{},netdev=net0", config.nic_model));
+    if let Some(pcap) = &config.pcap {
+        let pcap = Path::new(artifacts_dir).join(pcap);
+        if let Some(dir) = pcap.parent().filter(|_| !dry_run) {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        info!("Capturing guest network traffic to {}", pcap.display());
+        args.push("-object".to_string());
+        args.push(format!("filter-dump,id=dump0,netdev=net0,file={}", pcap.display()));
+    }
+    Ok(Network { args, tap })
+}
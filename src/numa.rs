@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use crate::limits::size_bytes;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NumaConfig {
+    #[serde(default)]
+    pub nodes: Vec<NumaNode>,
+    #[serde(default)]
+    pub distances: Vec<NumaDistance>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NumaNode {
+    pub memory: String,
+    #[serde(default)]
+    pub cpus: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NumaDistance {
+    pub src: u32,
+    pub dst: u32,
+    pub value: u32,
+}
+
+impl NumaConfig {
+    pub fn validate(&self, memory: Option<&str>) -> Result<(), String> {
+        let nodes = self.nodes.len() as u32;
+        if nodes > 0 {
+            let memory = memory.ok_or("NUMA nodes need memory set to the sum of their sizes")?;
+            let total = size_bytes(memory).ok_or_else(|| format!("memory {} is not a size NUMA can check", memory))?;
+            let mut sum = 0;
+            for node in &self.nodes {
+                sum += size_bytes(&node.memory)
+                    .ok_or_else(|| format!("NUMA node memory {} is not a size like 512M", node.memory))?;
+            }
+            if sum != total {
+                return Err(format!("NUMA node memory adds up to {} bytes but memory {} is {} bytes",
+                                   sum, memory, total));
+            }
+        }
+        for distance in &self.distances {
+            if distance.src >= nodes || distance.dst >= nodes {
+                return Err(format!("NUMA distance {}->{} refers to an undefined node",
+                                   distance.src, distance.dst));
+            }
+            if distance.src == distance.dst && distance.value != 10 {
+                return Err(format!("NUMA distance of node {} to itself must be 10", distance.src));
+            }
+            if distance.src != distance.dst && distance.value <= 10 {
+                return Err(format!("NUMA distance {}->{} must be greater than 10",
+                                   distance.src, distance.dst));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            args.push("-object".to_string());
+            args.push(format!("memory-backend-ram,id=numa-mem{},size={}", index, node.memory));
+            let mut spec = format!("node,nodeid={},memdev=numa-mem{}", index, index);
+            if let Some(cpus) = &node.cpus {
+                spec.push_str(&format!(",cpus={}", cpus));
+            }
+            args.push("-numa".to_string());
+            args.push(spec);
+        }
+        for distance in &self.distances {
+            args.push("-numa".to_string());
+            args.push(format!("dist,src={},dst={},val={}", distance.src, distance.dst, distance.value));
+        }
+        args
+    }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::rng::SplitMix64;
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FuzzMode {
+    #[default]
+    Corrupt,
+    Truncate,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NvramFuzz {
+    pub seed: u64,
+    #[serde(default)]
+    pub mode: FuzzMode,
+    #[serde(default = "default_bytes")]
+    pub bytes: u32,
+}
+
+fn default_bytes() -> u32 {
+    16
+}
+
+impl NvramFuzz {
+    pub fn apply(&self, vars: &Path, run_dir: &Path) -> io::Result<PathBuf> {
+        let mut image = fs::read(vars)?;
+        if image.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "OVMF_VARS image is empty"));
+        }
+        let mut rng = SplitMix64::new(self.seed);
+        match self.mode {
+            FuzzMode::Corrupt => {
+                for _ in 0..self.bytes {
+                    let offset = rng.below(image.len() as u64) as usize;
+                    let flip = (rng.next_u64() as u8).max(1);
+                    image[offset] ^= flip;
+                }
+                info!("Corrupted {} byte(s) of {} with seed {}", self.bytes, vars.display(), self.seed);
+            }
+            FuzzMode::Truncate => {
+                let length = rng.below(image.len() as u64) as usize;
+                image.truncate(length);
+                info!("Truncated {} to {} byte(s) with seed {}", vars.display(), length, self.seed);
+            }
+        }
+        let fuzzed = run_dir.join("OVMF_VARS.fuzzed.fd");
+        fs::write(&fuzzed, image)?;
+        Ok(fuzzed)
+    }
+}
+
+pub fn persistent_path(config: &RunnerConfig, template: &Path) -> PathBuf {
+    match &config.nvram_path {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(&config.artifacts_dir).join("nvram").join(template.file_name().unwrap_or_default()),
+    }
+}
+
+pub fn writable_copy(template: &Path, copy: &Path) -> io::Result<()> {
+    if let Some(parent) = copy.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(copy, fs::read(template)?)
+}
+
+pub fn prepare(config: &RunnerConfig, template: &Path, run_dir: &Path, dry_run: bool) -> io::Result<PathBuf> {
+    let vars = if config.persist_nvram {
+        persistent_path(config, template)
+    } else {
+        run_dir.join(template.file_name().unwrap_or_default())
+    };
+    if dry_run {
+        info!("[dry-run] would use NVRAM {} copied from {}", vars.display(), template.display());
+    } else if config.persist_nvram && vars.exists() {
+        info!("Reusing persistent NVRAM {}", vars.display());
+    } else {
+        writable_copy(template, &vars)?;
+        info!("Copied {} to {}", template.display(), vars.display());
+    }
+    Ok(vars)
+}
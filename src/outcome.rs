@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::counters::GuestCounters;
+use crate::efi_status::EfiStatus;
+use crate::memcheck::MemcheckReport;
+use crate::provenance::Provenance;
+use crate::retention::Retention;
+
+/// Version of the serialized result model, bumped only on breaking changes.
+pub const SCHEMA_VERSION: u32 = 1;
+pub const HISTORY_DIR: &str = "runs";
+// Run records kept when no retention is configured, so the history does not grow without bound.
+const HISTORY_KEEP: usize = 50;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Skipped,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TestResult {
+    pub name: String,
+    pub status: TestStatus,
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    #[serde(default)]
+    pub build_ms: Option<u64>,
+    #[serde(default)]
+    pub stage_ms: Option<u64>,
+    #[serde(default)]
+    pub firmware_ms: Option<u64>,
+    #[serde(default)]
+    pub run_ms: Option<u64>,
+    #[serde(default)]
+    pub boot_phases: Vec<BootPhase>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BootPhase {
+    pub name: String,
+    pub start_ms: u64,
+    pub duration_ms: u64,
+}
+
+pub fn millis(duration: Duration) -> Option<u64> {
+    Some(duration.as_millis() as u64)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "kind")]
+pub enum ExitReason {
+    GuestShutdown,
+    GuestReset,
+    GuestPanic,
+    Watchdog { action: String },
+    HostRequest { reason: String },
+    ProcessExit,
+    Killed,
+    Timeout { secs: u64 },
+    Idle { secs: u64 },
+}
+
+impl ExitReason {
+    // The serialized kind, a bounded label value unlike describe().
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExitReason::GuestShutdown => "guest-shutdown",
+            ExitReason::GuestReset => "guest-reset",
+            ExitReason::GuestPanic => "guest-panic",
+            ExitReason::Watchdog { .. } => "watchdog",
+            ExitReason::HostRequest { .. } => "host-request",
+            ExitReason::ProcessExit => "process-exit",
+            ExitReason::Killed => "killed",
+            ExitReason::Timeout { .. } => "timeout",
+            ExitReason::Idle { .. } => "idle",
+        }
+    }
+
+    pub fn classify(events: &[Value], status: ExitStatus) -> ExitReason {
+        let event = |name: &str| events.iter().rev().find(|event| event["event"] == name);
+        if event("GUEST_PANICKED").is_some() {
+            return ExitReason::GuestPanic;
+        }
+        if let Some(watchdog) = event("WATCHDOG") {
+            let action = watchdog["data"]["action"].as_str().unwrap_or("unknown");
+            if action != "none" && action != "debug" {
+                return ExitReason::Watchdog { action: action.to_string() };
+            }
+        }
+        if let Some(shutdown) = event("SHUTDOWN") {
+            return match shutdown["data"]["reason"].as_str().unwrap_or_default() {
+                "guest-shutdown" => ExitReason::GuestShutdown,
+                "guest-reset" => ExitReason::GuestReset,
+                "guest-panic" => ExitReason::GuestPanic,
+                reason => ExitReason::HostRequest { reason: reason.to_string() },
+            };
+        }
+        match status.code() {
+            Some(_) => ExitReason::ProcessExit,
+            None => ExitReason::Killed,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ExitReason::Timeout { .. } | ExitReason::Idle { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            ExitReason::GuestShutdown => "guest shutdown".to_string(),
+            ExitReason::GuestReset => "guest reset".to_string(),
+            ExitReason::GuestPanic => "guest panic".to_string(),
+            ExitReason::Watchdog { action } => format!("watchdog fired ({})", action),
+            ExitReason::HostRequest { reason } => format!("host request ({})", reason),
+            ExitReason::ProcessExit => "QEMU process exit".to_string(),
+            ExitReason::Killed => "QEMU killed".to_string(),
+            ExitReason::Timeout { secs } => format!("run timeout ({}s)", secs),
+            ExitReason::Idle { secs } => format!("no serial output for {}s", secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RunOutcome {
+    pub schema_version: u32,
+    pub success: bool,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub tests: Vec<TestResult>,
+    #[serde(default)]
+    pub timings: PhaseTimings,
+    #[serde(default)]
+    pub efi_status: Option<EfiStatus>,
+    #[serde(default)]
+    pub exit_reason: Option<ExitReason>,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub provenance: Option<Box<Provenance>>,
+    #[serde(default)]
+    pub counters: Option<GuestCounters>,
+    #[serde(default)]
+    pub memcheck: Option<MemcheckReport>,
+}
+
+impl RunOutcome {
+    pub fn describe(&self) -> String {
+        let status = match self.exit_code {
+            Some(code) => format!("exit code {}", code),
+            None => "terminated by signal".to_string(),
+        };
+        match &self.exit_reason {
+            Some(reason) => format!("{} after {}", status, reason.describe()),
+            None => status,
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), String> {
+        let path = dir.join("outcome.json");
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize outcome: {}", e))?;
+        fs::create_dir_all(dir).and_then(|_| fs::write(&path, json))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    // outcome.json in the artifacts dir only describes the latest run, every run also leaves a record under runs/.
+    pub fn archive(&self, dir: &Path, serial_log: Option<&Path>, retention: &Retention) -> Result<PathBuf, String> {
+        let history = dir.join(HISTORY_DIR);
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+        let record = history.join(format!("{}-{}", stamp, process::id()));
+        self.write(&record)?;
+        if let Some(log) = serial_log.filter(|log| log.exists()) {
+            fs::copy(log, record.join("serial.log")).map_err(|e| format!("Failed to archive serial log: {}", e))?;
+        }
+        if retention.is_unlimited() {
+            Retention { keep: Some(HISTORY_KEEP), max_age_days: None }.prune(&history);
+        } else {
+            retention.prune(&history);
+        }
+        Ok(record)
+    }
+}
+
+impl From<ExitStatus> for RunOutcome {
+    fn from(status: ExitStatus) -> Self {
+        RunOutcome {
+            schema_version: SCHEMA_VERSION,
+            success: status.success(),
+            exit_code: status.code(),
+            tests: Vec::new(),
+            timings: PhaseTimings::default(),
+            efi_status: None,
+            exit_reason: None,
+            seed: None,
+            tags: Vec::new(),
+            provenance: None,
+            counters: None,
+            memcheck: None,
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+static LOGS_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn stdio(self) -> Stdio {
+        match self {
+            Stream::Stdout => io::stdout().into(),
+            Stream::Stderr => io::stderr().into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OutputConfig {
+    #[serde(default = "default_serial")]
+    pub serial: Stream,
+    #[serde(default = "default_logs")]
+    pub logs: Stream,
+}
+
+fn default_serial() -> Stream {
+    Stream::Stdout
+}
+
+fn default_logs() -> Stream {
+    Stream::Stderr
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig { serial: default_serial(), logs: default_logs() }
+    }
+}
+
+struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match logs() {
+            Stream::Stdout => io::stdout().lock().write(buf),
+            Stream::Stderr => io::stderr().lock().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match logs() {
+            Stream::Stdout => io::stdout().flush(),
+            Stream::Stderr => io::stderr().flush(),
+        }
+    }
+}
+
+pub fn init_logger() {
+    env_logger::Builder::from_default_env()
+        .target(env_logger::Target::Pipe(Box::new(LogWriter)))
+        .init();
+}
+
+pub fn apply(config: &OutputConfig) {
+    LOGS_TO_STDOUT.store(config.logs == Stream::Stdout, Ordering::Relaxed);
+}
+
+pub fn logs() -> Stream {
+    if LOGS_TO_STDOUT.load(Ordering::Relaxed) { Stream::Stdout } else { Stream::Stderr }
+}
+
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+    log::set_max_level(LevelFilter::Off);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn verdict(passed: bool, detail: Option<&str>) {
+    if !quiet() {
+        return;
+    }
+    let line = match detail {
+        Some(detail) if !passed => format!("FAIL: {}", detail),
+        _ => (if passed { "PASS" } else { "FAIL" }).to_string(),
+    };
+    write_line(&line);
+}
+
+pub fn runner_line(line: &str) {
+    if !quiet() {
+        write_line(line);
+    }
+}
+
+fn write_line(line: &str) {
+    match logs() {
+        Stream::Stdout => println!("{}", line),
+        Stream::Stderr => eprintln!("{}", line),
+    }
+}
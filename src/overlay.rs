@@ -0,0 +1,111 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env::consts::EXE_SUFFIX;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::info;
+use crate::disk::DiskSource;
+use crate::RunnerConfig;
+
+const BACKING_FORMATS: &[&str] = &["raw", "qcow2"];
+
+pub fn qemu_img(config: &RunnerConfig) -> PathBuf {
+    let name = format!("qemu-img{}", EXE_SUFFIX);
+    match Path::new(&config.qemu_cmd).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+fn image_format(path: &str) -> String {
+    match Path::new(path).extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase()).as_deref() {
+        Some("qcow2") => "qcow2".to_string(),
+        Some("vmdk") => "vmdk".to_string(),
+        Some("vhdx") => "vhdx".to_string(),
+        Some("vdi") => "vdi".to_string(),
+        Some("vhd") => "vpc".to_string(),
+        _ => "raw".to_string(),
+    }
+}
+
+fn qemu_img_run(qemu_img: &Path, args: &[&str]) -> Result<(), String> {
+    let output = Command::new(qemu_img).args(args).output()
+        .map_err(|e| format!("Failed to run {}: {}", qemu_img.display(), e))?;
+    if !output.status.success() {
+        return Err(format!("qemu-img {} failed: {}", args[0], String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+fn cached_base(qemu_img: &Path, cache: &Path, base: &Path, format: &str) -> Result<PathBuf, String> {
+    let metadata = fs::metadata(base).map_err(|e| format!("Failed to read base image {}: {}", base.display(), e))?;
+    let mut hasher = DefaultHasher::new();
+    (base, metadata.len(), metadata.modified().ok()).hash(&mut hasher);
+    let converted = cache.join(format!("{:016x}.qcow2", hasher.finish()));
+    if converted.exists() {
+        info!("Using cached qcow2 conversion of {}", base.display());
+        return Ok(converted);
+    }
+    fs::create_dir_all(cache).map_err(|e| format!("Failed to create {}: {}", cache.display(), e))?;
+    info!("Converting {} base image {} to qcow2", format, base.display());
+    let partial = converted.with_extension("partial");
+    qemu_img_run(qemu_img, &["convert", "-f", format, "-O", "qcow2",
+                             &base.display().to_string(), &partial.display().to_string()])?;
+    fs::rename(&partial, &converted).map_err(|e| format!("Failed to cache {}: {}", converted.display(), e))?;
+    Ok(converted)
+}
+
+fn create(qemu_img: &Path, cache: &Path, base: &str, format: &str, overlay: &Path) -> Result<(), String> {
+    let base = fs::canonicalize(base).map_err(|e| format!("Failed to resolve base image {}: {}", base, e))?;
+    let (backing, format) = if BACKING_FORMATS.contains(&format) {
+        (base, format)
+    } else {
+        (cached_base(qemu_img, cache, &base, format)?, "qcow2")
+    };
+    qemu_img_run(qemu_img, &["create", "-q", "-f", "qcow2", "-F", format,
+                             "-b", &backing.display().to_string(), &overlay.display().to_string()])?;
+    info!("Overlay {} on top of {}", overlay.display(), backing.display());
+    Ok(())
+}
+
+pub fn apply(config: &RunnerConfig, dry_run: bool) -> Result<RunnerConfig, String> {
+    let mut config = config.clone();
+    if !config.base_overlays {
+        return Ok(config);
+    }
+    let root = PathBuf::from(&config.artifacts_dir).join("overlays");
+    let (runs, cache) = (root.join("runs"), root.join("cache"));
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let dir = runs.join(format!("{}-{}", stamp, process::id()));
+    if !dry_run {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let pruned = config.retention.prune(&runs) + config.retention.prune(&cache);
+        if pruned > 0 {
+            info!("Pruned {} old overlay(s)", pruned);
+        }
+    }
+    let qemu_img = qemu_img(&config);
+    let overlay = |base: &str, format: &str, name: &str| -> Result<String, String> {
+        let path = dir.join(format!("{}.qcow2", name));
+        if dry_run {
+            info!("[dry-run] would overlay {} at {}", base, path.display());
+        } else {
+            create(&qemu_img, &cache, base, format, &path)?;
+        }
+        Ok(path.display().to_string())
+    };
+    if let Some(image) = config.boot_image.clone().filter(|image| !image.to_ascii_lowercase().ends_with(".iso")) {
+        config.boot_image = Some(overlay(&image, &image_format(&image), "boot")?);
+    }
+    for (index, disk) in config.disks.iter_mut().enumerate().filter(|(_, disk)| !disk.readonly) {
+        let DiskSource::File { path } = &mut disk.source else {
+            continue;
+        };
+        let name = disk.id.clone().unwrap_or_else(|| format!("disk{}", index));
+        *path = overlay(path, disk.format.as_deref().unwrap_or("raw"), &name)?;
+        disk.format = Some("qcow2".to_string());
+    }
+    Ok(config)
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::qmp;
+
+const BACKENDS: &[&str] = &[
+    "null", "socket", "udp", "file", "pipe", "pty", "stdio", "serial", "tty", "parallel", "parport", "ringbuf",
+    "vc", "msmouse", "braille", "testdev", "spicevmc", "spiceport", "qemu-vdagent", "dbus", "hub",
+];
+const RESERVED_IDS: &[&str] = &["char0", "chrtpm", "tpm0", "net0", "dump0"];
+const AUTO_PORT: &str = "auto";
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ChardevConfig {
+    pub id: String,
+    pub backend: String,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+    #[serde(default)]
+    pub log: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    pub driver: String,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub chardev: Option<String>,
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+fn valid_id(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_alphabetic())
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+fn escape(value: &str) -> String {
+    value.replace(',', ",,")
+}
+
+fn check_options(what: &str, options: &BTreeMap<String, String>, reserved: &[&str]) -> Result<(), String> {
+    if let Some(key) = options.keys().find(|key| !valid_key(key)) {
+        return Err(format!("{} has an invalid option name {:?}", what, key));
+    }
+    if let Some(key) = options.keys().find(|key| reserved.contains(&key.as_str())) {
+        return Err(format!("{} sets {} in options, use the {} field instead", what, key, key));
+    }
+    Ok(())
+}
+
+pub fn validate(chardevs: &[ChardevConfig], devices: &[DeviceConfig]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for chardev in chardevs {
+        let what = format!("chardev {}", chardev.id);
+        if !valid_id(&chardev.id) {
+            return Err(format!("invalid chardev id {:?}, start with a letter and use letters, digits, -, _ and .",
+                               chardev.id));
+        }
+        if RESERVED_IDS.contains(&chardev.id.as_str()) {
+            return Err(format!("{} clashes with a chardev the runner creates", what));
+        }
+        if !ids.insert(chardev.id.as_str()) {
+            return Err(format!("{} is defined twice", what));
+        }
+        if !BACKENDS.contains(&chardev.backend.as_str()) {
+            return Err(format!("{} has unknown backend {}, expected one of {}", what, chardev.backend,
+                               BACKENDS.join(", ")));
+        }
+        check_options(&what, &chardev.options, &["id", "backend"])?;
+        if chardev.log && chardev.options.contains_key("logfile") {
+            return Err(format!("{} sets both log and a logfile option", what));
+        }
+        let port = chardev.options.get("port").map(String::as_str);
+        if port == Some(AUTO_PORT) && !matches!(chardev.backend.as_str(), "socket" | "udp") {
+            return Err(format!("{} asks for an automatic port, which only socket and udp backends have", what));
+        }
+        if let Some(port) = port.filter(|port| *port != AUTO_PORT && port.parse::<u16>().is_err()) {
+            return Err(format!("{} has invalid port {}, expected a number or \"auto\"", what, port));
+        }
+    }
+    let mut device_ids = HashSet::new();
+    for device in devices {
+        let what = format!("device {}", device.id.as_deref().unwrap_or(&device.driver));
+        if !valid_key(&device.driver) {
+            return Err(format!("invalid device driver {:?}", device.driver));
+        }
+        if let Some(id) = &device.id {
+            if !valid_id(id) {
+                return Err(format!("invalid device id {:?}, start with a letter and use letters, digits, -, _ and .",
+                                   id));
+            }
+            if !device_ids.insert(id.as_str()) {
+                return Err(format!("{} is defined twice", what));
+            }
+        }
+        if let Some(chardev) = device.chardev.as_ref().filter(|chardev| !ids.contains(chardev.as_str())) {
+            return Err(format!("{} uses chardev {}, which no [[chardev]] table defines", what, chardev));
+        }
+        check_options(&what, &device.options, &["id", "chardev", "driver"])?;
+    }
+    Ok(())
+}
+
+pub fn qemu_args(chardevs: &[ChardevConfig], devices: &[DeviceConfig],
+                 artifacts_dir: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    for chardev in chardevs {
+        let mut spec = format!("{},id={}", chardev.backend, chardev.id);
+        for (key, value) in &chardev.options {
+            let value = if key == "port" && value == AUTO_PORT {
+                let port = qmp::allocate_port()
+                    .map_err(|e| format!("Failed to allocate a port for chardev {}: {}", chardev.id, e))?;
+                info!("Chardev {} uses port {}", chardev.id, port);
+                port.to_string()
+            } else {
+                value.clone()
+            };
+            spec.push_str(&format!(",{}={}", key, escape(&value)));
+        }
+        if chardev.log {
+            fs::create_dir_all(artifacts_dir).map_err(|e| format!("Failed to create {}: {}", artifacts_dir, e))?;
+            let log = Path::new(artifacts_dir).join(format!("chardev-{}.log", chardev.id));
+            spec.push_str(&format!(",logfile={}", escape(&log.display().to_string())));
+        }
+        args.push("-chardev".to_string());
+        args.push(spec);
+    }
+    for device in devices {
+        let mut spec = device.driver.clone();
+        if let Some(id) = &device.id {
+            spec.push_str(&format!(",id={}", id));
+        }
+        if let Some(chardev) = &device.chardev {
+            spec.push_str(&format!(",chardev={}", chardev));
+        }
+        for (key, value) in &device.options {
+            spec.push_str(&format!(",{}={}", key, escape(value)));
+        }
+        args.push("-device".to_string());
+        args.push(spec);
+    }
+    Ok(args)
+}
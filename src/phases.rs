@@ -0,0 +1,64 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::modules::{self, ModuleKind};
+use crate::outcome::BootPhase;
+use crate::serial::LogFollower;
+
+const POLL: Duration = Duration::from_millis(20);
+const MARKERS: &[(&str, &[&str])] = &[
+    ("sec", &["SecCoreStartupWithStack", "SEC:"]),
+    ("pei", &["Install PPI:", "Register PPI Notify:", "Loading PEIM"]),
+    ("dxe", &["DXE IPL Entry", "Loading DXE CORE"]),
+    ("bds", &["[Bds]", "BdsDxe:"]),
+];
+const BOOT_MARKERS: &[&str] = &["[Bds]Booting", "BdsDxe: starting Boot", "BdsDxe: loading Boot"];
+
+fn phase(line: &str, images: &[String]) -> Option<&'static str> {
+    let app = line.contains("Loading driver at") && modules::parse(line).iter().any(|module| {
+        module.kind == ModuleKind::Driver
+            && module.name.as_ref().is_some_and(|name| images.iter().any(|image| name.eq_ignore_ascii_case(image)))
+    });
+    if app {
+        return Some("app");
+    }
+    if BOOT_MARKERS.iter().any(|marker| line.contains(marker)) {
+        return Some("boot");
+    }
+    MARKERS.iter().find(|(_, markers)| markers.iter().any(|marker| line.contains(marker))).map(|(name, _)| *name)
+}
+
+fn order(name: &str) -> usize {
+    ["sec", "pei", "dxe", "bds", "boot", "app"].iter().position(|phase| *phase == name).unwrap_or(0)
+}
+
+pub fn watch(log: &Path, images: &[String], started: Instant, done: &AtomicBool) -> Vec<BootPhase> {
+    let mut follower = LogFollower::new(log);
+    let mut starts: Vec<(&'static str, Duration)> = Vec::new();
+    loop {
+        let finished = done.load(Ordering::Acquire);
+        let now = started.elapsed();
+        for line in follower.poll() {
+            let Some(name) = phase(&line, images) else {
+                continue;
+            };
+            if starts.last().is_none_or(|(last, _)| order(name) > order(last)) {
+                starts.push((name, now));
+            }
+        }
+        if finished {
+            break;
+        }
+        thread::sleep(POLL);
+    }
+    let end = started.elapsed();
+    starts.iter().enumerate().map(|(index, (name, start))| {
+        let next = starts.get(index + 1).map_or(end, |(_, next)| *next);
+        BootPhase {
+            name: name.to_string(),
+            start_ms: start.as_millis() as u64,
+            duration_ms: next.saturating_sub(*start).as_millis() as u64,
+        }
+    }).collect()
+}
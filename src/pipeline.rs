@@ -0,0 +1,836 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, error, info, warn};
+use crate::arch::Arch;
+use crate::extension::{self, Event};
+use crate::firmware::{Firmware, FirmwareProvider};
+use crate::handle::{self, Control};
+use crate::hooks::Hooks;
+use crate::outcome::{millis, ExitReason, PhaseTimings, RunOutcome, TestResult, TestStatus};
+use crate::protocol::Guest;
+use crate::provenance::Provenance;
+use crate::provision::ProvisionConfig;
+use crate::qemu::{self, Launch, Running};
+use crate::redact::Redactor;
+use crate::tpm::Swtpm;
+use crate::{build, counters, deadline, diagnose, efi_status, firmware_provider, memcheck, modules, nvram, output, overlay,
+            phases, seed, staging, tools, transform, verdict, RunnerConfig, Workspace};
+
+pub struct Context {
+    pub config: RunnerConfig,
+    pub hooks: Arc<Hooks>,
+    pub control: Arc<Control>,
+    pub dry_run: bool,
+    pub extra_args: Vec<String>,
+    pub workspace: Option<Workspace>,
+    pub firmware: Option<Firmware>,
+    pub launch: Option<Launch>,
+    pub running: Option<Running>,
+    pub status: Option<ExitStatus>,
+    pub timings: PhaseTimings,
+    pub stage_timings: Vec<(&'static str, Duration)>,
+    pub outcome: Option<RunOutcome>,
+    pub failed_step: Option<&'static str>,
+    pub guest_exit: Option<i32>,
+    pub guest_tests: Vec<String>,
+    pub guest_results: Vec<TestResult>,
+    pub exit_reason: Option<ExitReason>,
+    pub timed_out: bool,
+    pub serial_log: Option<PathBuf>,
+    pub serial_times: Vec<u64>,
+    pub qemu_cmdline: Vec<String>,
+    pub staged_bytes: u64,
+    pub staged_files: Vec<PathBuf>,
+    pub overlap_saved: Option<Duration>,
+    pub provenance: Option<Provenance>,
+    pub swtpm: Option<Swtpm>,
+}
+
+impl Context {
+    pub fn new(config: RunnerConfig, hooks: Arc<Hooks>, control: Arc<Control>) -> Self {
+        handle::register(&control);
+        Context {
+            config,
+            hooks,
+            control,
+            dry_run: false,
+            extra_args: Vec::new(),
+            workspace: None,
+            firmware: None,
+            launch: None,
+            running: None,
+            status: None,
+            timings: PhaseTimings::default(),
+            stage_timings: Vec::new(),
+            outcome: None,
+            failed_step: None,
+            guest_exit: None,
+            guest_tests: Vec::new(),
+            guest_results: Vec::new(),
+            exit_reason: None,
+            timed_out: false,
+            serial_log: None,
+            serial_times: Vec::new(),
+            qemu_cmdline: Vec::new(),
+            staged_bytes: 0,
+            staged_files: Vec::new(),
+            overlap_saved: None,
+            provenance: None,
+            swtpm: None,
+        }
+    }
+
+    pub fn workspace(&self) -> Result<&Workspace, String> {
+        self.workspace.as_ref().ok_or_else(|| "Stage step has not run".to_string())
+    }
+
+    pub fn firmware(&self) -> Result<&Firmware, String> {
+        self.firmware.as_ref().ok_or_else(|| "Firmware step has not run".to_string())
+    }
+
+    // The run dir goes away with the workspace, so a log written there is copied next to the other artifacts.
+    pub fn keep_serial_log(&self) -> Result<Option<PathBuf>, String> {
+        let log = match (&self.serial_log, &self.workspace) {
+            (Some(log), Some(workspace)) if log.starts_with(workspace.run_dir()) => log,
+            (log, _) => return Ok(log.clone()),
+        };
+        if !log.exists() {
+            return Ok(None);
+        }
+        let artifacts_dir = PathBuf::from(&self.config.artifacts_dir);
+        let kept = artifacts_dir.join("serial.log");
+        fs::create_dir_all(&artifacts_dir).and_then(|_| fs::copy(log, &kept))
+            .map_err(|e| format!("Failed to keep serial log: {}", e))?;
+        Ok(Some(kept))
+    }
+
+    // Runner output rather than logging, so it follows output.logs and stays out of --quiet runs.
+    pub fn print_stats(&self) {
+        output::runner_line("Stage timings:");
+        for (name, elapsed) in &self.stage_timings {
+            output::runner_line(&format!("    {:<10} {:>8.3}s", name, elapsed.as_secs_f64()));
+        }
+        if !self.timings.boot_phases.is_empty() {
+            output::runner_line("Firmware boot phases:");
+            for phase in &self.timings.boot_phases {
+                output::runner_line(&format!("    {:<10} {:>8.3}s  from {:.3}s", phase.name,
+                                             phase.duration_ms as f64 / 1000.0, phase.start_ms as f64 / 1000.0));
+            }
+        }
+        if let Some(saved) = self.overlap_saved {
+            output::runner_line(&format!("Build overlapped with staging and firmware preparation, saving {:.3}s",
+                                         saved.as_secs_f64()));
+        }
+        let staging = self.stage_timings.iter()
+            .filter(|(name, _)| matches!(*name, "stage" | "stage-binary"))
+            .map(|(_, elapsed)| *elapsed)
+            .sum::<Duration>();
+        if !staging.is_zero() {
+            let dir = self.workspace.as_ref().map(|workspace| workspace.work_dir().display().to_string());
+            output::runner_line(&format!("Staged {} byte(s) into {} at {:.1} MiB/s", self.staged_bytes,
+                                         dir.unwrap_or_default(),
+                                         self.staged_bytes as f64 / 1048576.0 / staging.as_secs_f64().max(1e-6)));
+            let results = staging::benchmark(self.staged_bytes);
+            output::runner_line("Staging write benchmark, synced:");
+            for (dir, speed) in &results {
+                let kind = if staging::tmpfs_root().as_ref() == Some(dir) { "tmpfs" } else { "temp dir" };
+                output::runner_line(&match speed {
+                    Ok(speed) => format!("    {:<8} {:<24} {:>8.1} MiB/s", kind, dir.display(), speed),
+                    Err(e) => format!("    {:<8} {:<24} failed: {}", kind, dir.display(), e),
+                });
+            }
+            if let [(_, Ok(tmpfs)), (_, Ok(disk))] = results.as_slice() {
+                output::runner_line(&format!("    tmpfs staging is {:.1}x the temp dir, \
+                                              set work_dir = \"tmpfs\" to use it", tmpfs / disk));
+            }
+        }
+    }
+
+    pub fn reset_run(&mut self, config: RunnerConfig) {
+        self.config = config;
+        self.launch = None;
+        self.running = None;
+        self.swtpm = None;
+        self.status = None;
+        self.outcome = None;
+        self.failed_step = None;
+        self.guest_exit = None;
+        self.guest_tests.clear();
+        self.guest_results.clear();
+        self.exit_reason = None;
+        self.timed_out = false;
+        self.control.reset();
+        self.serial_log = None;
+        self.serial_times.clear();
+        self.qemu_cmdline.clear();
+        self.timings.run_ms = None;
+        self.timings.boot_phases.clear();
+    }
+}
+
+pub trait Step: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String>;
+
+    fn dry_run(&self, _ctx: &mut Context) -> Result<(), String> {
+        info!("[dry-run] skipping {}", self.name());
+        Ok(())
+    }
+}
+
+pub struct Configure;
+pub struct Build;
+pub struct Sign;
+pub struct Stage;
+pub struct Provision;
+pub struct StageBinary;
+pub struct BuildImage;
+pub struct RecordProvenance;
+pub struct ResolveFirmware;
+pub struct Launching;
+pub struct Monitor;
+pub struct Collect;
+
+pub struct Overlapped {
+    pub foreground: Pipeline,
+}
+
+impl Step for Configure {
+    fn name(&self) -> &'static str {
+        "configure"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        if let Some(preset) = ctx.config.preset {
+            preset.apply(&mut ctx.config);
+        }
+        let seed = *ctx.config.seed.get_or_insert_with(seed::generate);
+        info!("Run seed {}", seed);
+        ctx.config.fill_arch_defaults();
+        if let Some(binary) = ctx.config.boot_binary().filter(|_| ctx.config.binary_path.is_empty()) {
+            ctx.config.binary_path = binary.path.clone();
+        }
+        if let Some(image) = &ctx.config.boot_image {
+            info!("Booting prebuilt image {}, skipping build and staging", image);
+            ctx.config.auto_build = false;
+        }
+        info!("Targeting {:?} with {}, booting {}", ctx.config.arch(), ctx.config.qemu_cmd, ctx.config.efi_name);
+        ctx.config.validate()?;
+        tools::degrade(&mut ctx.config)?;
+        if !ctx.config.auto_build && ctx.config.move_binary && ctx.config.boot_image.is_none() {
+            warn!("Moving binary away but not auto-building, this may cause issues");
+        }
+        if ctx.config.gdb.as_ref().is_some_and(|gdb| gdb.script) && !ctx.config.firmware_log {
+            if ctx.config.arch() == Arch::X86_64 {
+                info!("Enabling firmware_log so the image base can be read for the debugger scripts");
+                ctx.config.firmware_log = true;
+            } else {
+                warn!("Debugger symbols need the image base from the serial console on {:?}", ctx.config.arch());
+            }
+        }
+        if !ctx.config.redact.is_empty() && ctx.config.stdio_serial {
+            warn!("redact only applies to serial logs, the live console on stdio is not redacted");
+        }
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        self.run(ctx)
+    }
+}
+
+impl Step for Build {
+    fn name(&self) -> &'static str {
+        "build"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        ctx.timings.build_ms = build(&ctx.config, &ctx.hooks)?.and_then(millis);
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.config.auto_build {
+            info!("[dry-run] would run cargo {} in {}", ctx.config.build_cmd, ctx.config.project_path);
+        }
+        Ok(())
+    }
+}
+
+impl Step for Sign {
+    fn name(&self) -> &'static str {
+        "sign"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        self.sign(ctx, false)
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        self.sign(ctx, true)
+    }
+}
+
+impl Sign {
+    fn sign(&self, ctx: &mut Context, dry_run: bool) -> Result<(), String> {
+        let Some(secure_boot) = ctx.config.secure_boot.as_ref().filter(|secure_boot| secure_boot.sign.is_some()) else {
+            debug!("Nothing to sign");
+            return Ok(());
+        };
+        let binary = PathBuf::from(&ctx.config.binary_path);
+        let Some(signed) = secure_boot.sign(&binary, ctx.workspace()?.run_dir(), dry_run)? else {
+            return Ok(());
+        };
+        if ctx.config.move_binary && !dry_run {
+            fs::remove_file(&binary).map_err(|e| format!("Failed to remove unsigned {}: {}", binary.display(), e))?;
+        }
+        ctx.config.binary_path = signed.display().to_string();
+        ctx.config.move_binary = true;
+        Ok(())
+    }
+}
+
+impl Step for Stage {
+    fn name(&self) -> &'static str {
+        "stage"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let workspace = Workspace::create(&ctx.config)?;
+        if ctx.config.boot_image.is_some() {
+            ctx.workspace = Some(workspace);
+            return Ok(());
+        }
+        fs::create_dir_all(workspace.work_dir().join("EFI/BOOT"))
+            .map_err(|e| format!("Failed to create EFI/BOOT directory: {}", e))?;
+        ctx.staged_files.clear();
+        if let Some(linux) = &ctx.config.linux {
+            ctx.staged_bytes += linux.stage(workspace.work_dir(), &mut ctx.staged_files)?;
+        }
+        let stats = staging::stage_extra_files(&ctx.config.extra_files, workspace.work_dir())?;
+        ctx.staged_bytes += stats.bytes;
+        ctx.staged_files.extend(stats.files);
+        if let Some(startup) = &ctx.config.startup_nsh {
+            ctx.staged_bytes += startup.stage(&ctx.config.boot_path(), ctx.config.efi_shell_mapping.as_deref(),
+                                               workspace.work_dir(), &mut ctx.staged_files)?;
+        }
+        ctx.workspace = Some(workspace);
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        let workspace = Workspace::create(&ctx.config)?;
+        info!("[dry-run] would stage {} extra file set(s) into {}",
+              ctx.config.extra_files.len(), workspace.work_dir().display());
+        ctx.workspace = Some(workspace);
+        Ok(())
+    }
+}
+
+impl Step for Provision {
+    fn name(&self) -> &'static str {
+        "provision"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let Some(provision) = ctx.config.provision.clone() else {
+            return Ok(());
+        };
+        let vars = ctx.firmware()?.vars.clone();
+        if provision.done(vars.as_deref()) {
+            info!("Persistent state was already provisioned by {}, skipping the provisioning boot", provision.binary);
+            return Ok(());
+        }
+        ctx.config = overlay::apply(&ctx.config, false)?;
+        ctx.config.base_overlays = false;
+        let fallback = ctx.workspace()?.work_dir().join(format!("EFI/BOOT/{}", ctx.config.efi_name));
+        info!("Provisioning with {} before the run", provision.binary);
+        fs::copy(&provision.binary, &fallback).map_err(|e| format!("Failed to copy provisioner: {}", e))?;
+        let mut sub = Context::new(provision.config(&ctx.config), Arc::new(Hooks::default()), ctx.control.clone());
+        sub.workspace = ctx.workspace.take();
+        sub.firmware = ctx.firmware.clone();
+        let result = Pipeline::execution().run(&mut sub);
+        ctx.workspace = sub.workspace.take();
+        ctx.control.reset();
+        let _ = fs::remove_file(&fallback);
+        result.map_err(|e| format!("Provisioning failed: {}", e))?;
+        let outcome = sub.outcome.ok_or("Provisioning run did not finish")?;
+        if !outcome.success {
+            let reason = outcome.exit_reason.map(|reason| reason.describe()).unwrap_or_else(|| "failed".to_string());
+            return Err(format!("Provisioning failed on {}, see {}", reason, sub.config.log_path));
+        }
+        info!("Provisioning finished");
+        if let Some(vars) = vars.as_ref().filter(|_| provision.once) {
+            let stamp = ProvisionConfig::stamp(vars);
+            fs::write(&stamp, &provision.binary).map_err(|e| format!("Failed to write {}: {}", stamp.display(), e))?;
+        }
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        if let Some(provision) = &ctx.config.provision {
+            info!("[dry-run] would boot {} from EFI/BOOT/{} to provision NVRAM and disks before the run",
+                  provision.binary, ctx.config.efi_name);
+        }
+        Ok(())
+    }
+}
+
+impl Step for StageBinary {
+    fn name(&self) -> &'static str {
+        "stage-binary"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.config.boot_image.is_some() {
+            extension::notify(ctx, Event::Staged, None)?;
+            return Ok(());
+        }
+        let work_dir = ctx.workspace()?.work_dir().to_path_buf();
+        ctx.staged_bytes += staging::stage_binaries(&ctx.config.binaries, &work_dir, &mut ctx.staged_files)?;
+        let efi_bin_path = ctx.workspace()?.work_dir().join(ctx.config.boot_path());
+        if ctx.config.move_binary {
+            info!("Moving binary to {}", efi_bin_path.display());
+            ctx.staged_bytes += staging::move_file(Path::new(&ctx.config.binary_path), &efi_bin_path)
+                .map_err(|e| format!("Failed to move binary: {}", e))?;
+        } else {
+            info!("Copying binary to {}", efi_bin_path.display());
+            ctx.staged_bytes += fs::copy(&ctx.config.binary_path, &efi_bin_path)
+                .map_err(|e| format!("Failed to copy binary: {}", e))?;
+        }
+        ctx.staged_files.push(efi_bin_path);
+        if staging::is_persistent(ctx.config.work_dir.as_deref()) {
+            staging::prune(&work_dir, &ctx.staged_files)?;
+        }
+        extension::notify(ctx, Event::Staged, None)?;
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.config.boot_image.is_some() {
+            return Ok(());
+        }
+        info!("[dry-run] would {} {} to {}",
+              if ctx.config.move_binary { "move" } else { "copy" }, ctx.config.binary_path,
+              ctx.workspace()?.work_dir().join(ctx.config.boot_path()).display());
+        for binary in &ctx.config.binaries {
+            info!("[dry-run] would copy {} to {}",
+                  binary.path, ctx.workspace()?.work_dir().join(binary.dest()).display());
+        }
+        extension::notify(ctx, Event::Staged, None)?;
+        Ok(())
+    }
+}
+
+impl Step for BuildImage {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let Some(image) = &ctx.config.esp_image else {
+            return Ok(());
+        };
+        let workspace = ctx.workspace()?;
+        image.build(workspace.work_dir(), &workspace.esp_image())?;
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.config.esp_image.is_some() {
+            info!("[dry-run] would build a GPT image of {} at {}",
+                  ctx.workspace()?.work_dir().display(), ctx.workspace()?.esp_image().display());
+        }
+        Ok(())
+    }
+}
+
+impl Step for RecordProvenance {
+    fn name(&self) -> &'static str {
+        "provenance"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.config.provenance.is_some() {
+            ctx.provenance = Some(Provenance::collect(&ctx.config, ctx.workspace()?, ctx.firmware()?));
+        }
+        Ok(())
+    }
+}
+
+impl Step for Overlapped {
+    fn name(&self) -> &'static str {
+        "build+prepare"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let config = ctx.config.clone();
+        let hooks = ctx.hooks.clone();
+        let wall = Instant::now();
+        let (built, prepared) = thread::scope(|scope| {
+            let build = scope.spawn(|| {
+                let start = Instant::now();
+                build(&config, &hooks).map(|duration| (duration, start.elapsed()))
+            });
+            let prepared = self.foreground.run(ctx);
+            (build.join().unwrap_or_else(|_| Err("Build thread panicked".to_string())), prepared)
+        });
+        let (built, build_elapsed) = built.inspect_err(|_| ctx.failed_step = Some("build"))?;
+        prepared?;
+        ctx.timings.build_ms = built.and_then(millis);
+        ctx.stage_timings.push(("build", build_elapsed));
+        let foreground = ctx.stage_timings.iter()
+            .filter(|(name, _)| self.foreground.steps.iter().any(|step| step.name() == *name))
+            .map(|(_, elapsed)| *elapsed)
+            .sum::<Duration>();
+        ctx.overlap_saved = Some((build_elapsed + foreground).saturating_sub(wall.elapsed()));
+        Ok(())
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        Build.dry_run(ctx)?;
+        self.foreground.run(ctx)
+    }
+}
+
+impl Step for ResolveFirmware {
+    fn name(&self) -> &'static str {
+        "firmware"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        self.resolve(ctx, false)
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        self.resolve(ctx, true)
+    }
+}
+
+impl ResolveFirmware {
+    fn resolve(&self, ctx: &mut Context, dry_run: bool) -> Result<(), String> {
+        let mut firmware = firmware_provider(&ctx.config, dry_run).resolve().map_err(|e| {
+            info!("Hint: This tool needs OVMF_CODE.fd and OVMF_VARS.fd to run \
+                   (or a single image with firmware_mode = \"bios\"), install your distro's OVMF package, \
+                   pin one with firmware_download or build one with firmware_build");
+            format!("OVMF files not found: {}", e)
+        })?;
+        let enrolled = ctx.config.secure_boot.as_ref().and_then(|secure_boot| secure_boot.enrolled_vars.as_ref());
+        if let Some(enrolled) = enrolled {
+            info!("Using pre-enrolled Secure Boot vars {}", enrolled);
+            firmware.vars = Some(PathBuf::from(enrolled));
+        }
+        let reused = ctx.config.persist_nvram
+            && firmware.vars.as_ref().is_some_and(|vars| nvram::persistent_path(&ctx.config, vars).exists());
+        if let (Some(fuzz), Some(vars)) = (&ctx.config.nvram_fuzz, &firmware.vars) {
+            firmware.vars = Some(fuzz.apply(vars, ctx.workspace()?.run_dir())
+                .map_err(|e| format!("Failed to fuzz OVMF_VARS: {}", e))?);
+        } else if let Some(vars) = &firmware.vars {
+            firmware.vars = Some(nvram::prepare(&ctx.config, vars, ctx.workspace()?.run_dir(), dry_run)
+                .map_err(|e| format!("Failed to copy OVMF_VARS: {}", e))?);
+        }
+        if let (Some(secure_boot), Some(vars)) = (&ctx.config.secure_boot, &firmware.vars) {
+            if reused && secure_boot.enrolls() {
+                info!("Persistent NVRAM already exists, not enrolling Secure Boot keys again");
+            } else {
+                secure_boot.enroll(vars, dry_run)?;
+            }
+        }
+        ctx.firmware = Some(firmware);
+        Ok(())
+    }
+}
+
+impl Step for Launching {
+    fn name(&self) -> &'static str {
+        "launch"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        if ctx.control.shutdown_requested() || ctx.control.kill_requested() {
+            return Err("Stopped before QEMU was launched".to_string());
+        }
+        let mut extra_args = ctx.extra_args.clone();
+        extra_args.extend(extension::notify(ctx, Event::Launch, None)?);
+        let mut attempt = 0;
+        loop {
+            let config = overlay::apply(&ctx.config, false)?;
+            if let Some(tpm) = &ctx.config.tpm {
+                ctx.swtpm = None;
+                ctx.swtpm = Some(tpm.spawn(ctx.workspace()?.run_dir(), &ctx.config.artifacts_dir)?);
+            }
+            let launch = qemu::prepare_launch(&config, ctx.workspace()?, ctx.firmware()?,
+                                              &extra_args, &ctx.hooks, false)?;
+            ctx.serial_log = launch.serial_log().map(PathBuf::from);
+            ctx.qemu_cmdline = launch.cmdline();
+            let mut running = launch.spawn(&ctx.hooks, &ctx.control)?;
+            if attempt < ctx.config.launch_retries {
+                if let Some(reason) = running.transient_failure() {
+                    attempt += 1;
+                    warn!("QEMU failed to start ({}), retrying ({}/{})",
+                          reason, attempt, ctx.config.launch_retries);
+                    drop(running);
+                    thread::sleep(Duration::from_secs(attempt as u64));
+                    continue;
+                }
+            }
+            ctx.running = Some(running);
+            return Ok(());
+        }
+    }
+
+    fn dry_run(&self, ctx: &mut Context) -> Result<(), String> {
+        let config = overlay::apply(&ctx.config, true)?;
+        extension::notify(ctx, Event::Launch, None)?;
+        if let Some(tpm) = &ctx.config.tpm {
+            let swtpm = tpm.command(ctx.workspace()?.run_dir(), &ctx.config.artifacts_dir);
+            info!("[dry-run] would run {:?}", swtpm);
+        }
+        let launch = qemu::prepare_launch(&config, ctx.workspace()?, ctx.firmware()?,
+                                          &ctx.extra_args, &ctx.hooks, true)?;
+        ctx.qemu_cmdline = launch.cmdline();
+        info!("[dry-run] would run {}", ctx.qemu_cmdline.join(" "));
+        ctx.launch = Some(launch);
+        Ok(())
+    }
+}
+
+impl Step for Monitor {
+    fn name(&self) -> &'static str {
+        "monitor"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let running = ctx.running.take().ok_or("Launch step has not run")?;
+        let guest = ctx.config.guest_protocol.clone()
+            .map(|protocol| Guest::new(protocol, &ctx.config.artifacts_dir));
+        let events = running.events();
+        let started = running.started();
+        let done = AtomicBool::new(false);
+        let images = [ctx.config.boot_path(), ctx.config.binary_path.clone()].iter()
+            .filter_map(|path| Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>();
+        let (status, timed_out, expired, boot_phases, serial_times) = thread::scope(|scope| {
+            let phases = qemu::firmware_log(&ctx.config).map(|log| {
+                let (images, done) = (&images, &done);
+                scope.spawn(move || phases::watch(&log, images, started, done))
+            });
+            let times = ctx.serial_log.as_deref().filter(|_| transform::timed(&ctx.config))
+                .map(|log| scope.spawn(|| transform::watch(log, started, &done)));
+            let watcher = ctx.config.test.as_ref()
+                .map(|test| scope.spawn(|| test.watch(ctx.serial_log.as_deref(), &ctx.control, &done)));
+            let deadline = deadline::enabled(&ctx.config)
+                .then(|| scope.spawn(|| deadline::watch(&ctx.config, ctx.serial_log.as_deref(), &ctx.control, &done)));
+            if let (Some(gdb), Some(workspace)) = (&ctx.config.gdb, &ctx.workspace) {
+                let logs = ctx.serial_log.iter().cloned().chain(qemu::firmware_log(&ctx.config)).collect::<Vec<_>>();
+                let symbols = gdb.symbol_file(&ctx.config, workspace.work_dir());
+                let (dir, efi_name, done) = (PathBuf::from(&ctx.config.artifacts_dir), &ctx.config.efi_name, &done);
+                scope.spawn(move || gdb.watch(&logs, efi_name, &symbols, &dir, done));
+            }
+            let status = running.monitor(&ctx.hooks, &ctx.control, guest.as_ref());
+            done.store(true, Ordering::Release);
+            let expired = deadline.and_then(|deadline| deadline.join().ok().flatten());
+            let boot_phases = phases.and_then(|phases| phases.join().ok()).unwrap_or_default();
+            let serial_times = times.and_then(|times| times.join().ok()).unwrap_or_default();
+            (status, watcher.is_some_and(|watcher| watcher.join().unwrap_or(false)), expired, boot_phases, serial_times)
+        });
+        let status = status?;
+        ctx.timings.boot_phases = boot_phases;
+        ctx.serial_times = serial_times;
+        ctx.timed_out = timed_out || expired.is_some();
+        ctx.exit_reason = Some(expired.unwrap_or_else(|| ExitReason::classify(&events.lock().unwrap(), status)));
+        ctx.status = Some(status);
+        if let Some(guest) = &guest {
+            ctx.guest_tests = guest.listed_tests();
+            ctx.guest_results = guest.test_results();
+        }
+        ctx.guest_exit = guest.and_then(|guest| guest.exit_code());
+        Ok(())
+    }
+}
+
+impl Step for Collect {
+    fn name(&self) -> &'static str {
+        "collect"
+    }
+
+    fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        let status = ctx.status.ok_or("Monitor step has not run")?;
+        let mut outcome = RunOutcome {
+            timings: ctx.timings.clone(),
+            exit_reason: ctx.exit_reason.clone(),
+            seed: ctx.config.seed,
+            tags: ctx.config.tags.clone(),
+            provenance: ctx.provenance.clone().map(Box::new),
+            ..RunOutcome::from(status)
+        };
+        if let Some(code) = ctx.guest_exit {
+            outcome.success = code == 0;
+            outcome.exit_code = Some(code);
+        }
+        if !ctx.guest_results.is_empty() {
+            outcome.success &= ctx.guest_results.iter().all(|test| test.status == TestStatus::Passed);
+            outcome.tests.extend(ctx.guest_results.iter().cloned());
+        }
+        if matches!(outcome.exit_reason, Some(ExitReason::GuestPanic | ExitReason::Watchdog { .. }
+                                             | ExitReason::Timeout { .. } | ExitReason::Idle { .. })) {
+            outcome.success = false;
+        }
+        let firmware_log = qemu::firmware_log(&ctx.config);
+        let logs = [ctx.serial_log.as_deref(), firmware_log.as_deref()];
+        let logs = logs.into_iter().flatten().collect::<Vec<_>>();
+        outcome.efi_status = efi_status::scan(&logs);
+        outcome.counters = counters::collect(&ctx.config);
+        if let Some(counters) = &outcome.counters {
+            match counters.blocks {
+                Some(blocks) => info!("Guest executed {} instruction(s) in {} basic block(s)",
+                                      counters.instructions, blocks),
+                None => info!("Guest executed {} instruction(s)", counters.instructions),
+            }
+        } else if ctx.config.counters.is_some() {
+            warn!("The counter plugin did not report, QEMU may have been killed before it exited");
+        }
+        outcome.memcheck = memcheck::collect(&ctx.config);
+        if let Some(report) = &outcome.memcheck {
+            info!("Memcheck flagged {} suspicious memory access(es), {} in the watched ranges",
+                  report.total, report.in_range);
+            for finding in report.findings.iter().filter(|finding| finding.in_range) {
+                let pc = finding.pc.map(|pc| format!(" from pc {:#x}", pc)).unwrap_or_default();
+                warn!("    {} at {:#x}{}", finding.kind, finding.address, pc);
+            }
+        }
+        if let Some(result) = memcheck::verify(&ctx.config, outcome.memcheck.as_ref()) {
+            outcome.success &= result.status == TestStatus::Passed;
+            outcome.tests.push(result);
+        }
+        if let Err(e) = modules::write_map(&logs, Path::new(&ctx.config.artifacts_dir)) {
+            warn!("{}", e);
+        }
+        if let Some(expected) = &ctx.config.expect_efi_status {
+            let result = efi_status::check(expected, outcome.efi_status.as_ref());
+            outcome.success &= result.status == TestStatus::Passed;
+            outcome.tests.push(result);
+        }
+        if let Some(result) = ctx.config.linux.as_ref().and_then(|linux| linux.verify(ctx.serial_log.as_deref())) {
+            outcome.success &= result.status == TestStatus::Passed;
+            outcome.tests.push(result);
+        }
+        if let Some(result) = ctx.config.audio.as_ref().and_then(|audio| audio.verify(&ctx.config.artifacts_dir)) {
+            outcome.success &= result.status == TestStatus::Passed;
+            outcome.tests.push(result);
+        }
+        if let Some(test) = &ctx.config.test {
+            outcome.tests.push(test.evaluate(&outcome, ctx.serial_log.as_deref(), ctx.timed_out));
+            outcome.success = outcome.tests.iter().all(|test| test.status == TestStatus::Passed);
+        }
+        if let Some(cmd) = &ctx.config.verdict_cmd {
+            let run_dir = ctx.workspace()?.run_dir().to_path_buf();
+            let result = verdict::run(cmd, &outcome, ctx.serial_log.as_deref(),
+                                      Path::new(&ctx.config.artifacts_dir), &run_dir);
+            outcome.success = result.status != TestStatus::Failed;
+            outcome.tests.push(result);
+        }
+        let serial_bytes = ctx.serial_log.as_ref().map(|log| fs::metadata(log).map(|m| m.len()).unwrap_or(0));
+        if !outcome.success && serial_bytes == Some(0) && ctx.config.boot_image.is_none() {
+            let image = ctx.workspace()?.work_dir().join(ctx.config.boot_path());
+            diagnose::report(&diagnose::no_serial_output(&ctx.config, &image, ctx.firmware.as_ref()));
+        }
+        if let Some(triage) = qemu::debug_log(&ctx.config).filter(|_| !outcome.success)
+            .and_then(|log| diagnose::triage(&log)) {
+            if let Err(e) = diagnose::write_triage(&triage, Path::new(&ctx.config.artifacts_dir)) {
+                warn!("{}", e);
+            }
+        }
+        let redactor = Redactor::new(&ctx.config);
+        for test in &mut outcome.tests {
+            test.message = test.message.as_deref().map(|message| redactor.redact(message));
+        }
+        for log in &logs {
+            if let Err(e) = redactor.redact_file(log) {
+                warn!("{}", e);
+            }
+        }
+        if let Some(log) = &ctx.serial_log {
+            transform::apply(&ctx.config, log, &ctx.serial_times);
+        }
+        if let (Some(format), Some(provenance)) = (ctx.config.provenance, &outcome.provenance) {
+            if let Err(e) = provenance.write(format, &ctx.config, &outcome) {
+                warn!("{}", e);
+            }
+        }
+        if let Err(e) = extension::notify(ctx, Event::Exit, Some(&outcome)) {
+            error!("{}", e);
+            outcome.success = false;
+        }
+        let artifacts_dir = Path::new(&ctx.config.artifacts_dir);
+        if let Err(e) = outcome.write(artifacts_dir) {
+            warn!("{}", e);
+        }
+        if let Err(e) = outcome.archive(artifacts_dir, ctx.serial_log.as_deref(), &ctx.config.retention) {
+            warn!("{}", e);
+        }
+        ctx.hooks.exit(&outcome);
+        ctx.outcome = Some(outcome);
+        Ok(())
+    }
+}
+
+pub struct Pipeline {
+    pub steps: Vec<Box<dyn Step>>,
+}
+
+impl Pipeline {
+    pub fn preparation() -> Self {
+        Pipeline {
+            steps: vec![
+                Box::new(Configure),
+                Box::new(Overlapped {
+                    foreground: Pipeline { steps: vec![Box::new(Stage), Box::new(ResolveFirmware)] },
+                }),
+                Box::new(Sign),
+                Box::new(Provision),
+                Box::new(StageBinary),
+                Box::new(BuildImage),
+                Box::new(RecordProvenance),
+            ],
+        }
+    }
+
+    pub fn execution() -> Self {
+        Pipeline { steps: vec![Box::new(Launching), Box::new(Monitor), Box::new(Collect)] }
+    }
+
+    pub fn standard() -> Self {
+        let mut pipeline = Pipeline::preparation();
+        pipeline.steps.extend(Pipeline::execution().steps);
+        pipeline
+    }
+
+    pub fn run(&self, ctx: &mut Context) -> Result<(), String> {
+        for step in &self.steps {
+            debug!("Running {} step", step.name());
+            let start = Instant::now();
+            let result = if ctx.dry_run { step.dry_run(ctx) } else { step.run(ctx) };
+            if result.is_err() {
+                ctx.failed_step.get_or_insert(step.name());
+            }
+            result?;
+            let elapsed = start.elapsed();
+            match step.name() {
+                "stage" | "stage-binary" => ctx.timings.stage_ms =
+                    millis(elapsed).map(|ms| ms + ctx.timings.stage_ms.unwrap_or(0)),
+                "firmware" => ctx.timings.firmware_ms = millis(elapsed),
+                "monitor" => ctx.timings.run_ms = millis(elapsed),
+                _ => {}
+            }
+            ctx.stage_timings.push((step.name(), elapsed));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::RunnerConfig;
+
+const ARTIFACTS: &str = "{artifacts}";
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Plugin {
+    pub path: String,
+    #[serde(default)]
+    pub args: BTreeMap<String, String>,
+}
+
+impl Plugin {
+    pub fn new(path: &str, args: &[(&str, &str)]) -> Self {
+        Plugin { path: path.to_string(), args: args.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect() }
+    }
+
+    fn qemu_arg(&self, artifacts_dir: &str) -> String {
+        let mut arg = self.path.clone();
+        for (key, value) in &self.args {
+            arg.push_str(&format!(",{}={}", key, value.replace(ARTIFACTS, artifacts_dir).replace(',', ",,")));
+        }
+        arg
+    }
+}
+
+fn loaded(config: &RunnerConfig) -> Vec<Plugin> {
+    let counters = config.counters.as_ref().map(|counters| Plugin::new(&counters.plugin, &[("inline", "on")]));
+    let memcheck = config.memcheck.as_ref().and_then(|memcheck| memcheck.plugin.clone());
+    counters.into_iter().chain(memcheck).chain(config.plugins.iter().cloned()).collect()
+}
+
+pub fn log_path(config: &RunnerConfig) -> Option<PathBuf> {
+    (config.counters.is_some() || config.memcheck.is_some() || !config.plugins.is_empty())
+        .then(|| PathBuf::from(&config.artifacts_dir).join("plugin.log"))
+}
+
+pub fn validate(config: &RunnerConfig) -> Result<(), String> {
+    if log_path(config).is_none() {
+        return Ok(());
+    }
+    if config.accel.as_deref().is_some_and(|accel| accel != "tcg") {
+        return Err("TCG plugins and memcheck need TCG, remove the accel setting".to_string());
+    }
+    if config.qemu_debug_log {
+        return Err("TCG plugins, memcheck and qemu_debug_log all use the QEMU log file".to_string());
+    }
+    Ok(())
+}
+
+pub fn qemu_args(config: &RunnerConfig) -> Vec<String> {
+    let Some(log) = log_path(config) else {
+        return Vec::new();
+    };
+    let mut args = Vec::new();
+    for plugin in loaded(config) {
+        args.extend(["-plugin".to_string(), plugin.qemu_arg(&config.artifacts_dir)]);
+    }
+    let flags = match &config.memcheck {
+        Some(memcheck) => format!("plugin,{}", memcheck.log_flags()),
+        None => "plugin".to_string(),
+    };
+    args.extend(["-d".to_string(), flags, "-D".to_string(), log.display().to_string()]);
+    args
+}
@@ -0,0 +1,269 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use serde_json::json;
+use crate::digest::{self, Entry};
+use crate::handle::Control;
+use crate::harness::TestConfig;
+use crate::hooks::Hooks;
+use crate::image::EspImage;
+use crate::outcome::{millis, TestResult, TestStatus};
+use crate::pipeline::{BuildImage, Configure, Context, Pipeline, ResolveFirmware, Stage};
+use crate::protocol::{self, Message};
+use crate::qemu;
+use crate::qmp::{self, ScheduledCommand};
+use crate::serial::LogFollower;
+use crate::staging::{StartupNsh, VOLUME_TAG};
+use crate::RunnerConfig;
+
+const READY: &str = "uefapi-pool: ready";
+const SNAPSHOT: &str = "uefapi-pool";
+
+enum Verdict {
+    Done(TestStatus, Option<String>),
+    Exited,
+}
+
+// The shell parks on pause once it booted, that point is saved once and every dispatch restores it, plugs in the
+// next ESP and presses a key so the tagged volume gets launched.
+fn startup() -> StartupNsh {
+    StartupNsh::Script(format!("@echo -off\r\necho {}\r\npause\r\nstall 1000000\r\n{{{{launch}}}}", READY))
+}
+
+fn hmp(command: &str, log: &Path) -> ScheduledCommand {
+    ScheduledCommand::now(qmp::MONITOR_COMMAND, json!({ "command-line": command, "log": log.display().to_string() }))
+}
+
+fn dispatch(qmp: &Sender<ScheduledCommand>, index: usize, esp: &Path, log: &Path) -> Result<(), String> {
+    let mut commands = Vec::new();
+    // The snapshot was taken without any test ESP, unplug the previous one before restoring it.
+    if index > 0 {
+        commands.push(ScheduledCommand::now("device_del", json!({ "id": format!("pool-esp{}", index - 1) })));
+        let node = format!("pool-esp-node{}", index - 1);
+        commands.push(ScheduledCommand::now("blockdev-del", json!({ "node-name": node })));
+    }
+    commands.push(hmp(&format!("loadvm {}", SNAPSHOT), log));
+    // -no-shutdown leaves a guest that powered off paused, loadvm keeps it that way.
+    commands.push(ScheduledCommand::now("cont", json!({})));
+    commands.push(ScheduledCommand::now("blockdev-add", json!({
+        "driver": "vvfat", "node-name": format!("pool-esp-node{}", index),
+        "dir": esp.display().to_string(), "rw": false, "read-only": true,
+    })));
+    commands.push(ScheduledCommand::now("device_add", json!({
+        "driver": "usb-storage", "id": format!("pool-esp{}", index),
+        "drive": format!("pool-esp-node{}", index), "bus": "pool-xhci.0", "removable": true,
+    })));
+    commands.push(ScheduledCommand::now("send-key", json!({ "keys": [{ "type": "qcode", "data": "ret" }] })));
+    for command in commands {
+        qmp.send(command).map_err(|_| "QMP controller is gone".to_string())?;
+    }
+    Ok(())
+}
+
+fn stage(config: &RunnerConfig, input: &str) -> Result<tempfile::TempDir, String> {
+    let esp = tempfile::tempdir().map_err(|e| format!("Failed to create ESP dir: {}", e))?;
+    let boot = esp.path().join("EFI/BOOT");
+    fs::create_dir_all(&boot).map_err(|e| format!("Failed to create EFI/BOOT directory: {}", e))?;
+    fs::copy(input, boot.join(&config.efi_name)).map_err(|e| format!("Failed to stage {}: {}", input, e))?;
+    let tag = esp.path().join(VOLUME_TAG);
+    fs::write(&tag, b"").map_err(|e| format!("Failed to write {}: {}", tag.display(), e))?;
+    Ok(esp)
+}
+
+fn wait_for_shell(follower: &mut LogFollower, timeout: Duration, exited: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout && !exited() {
+        if follower.poll().iter().any(|line| line.contains(READY)) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+fn watch(follower: &mut LogFollower, test: Option<&TestConfig>, timeout: Duration,
+         exited: impl Fn() -> bool) -> Verdict {
+    let marker = |line: &str, marker: Option<&String>| marker.is_some_and(|marker| line.contains(marker.as_str()));
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let exited = exited();
+        for line in follower.poll() {
+            match protocol::parse(&line) {
+                Some(Message::Exit(0)) => return Verdict::Done(TestStatus::Passed, None),
+                Some(Message::Exit(code)) => {
+                    return Verdict::Done(TestStatus::Failed, Some(format!("guest exited with {}", code)));
+                }
+                _ => {}
+            }
+            if marker(&line, test.and_then(|test| test.failure_marker.as_ref())) {
+                return Verdict::Done(TestStatus::Failed, Some("failure marker seen".to_string()));
+            }
+            if marker(&line, test.and_then(|test| test.success_marker.as_ref())) {
+                return Verdict::Done(TestStatus::Passed, None);
+            }
+        }
+        if exited {
+            return Verdict::Exited;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Verdict::Done(TestStatus::Timeout, Some(format!("no verdict within {:?}", timeout)))
+}
+
+type Results = Vec<(TestResult, PathBuf)>;
+
+// Boots one VM and feeds it binaries until the queue drains or the guest takes QEMU down, as isa-debug-exit does.
+fn session(ctx: &Context, control: &Control, queue: &Mutex<Vec<String>>, timeout: Duration,
+           results: &mut Results) -> Result<bool, String> {
+    let log = PathBuf::from(&ctx.config.log_path);
+    let _ = fs::remove_file(&log);
+    let monitor_log = log.with_extension("monitor.log");
+    let launch = qemu::prepare_launch(&ctx.config, ctx.workspace()?, ctx.firmware()?,
+                                      &ctx.extra_args, &ctx.hooks, false)?;
+    let running = launch.spawn(&ctx.hooks, control)?;
+    let qmp = running.commands();
+    let mut follower = LogFollower::new(&log);
+    thread::scope(|scope| {
+        let monitor = scope.spawn(|| running.monitor(&ctx.hooks, control, None));
+        if !wait_for_shell(&mut follower, timeout, || monitor.is_finished()) {
+            control.request_kill();
+            let _ = monitor.join();
+            return Err(format!("VM never reached the shell, see {}", log.display()));
+        }
+        qmp.send(hmp(&format!("savevm {}", SNAPSHOT), &monitor_log))
+            .map_err(|_| "QMP controller is gone".to_string())?;
+        let mut index = 0;
+        let mut pending = None;
+        while let Some(input) = queue.lock().unwrap().pop() {
+            let start = Instant::now();
+            let staged = match stage(&ctx.config, &input) {
+                Ok(staged) => staged,
+                Err(e) => {
+                    let duration_ms = millis(start.elapsed());
+                    let result = TestResult { name: input, status: TestStatus::Failed, duration_ms, message: Some(e) };
+                    results.push((result, log.clone()));
+                    continue;
+                }
+            };
+            follower.poll();
+            if let Err(e) = dispatch(&qmp, index, staged.path(), &monitor_log) {
+                queue.lock().unwrap().push(input);
+                control.request_kill();
+                let _ = monitor.join();
+                return Err(e);
+            }
+            index += 1;
+            let test = ctx.config.test.as_ref();
+            match watch(&mut follower, test, timeout, || monitor.is_finished()) {
+                Verdict::Done(status, message) => {
+                    info!("Pool VM {}: {} {:?}", log.display(), input, status);
+                    let result = TestResult { name: input, status, duration_ms: millis(start.elapsed()), message };
+                    results.push((result, log.clone()));
+                }
+                Verdict::Exited => {
+                    pending = Some((input, start));
+                    break;
+                }
+            }
+        }
+        let Some((input, start)) = pending else {
+            control.request_kill();
+            if let Ok(Err(e)) = monitor.join() {
+                warn!("Pool VM {}: {}", log.display(), e);
+            }
+            return Ok(false);
+        };
+        let status = monitor.join().map_err(|_| "Pool monitor panicked".to_string())??;
+        let code = status.code().unwrap_or(-1);
+        let (status, message) = ctx.config.test.as_ref().and_then(|test| test.debug_exit_status(code))
+            .unwrap_or((TestStatus::Failed, Some(format!("QEMU exited with {}", status))));
+        info!("Pool VM {}: {} {:?}", log.display(), input, status);
+        results.push((TestResult { name: input, status, duration_ms: millis(start.elapsed()), message }, log.clone()));
+        Ok(true)
+    })
+}
+
+fn worker(id: usize, config: RunnerConfig, queue: Arc<Mutex<Vec<String>>>, dir: PathBuf,
+          timeout: Duration) -> Result<Results, String> {
+    let control = Arc::new(Control::default());
+    let mut ctx = Context::new(config, Arc::new(Hooks::default()), control.clone());
+    // Every drive gets a throwaway qcow2 overlay, which is what lets savevm store the shell state.
+    ctx.extra_args = ["-snapshot", "-no-shutdown", "-device", "qemu-xhci,id=pool-xhci",
+                      "-device", "usb-kbd,bus=pool-xhci.0"].map(String::from).to_vec();
+    Pipeline { steps: vec![Box::new(Configure), Box::new(Stage)] }.run(&mut ctx)?;
+    // The shared ESP only carries startup.nsh, only the hot-plugged ones are tagged for the launch loop.
+    let tag = ctx.workspace()?.work_dir().join(VOLUME_TAG);
+    fs::remove_file(&tag).map_err(|e| format!("Failed to remove {}: {}", tag.display(), e))?;
+    Pipeline { steps: vec![Box::new(BuildImage), Box::new(ResolveFirmware)] }.run(&mut ctx)?;
+    let mut results = Vec::new();
+    for boot in 0.. {
+        ctx.config.log_path = dir.join(format!("vm{}-{}.log", id, boot)).display().to_string();
+        info!("Pool VM {} booting to the shell", id);
+        if !session(&ctx, &control, &queue, timeout, &mut results)? {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+pub fn run(config: &RunnerConfig, inputs: &[String], size: usize, timeout: Duration) -> bool {
+    let dir = PathBuf::from(&config.artifacts_dir).join("pool");
+    fs::create_dir_all(&dir).expect("Failed to create pool log dir");
+    if inputs.is_empty() {
+        error!("pool needs at least one EFI binary");
+        return false;
+    }
+    let mut queue = inputs.to_vec();
+    queue.reverse();
+    let queue = Arc::new(Mutex::new(queue));
+    let handles = (0..size.clamp(1, inputs.len())).map(|id| {
+        let config = RunnerConfig {
+            binary_path: inputs[0].clone(),
+            auto_build: false,
+            stdio_serial: false,
+            log_serial: true,
+            // Workers idle in the shell, each binary boots from its own hot-plugged ESP.
+            startup_nsh: Some(startup()),
+            efi_shell_mapping: None,
+            esp_image: Some(EspImage::default()),
+            boot_image: None,
+            binaries: Vec::new(),
+            linux: None,
+            ..config.clone()
+        };
+        let (queue, dir) = (queue.clone(), dir.clone());
+        thread::spawn(move || worker(id, config, queue, dir, timeout))
+    }).collect::<Vec<_>>();
+    let mut results = Vec::new();
+    let mut entries = Vec::new();
+    for handle in handles {
+        match handle.join().expect("Pool worker panicked") {
+            Ok(worker_results) => {
+                for (result, log) in worker_results {
+                    entries.push(Entry { result: result.clone(), artifacts: vec![log] });
+                    results.push(result);
+                }
+            }
+            Err(e) => error!("Pool VM failed: {}", e),
+        }
+    }
+    if let Some(format) = config.digest {
+        if let Err(e) = digest::write(format, "Pool summary", &entries, &config.artifacts_dir) {
+            warn!("{}", e);
+        }
+    }
+    let leftover = queue.lock().unwrap().len();
+    info!("Pool results (serial logs in {}):", dir.display());
+    for result in &results {
+        info!("    {:?} {}{}", result.status, result.name,
+              result.message.as_ref().map(|m| format!(" ({})", m)).unwrap_or_default());
+    }
+    if leftover > 0 {
+        error!("{} test binar(ies) were never dispatched", leftover);
+    }
+    leftover == 0 && results.iter().all(|result| result.status == TestStatus::Passed)
+}
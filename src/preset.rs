@@ -0,0 +1,115 @@
+use std::path::Path;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::arch::Arch;
+use crate::firmware::FirmwareMode;
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    UbootAarch64,
+    UbootRiscv64,
+    FragmentedMemory,
+}
+
+impl Preset {
+    pub fn machine_args(&self) -> &'static [&'static str] {
+        match self {
+            Preset::UbootAarch64 => &["-machine", "virt"],
+            Preset::UbootRiscv64 => &["-machine", "virt"],
+            Preset::FragmentedMemory => &["-machine", "q35,nvdimm=on"],
+        }
+    }
+
+    pub fn cpu(&self) -> Option<&'static str> {
+        match self {
+            Preset::UbootAarch64 => Some("cortex-a57"),
+            _ => None,
+        }
+    }
+
+    pub fn arch(&self) -> Arch {
+        match self {
+            Preset::UbootAarch64 => Arch::Aarch64,
+            Preset::UbootRiscv64 => Arch::Riscv64,
+            Preset::FragmentedMemory => Arch::X86_64,
+        }
+    }
+
+    pub fn efi_name(&self) -> &'static str {
+        match self {
+            Preset::UbootAarch64 => "BOOTAA64.EFI",
+            Preset::UbootRiscv64 => "BOOTRISCV64.EFI",
+            Preset::FragmentedMemory => "BOOTX64.EFI",
+        }
+    }
+
+    pub fn extra_args(&self, run_dir: &Path) -> Vec<String> {
+        match self {
+            Preset::FragmentedMemory => fragmented_memory_args(run_dir),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn apply(&self, config: &mut RunnerConfig) {
+        info!("Applying preset {:?}", self);
+        match self {
+            Preset::UbootAarch64 | Preset::UbootRiscv64 => {
+                if config.firmware_mode != FirmwareMode::Bios {
+                    info!("U-Boot is loaded via -bios, overriding firmware_mode");
+                    config.firmware_mode = FirmwareMode::Bios;
+                }
+                match config.arch {
+                    None => config.arch = Some(self.arch()),
+                    Some(arch) if arch != self.arch() => warn!("Preset {:?} boots {:?} but arch is {:?}",
+                                                               self, self.arch(), arch),
+                    Some(_) => {}
+                }
+                // An empty name or another architecture's default was never chosen for this preset.
+                let default = config.efi_name.is_empty() || Arch::from_efi_name(&config.efi_name)
+                    .is_some_and(|arch| arch != self.arch() && config.arch == Some(self.arch()));
+                if default {
+                    config.efi_name = self.efi_name().to_string();
+                } else if !config.efi_name.eq_ignore_ascii_case(self.efi_name()) {
+                    warn!("U-Boot looks for EFI/BOOT/{} but efi_name is {}",
+                          self.efi_name(), config.efi_name);
+                }
+            }
+            Preset::FragmentedMemory => {
+                if config.memory.is_some() || config.maxmem.is_some() {
+                    warn!("Fragmented memory preset overrides memory and maxmem");
+                }
+                config.memory = Some("2G".to_string());
+                config.maxmem = Some("8G".to_string());
+            }
+        }
+    }
+}
+
+pub fn machine_args(config: &RunnerConfig) -> &'static [&'static str] {
+    match config.preset {
+        Some(preset) => preset.machine_args(),
+        None => config.arch().machine_args(),
+    }
+}
+
+fn fragmented_memory_args(run_dir: &Path) -> Vec<String> {
+    let mut args = Vec::new();
+    for node in 0..4 {
+        args.push("-object".to_string());
+        args.push(format!("memory-backend-ram,id=node-mem{},size=512M", node));
+        args.push("-numa".to_string());
+        args.push(format!("node,nodeid={},memdev=node-mem{}", node, node));
+    }
+    args.push("-object".to_string());
+    args.push("memory-backend-ram,id=dimm-mem0,size=256M".to_string());
+    args.push("-device".to_string());
+    args.push("pc-dimm,id=dimm0,memdev=dimm-mem0,node=1".to_string());
+    args.push("-object".to_string());
+    args.push(format!("memory-backend-file,id=pmem-mem0,share=on,mem-path={},size=128M",
+                      run_dir.join("pmem0.img").display()));
+    args.push("-device".to_string());
+    args.push("nvdimm,id=pmem0,memdev=pmem-mem0,node=3".to_string());
+    args
+}
@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::env::{self, consts::EXE_SUFFIX};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use crate::disk::DiskSource;
+use crate::RunnerConfig;
+
+const KVM: &str = "/dev/kvm";
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Escalation {
+    #[default]
+    Guidance,
+    Sudo,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Capability {
+    NetAdmin,
+}
+
+impl Capability {
+    fn bit(&self) -> u32 {
+        match self {
+            Capability::NetAdmin => 12,
+        }
+    }
+
+    fn setcap_name(&self) -> &'static str {
+        match self {
+            Capability::NetAdmin => "cap_net_admin",
+        }
+    }
+}
+
+fn proc_status_field(name: &str) -> Option<String> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status.lines()
+        .find_map(|line| line.strip_prefix(name))
+        .map(|value| value.trim().to_string())
+}
+
+pub fn is_root() -> bool {
+    if let Some(uid) = proc_status_field("Uid:") {
+        return uid.split_whitespace().nth(1) == Some("0");
+    }
+    Command::new("id").arg("-u").output()
+        .map(|out| String::from_utf8_lossy(&out.stdout).trim() == "0")
+        .unwrap_or(false)
+}
+
+pub fn has_capability(cap: Capability) -> bool {
+    if is_root() {
+        return true;
+    }
+    proc_status_field("CapEff:")
+        .and_then(|caps| u64::from_str_radix(&caps, 16).ok())
+        .is_some_and(|caps| caps & (1 << cap.bit()) != 0)
+}
+
+pub fn print_setcap_guidance(program: &str, cap: Capability) {
+    let path = which(program).unwrap_or_else(|| PathBuf::from(program));
+    info!("Hint: grant only this helper the capability it needs:");
+    info!("    sudo setcap {}+ep {}", cap.setcap_name(), path.display());
+}
+
+pub fn print_device_guidance(device: &Path) {
+    info!("Hint: grant your user access to {} instead of running as root:", device.display());
+    info!("    sudo setfacl -m u:$USER:rw {}", device.display());
+    info!("Hint: or persist it with a udev rule, e.g. /etc/udev/rules.d/99-uefapi-runner.rules:");
+    info!("    KERNEL==\"{}\", MODE=\"0660\", GROUP=\"plugdev\"",
+          device.file_name().map(|n| n.to_string_lossy()).unwrap_or_default());
+}
+
+pub fn device_accessible(device: &Path, write: bool) -> bool {
+    is_root() || fs::OpenOptions::new().read(true).write(write).open(device).is_ok()
+}
+
+fn sysfs_field(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name)).ok().map(|value| value.trim().to_string())
+}
+
+fn usb_host_node(options: &BTreeMap<String, String>) -> Option<PathBuf> {
+    let number = |key: &str| options.get(key).and_then(|value| value.parse::<u32>().ok());
+    if let (Some(bus), Some(addr)) = (number("hostbus"), number("hostaddr")) {
+        return Some(PathBuf::from(format!("/dev/bus/usb/{:03}/{:03}", bus, addr)));
+    }
+    let id = |key: &str| options.get(key).map(|value| value.trim_start_matches("0x").to_ascii_lowercase());
+    let (vendor, product) = (id("vendorid")?, id("productid")?);
+    fs::read_dir("/sys/bus/usb/devices").ok()?.flatten().map(|entry| entry.path()).find_map(|dir| {
+        if sysfs_field(&dir, "idVendor")? != vendor || sysfs_field(&dir, "idProduct")? != product {
+            return None;
+        }
+        let bus = sysfs_field(&dir, "busnum")?.parse::<u32>().ok()?;
+        let dev = sysfs_field(&dir, "devnum")?.parse::<u32>().ok()?;
+        Some(PathBuf::from(format!("/dev/bus/usb/{:03}/{:03}", bus, dev)))
+    })
+}
+
+pub fn check_kvm() {
+    let kvm = Path::new(KVM);
+    if kvm.exists() && !device_accessible(kvm, true) {
+        warn!("No read/write access to {}, QEMU will fall back to TCG", KVM);
+        info!("Hint: add your user to the kvm group: sudo usermod -aG kvm $USER");
+        print_device_guidance(kvm);
+    }
+}
+
+pub fn check_devices(config: &RunnerConfig) -> Result<(), String> {
+    let usb = config.device.iter().filter(|device| device.driver == "usb-host")
+        .filter_map(|device| usb_host_node(&device.options).map(|node| (node, true, "USB passthrough")));
+    let disks = config.disks.iter().filter_map(|disk| match &disk.source {
+        DiskSource::File { path } if path.starts_with("/dev/") =>
+            Some((PathBuf::from(path), !disk.readonly, "raw disk")),
+        _ => None,
+    });
+    let mut missing = Vec::new();
+    for (node, write, what) in usb.chain(disks) {
+        if !device_accessible(&node, write) {
+            error!("{} needs {} access to {}", what, if write { "read/write" } else { "read" }, node.display());
+            print_device_guidance(&node);
+            missing.push(node.display().to_string());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!("missing access to {}", missing.join(", ")));
+    }
+    Ok(())
+}
+
+// The one PATH lookup for helpers and tools, a bare name also matches its EXE_SUFFIX variant.
+pub fn which(program: &str) -> Option<PathBuf> {
+    if Path::new(program).components().count() > 1 {
+        return Some(PathBuf::from(program));
+    }
+    let names = [program.to_string(), format!("{}{}", program, EXE_SUFFIX)];
+    env::split_paths(&env::var_os("PATH")?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn run_helper(step: &str, program: &str, args: &[String],
+                  cap: Capability, escalation: Escalation) -> Result<(), String> {
+    let mut cmd = if has_capability(cap) {
+        Command::new(program)
+    } else {
+        // The capability may sit on the helper itself after setcap, so only a refused attempt escalates.
+        let out = Command::new(program).args(args).stdout(Stdio::null()).output()
+            .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+        if out.status.success() {
+            return Ok(());
+        }
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if !stderr.contains("Operation not permitted") {
+            return Err(format!("{} failed with status {}: {}", step, out.status, stderr.trim()));
+        }
+        match escalation {
+            Escalation::Sudo => {
+                warn!("{} needs {}, escalating this step with sudo", step, cap.setcap_name());
+                let mut cmd = Command::new("sudo");
+                cmd.arg("--").arg(program);
+                cmd
+            }
+            Escalation::Guidance => {
+                error!("{} needs {}: {}", step, cap.setcap_name(), stderr.trim());
+                print_setcap_guidance(program, cap);
+                info!("Hint: or set escalation = \"sudo\" to be prompted for just this step");
+                return Err(format!("missing {} for {}", cap.setcap_name(), step));
+            }
+        }
+    };
+    let status = cmd.args(args).status()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} failed with status {}", step, status));
+    }
+    Ok(())
+}
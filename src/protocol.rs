@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::handle::Control;
+use crate::outcome::{millis, TestResult, TestStatus};
+use crate::qmp::{self, ScheduledCommand};
+
+pub const PREFIX: &str = "##UEFAPI:";
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct GuestProtocol {
+    #[serde(default)]
+    pub heartbeat_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Message {
+    Exit(i32),
+    Heartbeat,
+    Artifact { name: String, chunk: Vec<u8> },
+    Screenshot(String),
+    Mark(Option<String>),
+    TestListed(String),
+    TestStarted(String),
+    TestPassed(String),
+    TestFailed { name: String, message: Option<String> },
+    Unknown(String),
+}
+
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in data.bytes().filter(|c| *c != b'=') {
+        let value = BASE64.iter().position(|byte| *byte == c)?;
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+pub fn parse(line: &str) -> Option<Message> {
+    let message = &line[line.find(PREFIX)? + PREFIX.len()..];
+    let message = message.trim_end();
+    let (key, value) = message.split_once('=').unwrap_or((message, ""));
+    Some(match key {
+        "EXIT" => match value.parse() {
+            Ok(code) => Message::Exit(code),
+            Err(_) => Message::Unknown(message.to_string()),
+        },
+        "HEARTBEAT" => Message::Heartbeat,
+        "ARTIFACT" => match value.split_once(':').and_then(|(name, data)| Some((name, decode_base64(data)?))) {
+            Some((name, chunk)) => Message::Artifact { name: name.to_string(), chunk },
+            None => Message::Unknown(message.to_string()),
+        },
+        "SCREENSHOT" if !value.is_empty() => Message::Screenshot(value.to_string()),
+        "MARK" => Message::Mark(Some(value.to_string()).filter(|label| !label.is_empty())),
+        "TEST_LIST" if !value.is_empty() => Message::TestListed(value.to_string()),
+        "TEST_START" if !value.is_empty() => Message::TestStarted(value.to_string()),
+        "TEST_PASS" if !value.is_empty() => Message::TestPassed(value.to_string()),
+        "TEST_FAIL" if !value.is_empty() => {
+            let (name, message) = value.split_once(' ').unwrap_or((value, ""));
+            let message = Some(message.to_string()).filter(|message| !message.is_empty());
+            Message::TestFailed { name: name.to_string(), message }
+        }
+        _ => Message::Unknown(message.to_string()),
+    })
+}
+
+pub struct Guest {
+    config: GuestProtocol,
+    artifacts_dir: PathBuf,
+    exit_code: Mutex<Option<i32>>,
+    last_heartbeat: Mutex<Instant>,
+    marks: Mutex<usize>,
+    tests: Mutex<GuestTests>,
+    artifacts: Mutex<HashSet<String>>,
+}
+
+#[derive(Default)]
+struct GuestTests {
+    listed: Vec<String>,
+    running: Option<(String, Instant)>,
+    results: Vec<TestResult>,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains('/') && !name.contains("..")
+}
+
+impl Guest {
+    pub fn new(config: GuestProtocol, artifacts_dir: impl Into<PathBuf>) -> Guest {
+        Guest {
+            config,
+            artifacts_dir: artifacts_dir.into(),
+            exit_code: Mutex::new(None),
+            last_heartbeat: Mutex::new(Instant::now()),
+            marks: Mutex::new(0),
+            tests: Mutex::new(GuestTests::default()),
+            artifacts: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn listed_tests(&self) -> Vec<String> {
+        self.tests.lock().unwrap().listed.clone()
+    }
+
+    pub fn test_results(&self) -> Vec<TestResult> {
+        let tests = self.tests.lock().unwrap();
+        let mut results = tests.results.clone();
+        if let Some((name, started)) = &tests.running {
+            results.push(TestResult {
+                name: name.clone(),
+                status: TestStatus::Failed,
+                duration_ms: millis(started.elapsed()),
+                message: Some("guest stopped before the test finished".to_string()),
+            });
+        }
+        results
+    }
+
+    fn finish_test(&self, name: String, status: TestStatus, message: Option<String>) {
+        let mut tests = self.tests.lock().unwrap();
+        let started = tests.running.take().filter(|(running, _)| *running == name).map(|(_, started)| started);
+        match (&status, &message) {
+            (TestStatus::Passed, _) => info!("Guest test {} passed", name),
+            (_, Some(message)) => warn!("Guest test {} failed: {}", name, message),
+            _ => warn!("Guest test {} failed", name),
+        }
+        let duration_ms = started.and_then(|started| millis(started.elapsed()));
+        tests.results.push(TestResult { name, status, duration_ms, message });
+    }
+
+    pub fn exit_code(&self) -> Option<i32> {
+        *self.exit_code.lock().unwrap()
+    }
+
+    pub fn handle(&self, line: &str, control: &Control, qmp: &Sender<ScheduledCommand>) {
+        match parse(line) {
+            Some(Message::Exit(code)) => {
+                info!("Guest requested exit with code {}", code);
+                *self.exit_code.lock().unwrap() = Some(code);
+                control.request_shutdown();
+            }
+            Some(Message::Heartbeat) => *self.last_heartbeat.lock().unwrap() = Instant::now(),
+            Some(Message::Artifact { name, chunk }) => {
+                if let Err(e) = self.append_artifact(&name, &chunk) {
+                    warn!("Failed to store guest artifact {}: {}", name, e);
+                }
+            }
+            Some(Message::Screenshot(name)) if valid_name(&name) => {
+                info!("Guest requested screenshot {}", name);
+                self.capture(qmp, &name, false);
+            }
+            Some(Message::Screenshot(name)) => warn!("Invalid guest screenshot name {}", name),
+            Some(Message::Mark(label)) => {
+                let index = {
+                    let mut marks = self.marks.lock().unwrap();
+                    *marks += 1;
+                    *marks
+                };
+                let prefix = match label.as_deref().filter(|label| valid_name(label)) {
+                    Some(label) => format!("mark-{}-{}", index, label),
+                    None => format!("mark-{}", index),
+                };
+                info!("Guest mark {}{}", index, label.map(|label| format!(" ({})", label)).unwrap_or_default());
+                self.capture(qmp, &prefix, true);
+            }
+            Some(Message::TestListed(name)) => self.tests.lock().unwrap().listed.push(name),
+            Some(Message::TestStarted(name)) => {
+                info!("Guest test {} started", name);
+                self.tests.lock().unwrap().running = Some((name, Instant::now()));
+            }
+            Some(Message::TestPassed(name)) => self.finish_test(name, TestStatus::Passed, None),
+            Some(Message::TestFailed { name, message }) => self.finish_test(name, TestStatus::Failed, message),
+            Some(Message::Unknown(message)) => warn!("Unknown guest message {}", message),
+            None => {}
+        }
+    }
+
+    fn capture(&self, qmp: &Sender<ScheduledCommand>, prefix: &str, registers: bool) {
+        let arguments = json!({
+            "dir": self.artifacts_dir.display().to_string(), "prefix": prefix, "registers": registers, "pause": true,
+        });
+        let _ = qmp.send(ScheduledCommand::now(qmp::CAPTURE_COMMAND, arguments));
+    }
+
+    fn append_artifact(&self, name: &str, chunk: &[u8]) -> Result<(), String> {
+        if !valid_name(name) {
+            return Err("invalid artifact name".to_string());
+        }
+        fs::create_dir_all(&self.artifacts_dir).map_err(|e| e.to_string())?;
+        // The first chunk of a run replaces whatever an earlier run left under the same name.
+        let first = self.artifacts.lock().unwrap().insert(name.to_string());
+        let mut options = OpenOptions::new();
+        options.create(true);
+        if first { options.write(true).truncate(true) } else { options.append(true) };
+        options.open(self.artifacts_dir.join(name))
+            .and_then(|mut file| file.write_all(chunk))
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn check(&self, control: &Control) {
+        let Some(timeout) = self.config.heartbeat_timeout_secs else {
+            return;
+        };
+        let elapsed = self.last_heartbeat.lock().unwrap().elapsed();
+        if elapsed > Duration::from_secs(timeout) && !control.kill_requested() {
+            error!("No guest heartbeat for {:?}, killing QEMU", elapsed);
+            control.request_kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fail_splits_name_and_message() {
+        assert_eq!(parse("##UEFAPI:TEST_FAIL=alloc pool exhausted after 3 pages"),
+                   Some(Message::TestFailed { name: "alloc".to_string(),
+                                              message: Some("pool exhausted after 3 pages".to_string()) }));
+        assert_eq!(parse("##UEFAPI:TEST_FAIL=alloc"),
+                   Some(Message::TestFailed { name: "alloc".to_string(), message: None }));
+    }
+
+    #[test]
+    fn unknown_keys_are_kept() {
+        assert_eq!(parse("##UEFAPI:FROB=1"), Some(Message::Unknown("FROB=1".to_string())));
+        assert_eq!(parse("##UEFAPI:TEST_START="), Some(Message::Unknown("TEST_START=".to_string())));
+        assert_eq!(parse("plain console output"), None);
+    }
+
+    #[test]
+    fn exit_needs_a_number() {
+        assert_eq!(parse("boot ok ##UEFAPI:EXIT=3\r"), Some(Message::Exit(3)));
+        assert_eq!(parse("##UEFAPI:EXIT=-1"), Some(Message::Exit(-1)));
+        assert_eq!(parse("##UEFAPI:EXIT=done"), Some(Message::Unknown("EXIT=done".to_string())));
+    }
+
+    #[test]
+    fn artifact_chunks_decode_base64() {
+        assert_eq!(parse("##UEFAPI:ARTIFACT=log.txt:aGVsbG8="),
+                   Some(Message::Artifact { name: "log.txt".to_string(), chunk: b"hello".to_vec() }));
+        assert_eq!(parse("##UEFAPI:ARTIFACT=log.txt:a$b"), Some(Message::Unknown("ARTIFACT=log.txt:a$b".to_string())));
+        assert_eq!(parse("##UEFAPI:ARTIFACT=log.txt"), Some(Message::Unknown("ARTIFACT=log.txt".to_string())));
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foob", &[0, 0xff, 0x10, 0x80]] {
+            assert_eq!(decode_base64(&encode_base64(data)).unwrap(), data);
+        }
+        assert_eq!(encode_base64(b"foob"), "Zm9vYg==");
+    }
+}
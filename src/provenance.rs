@@ -0,0 +1,157 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::firmware::Firmware;
+use crate::outcome::RunOutcome;
+use crate::{version, RunnerConfig, Workspace};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvenanceFormat {
+    Report,
+    Slsa,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Provenance {
+    #[serde(default)]
+    pub git_commit: Option<String>,
+    #[serde(default)]
+    pub git_dirty: bool,
+    #[serde(default)]
+    pub cargo_lock_sha256: Option<String>,
+    #[serde(default)]
+    pub rustc_version: Option<String>,
+    #[serde(default)]
+    pub firmware: Vec<FileDigest>,
+    #[serde(default)]
+    pub staged: Vec<FileDigest>,
+    #[serde(default)]
+    pub esp_image: Option<FileDigest>,
+}
+
+pub fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn digest(path: &Path, name: String) -> Option<FileDigest> {
+    match sha256(path) {
+        Ok(sha256) => Some(FileDigest { path: name, sha256 }),
+        Err(e) => {
+            warn!("Failed to hash {} for provenance: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn command(program: &str, args: &[&str], dir: &str) -> Option<String> {
+    let output = Command::new(program).args(args).current_dir(dir).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn cargo_lock(project: &str) -> Option<PathBuf> {
+    let project = fs::canonicalize(project).ok()?;
+    project.ancestors().map(|dir| dir.join("Cargo.lock")).find(|lock| lock.is_file())
+}
+
+fn staged(root: &Path, dir: &Path, digests: &mut Vec<FileDigest>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            staged(root, &path, digests)?;
+        } else {
+            let name = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            digests.extend(digest(&path, name));
+        }
+    }
+    Ok(())
+}
+
+impl Provenance {
+    pub fn collect(config: &RunnerConfig, workspace: &Workspace, firmware: &Firmware) -> Provenance {
+        let project = config.project_path.as_str();
+        let git_commit = command("git", &["rev-parse", "HEAD"], project);
+        let git_dirty = git_commit.is_some()
+            && command("git", &["status", "--porcelain"], project).is_some_and(|status| !status.is_empty());
+        let firmware = [Some(&firmware.code), firmware.vars.as_ref()].into_iter().flatten()
+            .filter_map(|path| digest(path, path.display().to_string()))
+            .collect();
+        let mut staged_files = Vec::new();
+        if let Err(e) = staged(workspace.work_dir(), workspace.work_dir(), &mut staged_files) {
+            warn!("Failed to hash staged files for provenance: {}", e);
+        }
+        staged_files.extend(config.boot_image.as_ref().and_then(|image| digest(Path::new(image), image.clone())));
+        let esp_image = config.esp_image.as_ref()
+            .and_then(|_| digest(&workspace.esp_image(), "esp.img".to_string()));
+        Provenance {
+            git_commit,
+            git_dirty,
+            cargo_lock_sha256: cargo_lock(project).and_then(|lock| sha256(&lock).ok()),
+            rustc_version: command("rustc", &["--version"], project),
+            firmware,
+            staged: staged_files,
+            esp_image,
+        }
+    }
+
+    fn slsa(&self, config: &RunnerConfig, outcome: &RunOutcome) -> Value {
+        let sha256 = |file: &FileDigest| json!({ "name": file.path, "digest": { "sha256": file.sha256 } });
+        let mut dependencies = self.firmware.iter().map(sha256).collect::<Vec<_>>();
+        if let Some(commit) = &self.git_commit {
+            dependencies.push(json!({ "name": config.project_path, "digest": { "gitCommit": commit } }));
+        }
+        if let Some(lock) = &self.cargo_lock_sha256 {
+            dependencies.push(json!({ "name": "Cargo.lock", "digest": { "sha256": lock } }));
+        }
+        json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": self.staged.iter().chain(&self.esp_image).map(sha256).collect::<Vec<_>>(),
+            "predicateType": "https://slsa.dev/provenance/v1",
+            "predicate": {
+                "buildDefinition": {
+                    "buildType": "https://github.com/aurantiaco-sucus/uefapi-runner/run@v1",
+                    "externalParameters": {
+                        "build_cmd": config.build_cmd,
+                        "arch": config.arch(),
+                        "seed": outcome.seed,
+                        "tags": outcome.tags,
+                    },
+                    "internalParameters": { "git_dirty": self.git_dirty, "rustc": self.rustc_version },
+                    "resolvedDependencies": dependencies,
+                },
+                "runDetails": {
+                    "builder": { "id": format!("uefapi-runner@{}", version::CURRENT) },
+                    "byproducts": [{ "name": "outcome", "content": outcome }],
+                },
+            },
+        })
+    }
+
+    pub fn write(&self, format: ProvenanceFormat, config: &RunnerConfig, outcome: &RunOutcome) -> Result<(), String> {
+        let dir = Path::new(&config.artifacts_dir);
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let (path, value) = match format {
+            ProvenanceFormat::Report => (dir.join("provenance.json"), json!(self)),
+            ProvenanceFormat::Slsa => (dir.join("provenance.intoto.json"), self.slsa(config, outcome)),
+        };
+        let json = serde_json::to_string_pretty(&value).expect("Failed to serialize provenance");
+        fs::write(&path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        info!("Provenance written to {}", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::RunnerConfig;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ProvisionConfig {
+    pub binary: String,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub once: bool,
+}
+
+fn default_timeout() -> u64 {
+    300
+}
+
+impl ProvisionConfig {
+    pub fn validate(&self, config: &RunnerConfig) -> Result<(), String> {
+        if !Path::new(&self.binary).is_file() {
+            return Err(format!("provision binary {} does not exist", self.binary));
+        }
+        if self.timeout_secs == 0 {
+            return Err("provision timeout_secs must be at least 1".to_string());
+        }
+        if config.boot_image.is_some() {
+            return Err("provision boots from the staged ESP and cannot be combined with boot_image".to_string());
+        }
+        if self.once && !config.persist_nvram {
+            return Err("provision once needs persist_nvram to keep the provisioned state".to_string());
+        }
+        if self.once && config.base_overlays {
+            return Err("provision once cannot be combined with base_overlays, which start every run \
+                        from fresh disks".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn stamp(vars: &Path) -> PathBuf {
+        vars.with_extension("provisioned")
+    }
+
+    pub fn done(&self, vars: Option<&Path>) -> bool {
+        self.once && vars.and_then(|vars| fs::read_to_string(Self::stamp(vars)).ok())
+            .is_some_and(|stamp| stamp.trim() == self.binary)
+    }
+
+    pub fn config(&self, config: &RunnerConfig) -> RunnerConfig {
+        let mut config = config.clone();
+        let artifacts_dir = Path::new(&config.artifacts_dir).join("provision");
+        config.log_serial = true;
+        config.log_path = artifacts_dir.join("serial.log").display().to_string();
+        config.artifacts_dir = artifacts_dir.display().to_string();
+        config.esp_image = None;
+        config.base_overlays = false;
+        config.timeout_secs = Some(self.timeout_secs);
+        config.idle_timeout_secs = None;
+        config.serial_input = None;
+        config.serial_input_prompt = None;
+        config.hotplug.clear();
+        config.clock.clear();
+        config.qmp = None;
+        config.gdb = None;
+        config.linux = None;
+        config.audio = None;
+        config.guest_protocol = None;
+        config.counters = None;
+        config.memcheck = None;
+        config.plugins.clear();
+        config.extensions.clear();
+        config.expect_efi_status = None;
+        config.verdict_cmd = None;
+        config.log_transforms.clear();
+        config.provenance = None;
+        config.test = None;
+        config.matrix = None;
+        config.provision = None;
+        config
+    }
+}
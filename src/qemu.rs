@@ -0,0 +1,628 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::arch::Arch;
+use crate::firmware::{Firmware, FirmwareMode};
+use crate::handle::{Control, RunState};
+use crate::hooks::Hooks;
+use crate::limits::{Cgroup, CgroupLimits};
+use crate::network::Network;
+use crate::protocol::Guest;
+use crate::qmp::{EventLog, ScheduledCommand};
+use crate::redact::Redactor;
+use crate::serial::LogFollower;
+use crate::serial_input::SerialInput;
+use crate::shaping::SerialShaper;
+use crate::watchdog::WatchdogModel;
+use crate::{input, network, output, passthrough, plugins, preset, privilege, qmp, reaper, removable, seed,
+            RunnerConfig, Workspace};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Display {
+    None,
+    Gtk,
+    Sdl,
+    Vnc,
+}
+
+impl Display {
+    pub fn qemu_args(&self) -> [&'static str; 2] {
+        match self {
+            Display::None => ["-display", "none"],
+            Display::Gtk => ["-display", "gtk"],
+            Display::Sdl => ["-display", "sdl"],
+            Display::Vnc => ["-display", "vnc=localhost:0"],
+        }
+    }
+}
+
+pub fn memory_args(config: &RunnerConfig) -> Vec<String> {
+    match (&config.memory, &config.maxmem) {
+        (Some(memory), Some(maxmem)) =>
+            vec!["-m".to_string(), format!("size={},slots=2,maxmem={}", memory, maxmem)],
+        (Some(memory), None) => vec!["-m".to_string(), memory.clone()],
+        (None, Some(maxmem)) => {
+            warn!("maxmem {} ignored without memory", maxmem);
+            Vec::new()
+        }
+        (None, None) => Vec::new(),
+    }
+}
+
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+const QUIT_GRACE: Duration = Duration::from_secs(3);
+const CAPTURE_GRACE: Duration = Duration::from_secs(3);
+const STARTUP_WINDOW: Duration = Duration::from_secs(2);
+const STDERR_LINES: usize = 64;
+const EVENT_DRAIN: Duration = Duration::from_secs(1);
+const TRANSIENT_ERRORS: &[&str] = &[
+    "Address already in use",
+    "Failed to bind socket",
+    "vhost",
+    "Device or resource busy",
+    "Could not access KVM kernel module",
+    "failed to initialize kvm",
+];
+
+pub struct Launch {
+    pub cmd: Command,
+    qmp_port: u16,
+    qmp_commands: Vec<ScheduledCommand>,
+    serial_log: Option<PathBuf>,
+    network: Option<Network>,
+    cgroup: Option<CgroupLimits>,
+    redactor: Redactor,
+    panic_dir: Option<PathBuf>,
+    serial_input: Option<SerialInput>,
+    shaper: Option<SerialShaper>,
+    exit_capture: Option<ExitCapture>,
+}
+
+struct ExitCapture {
+    dir: PathBuf,
+    at_exit: bool,
+    on_kill: bool,
+}
+
+#[cfg(windows)]
+const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+pub fn new_process_group(cmd: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    #[cfg(windows)]
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    cmd
+}
+
+pub fn cmdline(cmd: &Command) -> Vec<String> {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+pub fn firmware_log(config: &RunnerConfig) -> Option<PathBuf> {
+    config.firmware_log.then(|| PathBuf::from(&config.artifacts_dir).join("firmware.log"))
+}
+
+pub fn debug_log(config: &RunnerConfig) -> Option<PathBuf> {
+    config.qemu_debug_log.then(|| PathBuf::from(&config.artifacts_dir).join("qemu-debug.log"))
+}
+
+fn boot_image_drive(image: &str) -> String {
+    let lower = image.to_ascii_lowercase();
+    if lower.ends_with(".iso") {
+        format!("format=raw,media=cdrom,file={}", image)
+    } else if lower.ends_with(".qcow2") {
+        format!("format=qcow2,file={}", image)
+    } else {
+        format!("format=raw,file={}", image)
+    }
+}
+
+pub fn prepare_launch(config: &RunnerConfig, workspace: &Workspace, firmware: &Firmware,
+                      extra_args: &[String], hooks: &Hooks,
+                      dry_run: bool) -> Result<Launch, String> {
+    let (work_dir, run_dir) = (workspace.work_dir(), workspace.run_dir());
+    let wrapper = config.limits.as_ref().map(|limits| limits.wrapper()).unwrap_or_default();
+    let mut cmd = match wrapper.split_first() {
+        Some((program, args)) => {
+            let mut cmd = Command::new(program);
+            cmd.args(args).arg(&config.qemu_cmd);
+            cmd
+        }
+        None => Command::new(&config.qemu_cmd),
+    };
+    cmd.args(preset::machine_args(config));
+    match config.accel.as_deref() {
+        Some(accel) => {
+            if accel.starts_with("kvm") {
+                privilege::check_kvm();
+            }
+            cmd.args(["-accel", accel]);
+        }
+        None if config.qemu_debug_log => info!("Using TCG, QEMU only logs interrupts under emulation"),
+        None if plugins::log_path(config).is_some() => info!("Using TCG, plugins only run under emulation"),
+        None => match config.arch().accel() {
+            "tcg" => {}
+            accel => {
+                if accel == "kvm" {
+                    privilege::check_kvm();
+                }
+                info!("Using {} acceleration, falling back to TCG if unavailable", accel);
+                cmd.args(["-accel", accel, "-accel", "tcg"]);
+            }
+        },
+    }
+    let cpu = config.cpu.as_deref()
+        .or(config.preset.and_then(|preset| preset.cpu()))
+        .or(config.arch().default_cpu());
+    if let Some(cpu) = cpu {
+        cmd.args(["-cpu", cpu]);
+    }
+    cmd.args(memory_args(config));
+    if let Some(smp) = config.smp {
+        cmd.arg("-smp").arg(smp.to_string());
+    }
+    if let Some(display) = config.display {
+        if display == Display::Vnc {
+            info!("Guest display on VNC at localhost:5900");
+        }
+        cmd.args(display.qemu_args());
+    }
+    if let Some(gpu) = &config.gpu {
+        cmd.args(gpu.qemu_args());
+    }
+    if let Some(devices) = &config.input {
+        cmd.args(input::qemu_args(devices, config.arch()));
+    }
+    if let Some(audio) = &config.audio {
+        if let Some(wav) = audio.capture(&config.artifacts_dir) {
+            fs::create_dir_all(&config.artifacts_dir)
+                .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+            info!("Capturing guest audio to {}", wav.display());
+        }
+        cmd.args(audio.qemu_args(&config.artifacts_dir));
+    }
+    if let Some(preset) = config.preset {
+        cmd.args(preset.extra_args(run_dir));
+    }
+    if let Some(numa) = &config.numa {
+        cmd.args(numa.qemu_args());
+    }
+    if let Some(secure_boot) = &config.secure_boot {
+        cmd.args(secure_boot.qemu_args(config.arch()));
+    }
+    if let Some(tpm) = &config.tpm {
+        cmd.args(tpm.qemu_args(run_dir, config.arch()));
+    }
+    match (config.firmware_mode, &firmware.vars) {
+        (FirmwareMode::Pflash, Some(vars)) => {
+            cmd.arg("-drive")
+                .arg(format!("if=pflash,format=raw,file={}", firmware.code.display()))
+                .arg("-drive")
+                .arg(format!("if=pflash,format=raw,file={}", vars.display()));
+        }
+        (FirmwareMode::Pflash, None) => {
+            warn!("firmware_mode is pflash but {} came without a vars file, booting it via -bios without \
+                   persistent variables", firmware.code.display());
+            cmd.arg("-bios").arg(&firmware.code);
+        }
+        (FirmwareMode::Bios, _) => {
+            info!("Booting firmware {} via -bios", firmware.code.display());
+            cmd.arg("-bios").arg(&firmware.code);
+        }
+    }
+    match (&config.boot_image, &config.esp_image) {
+        (Some(image), _) => cmd.arg("-drive").arg(boot_image_drive(image)),
+        (None, Some(_)) => cmd.arg("-drive").arg(format!("format=raw,file={}", workspace.esp_image().display())),
+        (None, None) => cmd.arg("-drive").arg(format!("format=raw,file=fat:rw:{}", work_dir.display())),
+    };
+    if let Some(linux) = &config.linux {
+        cmd.args(linux.qemu_args());
+    }
+    if let (Some(_), arch @ (Arch::Aarch64 | Arch::Riscv64)) = (firmware_log(config), config.arch()) {
+        warn!("Firmware debug log uses the x86 debug port, which {:?} machines do not have", arch);
+    } else if let Some(log) = firmware_log(config) {
+        fs::create_dir_all(&config.artifacts_dir)
+            .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+        let _ = fs::remove_file(&log);
+        cmd.arg("-debugcon")
+            .arg(format!("file:{}", log.display()))
+            .args(["-global", "isa-debugcon.iobase=0x402"]);
+    }
+    if let Some(log) = debug_log(config) {
+        if let Some(accel) = config.accel.as_deref().filter(|accel| *accel != "tcg") {
+            warn!("QEMU debug log only records interrupts under TCG, not {}", accel);
+        }
+        fs::create_dir_all(&config.artifacts_dir)
+            .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+        let _ = fs::remove_file(&log);
+        cmd.args(["-d", "int,guest_errors,cpu_reset", "-D"]).arg(&log);
+    }
+    if let Some(log) = plugins::log_path(config) {
+        fs::create_dir_all(&config.artifacts_dir)
+            .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+        let _ = fs::remove_file(&log);
+        cmd.args(plugins::qemu_args(config));
+    }
+    if let Some(counters) = &config.counters {
+        cmd.args(counters.qemu_args());
+    }
+    for (index, disk) in config.disks.iter().enumerate() {
+        cmd.args(disk.qemu_args(index, run_dir)?);
+    }
+    cmd.args(removable::controller_args(&config.removables));
+    let mut qmp_commands = Vec::new();
+    for removable in &config.removables {
+        cmd.args(removable.qemu_args());
+        qmp_commands.extend(removable.qmp_commands());
+    }
+    let serial_log = if config.log_serial {
+        Some(PathBuf::from(&config.log_path))
+    } else {
+        let marker = config.linux.as_ref().is_some_and(|linux| linux.marker.is_some());
+        (config.stdio_serial || hooks.wants_serial() || config.guest_protocol.is_some() || marker
+            || config.expect_efi_status.is_some() || config.test.is_some() || config.idle_timeout_secs.is_some())
+            .then(|| run_dir.join("serial.log"))
+    };
+    for step in &config.hotplug {
+        if step.on_serial.is_some() && serial_log.is_none() {
+            warn!("Hotplug step {} waits for serial output, but the serial log is disabled", step.id);
+        }
+        cmd.args(step.qemu_args());
+        qmp_commands.push(step.qmp_command());
+    }
+    match config.seed {
+        Some(seed) => {
+            cmd.args(seed::qemu_args(seed, config.virtio_rng, run_dir)
+                .map_err(|e| format!("Failed to write seeded entropy: {}", e))?);
+        }
+        None if config.virtio_rng => {
+            cmd.args(["-object", "rng-random,id=rng0,filename=/dev/urandom", "-device", "virtio-rng-pci,rng=rng0"]);
+        }
+        None => {}
+    }
+    if let Some(test) = &config.test {
+        cmd.args(test.qemu_args(config.arch()));
+    }
+    if let Some(watchdog) = &config.watchdog {
+        if watchdog.model == WatchdogModel::Ib700 && config.arch() != Arch::X86_64 {
+            warn!("The ib700 watchdog is an ISA device and only exists on x86 machines");
+        }
+        cmd.args(watchdog.qemu_args());
+    }
+    if let Some(base) = &config.rtc_base {
+        cmd.arg("-rtc").arg(format!("base={}", base.trim_end_matches('Z')));
+    }
+    if !config.clock.is_empty() && config.arch() != Arch::X86_64 {
+        warn!("Clock steps write the CMOS RTC, which {:?} machines do not have", config.arch());
+    }
+    for step in &config.clock {
+        if step.on_serial.is_some() && serial_log.is_none() {
+            warn!("Clock step waits for serial output, but the serial log is disabled");
+        }
+        qmp_commands.push(step.qmp_command());
+    }
+    if let Some(gpu) = config.gpu.as_ref().filter(|gpu| !gpu.screenshots.is_empty()) {
+        if gpu.screenshots.iter().any(|shot| shot.on_serial.is_some()) && serial_log.is_none() {
+            warn!("Screenshots wait for serial output, but the serial log is disabled");
+        }
+        fs::create_dir_all(&config.artifacts_dir)
+            .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+        qmp_commands.extend(gpu.qmp_commands(Path::new(&config.artifacts_dir)));
+    }
+    if let Some(qmp) = &config.qmp {
+        if qmp.monitor.iter().any(|command| command.on_serial.is_some()) && serial_log.is_none() {
+            warn!("Monitor commands wait for serial output, but the serial log is disabled");
+        }
+        fs::create_dir_all(&config.artifacts_dir)
+            .map_err(|e| format!("Failed to create {}: {}", config.artifacts_dir, e))?;
+        let _ = fs::remove_file(Path::new(&config.artifacts_dir).join("monitor.log"));
+        qmp_commands.extend(qmp.qmp_commands(Path::new(&config.artifacts_dir)));
+    }
+    let exit_capture = config.qmp.as_ref().filter(|qmp| qmp.screendump_at_exit || qmp.dump_on_kill)
+        .map(|qmp| ExitCapture { dir: PathBuf::from(&config.artifacts_dir), at_exit: qmp.screendump_at_exit,
+                                 on_kill: qmp.dump_on_kill });
+    if let Some(gdb) = &config.gdb {
+        if gdb.wait {
+            info!("QEMU will wait for a debugger on gdbstub localhost:{}", gdb.port);
+        } else {
+            info!("gdbstub listening on localhost:{}", gdb.port);
+        }
+        cmd.args(gdb.qemu_args());
+    }
+    let panic_dir = config.pvpanic.then(|| PathBuf::from(&config.artifacts_dir));
+    if config.pvpanic {
+        let device = if config.arch() == Arch::X86_64 { "pvpanic" } else { "pvpanic-pci" };
+        cmd.args(["-device", device, "-action", "panic=pause"]);
+    }
+    let qmp_port = qmp::allocate_port()
+        .map_err(|e| format!("Failed to allocate QMP port: {}", e))?;
+    cmd.args(qmp::qemu_args(qmp_port));
+    cmd.stdout(config.output.serial.stdio());
+    let serial_input = match &config.serial_input {
+        Some(path) => Some(SerialInput {
+            socket: run_dir.join("console.sock"),
+            script: fs::read_to_string(path).map_err(|e| format!("Failed to read serial input {}: {}", path, e))?,
+            prompt: config.serial_input_prompt.clone(),
+            echo: config.stdio_serial.then_some(config.output.serial),
+        }),
+        None => None,
+    };
+    let serial = serial_input.is_some() || config.stdio_serial || serial_log.is_some();
+    let shaper = config.serial_shaping.as_ref().filter(|_| serial).map(|shaping| SerialShaper {
+        socket: run_dir.join("serial.sock"),
+        shaping: shaping.clone(),
+        seed: shaping.seed.or(config.seed).unwrap_or_default(),
+        log: serial_log.clone(),
+        echo: (config.stdio_serial && serial_input.is_none()).then_some(config.output.serial),
+        console: serial_input.as_ref().map(|input| input.socket.clone()),
+    });
+    if serial {
+        let backend = match (&shaper, &serial_input) {
+            (Some(shaper), _) => format!("socket,path={},server=on,wait=on", shaper.socket.display()),
+            (None, Some(input)) => format!("socket,path={},server=on,wait=off", input.socket.display()),
+            (None, None) if config.stdio_serial => "stdio".to_string(),
+            (None, None) => "null".to_string(),
+        };
+        let mut chardev = format!("{},id=char0", backend);
+        if let Some(log) = serial_log.as_ref().filter(|_| shaper.is_none()) {
+            chardev.push_str(&format!(",logfile={}", log.display()));
+        }
+        cmd.arg("-chardev")
+            .arg(chardev)
+            .args(["-serial", "chardev:char0"]);
+    }
+    let network = match &config.network {
+        Some(network) => Some(network::setup(network, config.escalation, &config.artifacts_dir, dry_run)
+            .map_err(|e| format!("Network setup failed: {}", e))?),
+        None => None,
+    };
+    if let Some(network) = &network {
+        cmd.args(&network.args);
+        if let Some(tap) = &network.tap {
+            info!("Guest NIC attached to tap device {}", tap.name);
+        }
+    }
+    privilege::check_devices(config)?;
+    cmd.args(passthrough::qemu_args(&config.chardev, &config.device, &config.artifacts_dir)?);
+    cmd.args(extra_args);
+    cmd.args(&config.extra_qemu_args);
+    if !config.stdio_serial {
+        // Keep terminal signals away from QEMU so Ctrl-C reaches the runner, which powers the guest down.
+        new_process_group(&mut cmd);
+    }
+    let cgroup = config.limits.as_ref().and_then(|limits| limits.cgroup.clone());
+    Ok(Launch { cmd, qmp_port, qmp_commands, serial_log, network, cgroup, redactor: Redactor::new(config),
+                panic_dir, serial_input, shaper, exit_capture })
+}
+
+pub struct Running {
+    child: Child,
+    pub pid: u32,
+    qmp: Sender<ScheduledCommand>,
+    controller: JoinHandle<()>,
+    events: EventLog,
+    serial_log: Option<PathBuf>,
+    stderr: Arc<Mutex<Vec<String>>>,
+    pub network: Option<Network>,
+    pub cgroup: Option<Cgroup>,
+    exit_capture: Option<ExitCapture>,
+    captured: Arc<AtomicUsize>,
+    started: Instant,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            warn!("Killing leftover QEMU (pid {})", self.pid);
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        reaper::untrack(self.pid);
+    }
+}
+
+impl Launch {
+    pub fn serial_log(&self) -> Option<&Path> {
+        self.serial_log.as_deref()
+    }
+
+    pub fn cmdline(&self) -> Vec<String> {
+        cmdline(&self.cmd).iter().map(|arg| self.redactor.redact(arg)).collect()
+    }
+
+    pub fn spawn(mut self, hooks: &Hooks, control: &Control) -> Result<Running, String> {
+        let cmdline = self.cmdline();
+        let mut child = self.cmd.stderr(Stdio::piped()).spawn()
+            .map_err(|e| format!("Failed to run QEMU: {}", e))?;
+        info!("QEMU started");
+        let started = Instant::now();
+        let pid = child.id();
+        let cgroup = match &self.cgroup {
+            Some(limits) => match Cgroup::attach(limits, pid) {
+                Ok(cgroup) => Some(cgroup),
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(e);
+                }
+            },
+            None => None,
+        };
+        let stderr = Arc::new(Mutex::new(Vec::new()));
+        if let Some(pipe) = child.stderr.take() {
+            let stderr = stderr.clone();
+            thread::spawn(move || {
+                for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+                    output::runner_line(&line);
+                    let mut stderr = stderr.lock().unwrap();
+                    if stderr.len() < STDERR_LINES {
+                        stderr.push(line);
+                    }
+                }
+            });
+        }
+        if let Some(shaper) = self.shaper {
+            if let Err(e) = shaper.spawn() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(e);
+            }
+        }
+        if let Some(input) = self.serial_input {
+            input.spawn();
+        }
+        reaper::track(pid, "QEMU");
+        control.set_state(RunState::Running { pid });
+        hooks.qemu_spawn(pid, &cmdline);
+        let events = EventLog::default();
+        let captured = Arc::new(AtomicUsize::new(0));
+        let (qmp, controller) = qmp::spawn_controller(self.qmp_port, self.qmp_commands, self.serial_log.clone(),
+                                                      events.clone(), self.panic_dir, captured.clone());
+        Ok(Running { child, pid, qmp, controller, events, serial_log: self.serial_log,
+                     stderr, network: self.network, cgroup, exit_capture: self.exit_capture, captured,
+                     started })
+    }
+}
+
+impl Running {
+    pub fn commands(&self) -> Sender<ScheduledCommand> {
+        self.qmp.clone()
+    }
+
+    pub fn events(&self) -> EventLog {
+        self.events.clone()
+    }
+
+    pub fn started(&self) -> Instant {
+        self.started
+    }
+
+    pub fn transient_failure(&mut self) -> Option<String> {
+        let start = Instant::now();
+        while start.elapsed() < STARTUP_WINDOW {
+            match self.child.try_wait() {
+                Ok(Some(status)) if !status.success() => {
+                    thread::sleep(Duration::from_millis(100));
+                    return self.stderr.lock().unwrap().iter()
+                        .find(|line| TRANSIENT_ERRORS.iter().any(|error| line.contains(error)))
+                        .cloned();
+                }
+                Ok(Some(_)) | Err(_) => return None,
+                Ok(None) => thread::sleep(Duration::from_millis(50)),
+            }
+        }
+        None
+    }
+
+    pub fn monitor(mut self, hooks: &Hooks, control: &Control,
+                   guest: Option<&Guest>) -> Result<ExitStatus, String> {
+        let done = AtomicBool::new(false);
+        let serial_log = self.serial_log.take();
+        let qmp = self.qmp.clone();
+        thread::scope(|scope| {
+            if let Some(log) = serial_log.as_ref().filter(|_| hooks.wants_serial() || guest.is_some()) {
+                let done = &done;
+                scope.spawn(move || {
+                    let mut follower = LogFollower::new(log);
+                    loop {
+                        let finished = done.load(Ordering::Acquire);
+                        for line in follower.poll() {
+                            hooks.serial_line(&line);
+                            if let Some(guest) = guest {
+                                guest.handle(&line, control, &qmp);
+                            }
+                        }
+                        if let Some(guest) = guest {
+                            guest.check(control);
+                        }
+                        if finished {
+                            break;
+                        }
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                });
+            }
+            let status = self.supervise(control);
+            done.store(true, Ordering::Release);
+            let start = Instant::now();
+            while !self.controller.is_finished() && start.elapsed() < EVENT_DRAIN {
+                thread::sleep(Duration::from_millis(10));
+            }
+            status
+        }).map_err(|e| format!("Failed to wait for QEMU: {}", e))
+    }
+
+    fn capture(&self, prefix: &str, registers: bool) {
+        let Some(capture) = &self.exit_capture else {
+            return;
+        };
+        info!("Capturing the guest {} into {}", if registers { "state" } else { "screen" }, capture.dir.display());
+        let before = self.captured.load(Ordering::Acquire);
+        let arguments = json!({ "dir": capture.dir.display().to_string(), "prefix": prefix, "registers": registers });
+        let _ = self.qmp.send(ScheduledCommand::now(qmp::CAPTURE_COMMAND, arguments));
+        let start = Instant::now();
+        while self.captured.load(Ordering::Acquire) == before && start.elapsed() < CAPTURE_GRACE
+            && !self.controller.is_finished() {
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn supervise(&mut self, control: &Control) -> io::Result<ExitStatus> {
+        let mut shutdown_sent: Option<Instant> = None;
+        let mut quit_sent: Option<Instant> = None;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(status);
+            }
+            if control.kill_requested() {
+                if let Some(registers) = self.exit_capture.as_ref().map(|capture| capture.on_kill) {
+                    self.capture("kill", registers);
+                }
+                warn!("Killing QEMU");
+                self.child.kill()?;
+                return self.child.wait();
+            }
+            match (shutdown_sent, quit_sent) {
+                (None, _) if control.shutdown_requested() => {
+                    if self.exit_capture.as_ref().is_some_and(|capture| capture.at_exit) {
+                        self.capture("exit", false);
+                    }
+                    info!("Requesting guest shutdown");
+                    control.set_state(RunState::ShuttingDown { pid: self.pid });
+                    let _ = self.qmp.send(ScheduledCommand::now("system_powerdown", json!({})));
+                    shutdown_sent = Some(Instant::now());
+                }
+                (Some(sent), None) if sent.elapsed() > SHUTDOWN_GRACE => {
+                    warn!("Guest did not power down within {:?}, asking QEMU to quit", SHUTDOWN_GRACE);
+                    let _ = self.qmp.send(ScheduledCommand::now("quit", json!({})));
+                    quit_sent = Some(Instant::now());
+                }
+                (_, Some(sent)) if sent.elapsed() > QUIT_GRACE => {
+                    warn!("QEMU did not quit within {:?}, killing it", QUIT_GRACE);
+                    self.child.kill()?;
+                    return self.child.wait();
+                }
+                _ => {}
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::clock;
+use crate::serial::LogFollower;
+
+const READ_POLL: Duration = Duration::from_millis(10);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+pub const CAPTURE_COMMAND: &str = "x-runner-capture";
+pub const MONITOR_COMMAND: &str = "x-runner-monitor";
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MonitorCommand {
+    pub command: String,
+    #[serde(default)]
+    pub after_secs: Option<u64>,
+    #[serde(default)]
+    pub on_serial: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct QmpConfig {
+    #[serde(default)]
+    pub screendump_every_secs: Option<u64>,
+    #[serde(default)]
+    pub screendump_at_exit: bool,
+    #[serde(default)]
+    pub dump_on_kill: bool,
+    #[serde(default)]
+    pub monitor: Vec<MonitorCommand>,
+}
+
+impl QmpConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.screendump_every_secs == Some(0) {
+            return Err("qmp screendump_every_secs must be at least 1".to_string());
+        }
+        if self.monitor.iter().any(|command| command.after_secs.is_some() == command.on_serial.is_some()) {
+            return Err("qmp monitor command needs exactly one of after_secs or on_serial".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn qmp_commands(&self, dir: &Path) -> Vec<ScheduledCommand> {
+        let mut commands = Vec::new();
+        if let Some(secs) = self.screendump_every_secs {
+            commands.push(ScheduledCommand {
+                trigger: Trigger::After(Duration::from_secs(secs)),
+                command: CAPTURE_COMMAND.to_string(),
+                arguments: json!({ "dir": dir.display().to_string(), "prefix": "screen", "every": secs }),
+            });
+        }
+        let log = dir.join("monitor.log");
+        for monitor in &self.monitor {
+            let trigger = match (&monitor.after_secs, &monitor.on_serial) {
+                (Some(secs), _) => Trigger::After(Duration::from_secs(*secs)),
+                (None, Some(marker)) => Trigger::Serial(marker.clone()),
+                (None, None) => Trigger::After(Duration::ZERO),
+            };
+            let arguments = json!({ "command-line": monitor.command, "log": log.display().to_string() });
+            commands.push(ScheduledCommand { trigger, command: MONITOR_COMMAND.to_string(), arguments });
+        }
+        commands
+    }
+}
+
+pub type EventLog = Arc<Mutex<Vec<Value>>>;
+
+pub struct Qmp {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    pending: Vec<u8>,
+    pub events: EventLog,
+}
+
+fn would_block(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+pub fn allocate_port() -> io::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+pub fn qemu_args(port: u16) -> Vec<String> {
+    vec!["-qmp".to_string(), format!("tcp:127.0.0.1:{},server=on,wait=off", port)]
+}
+
+impl Qmp {
+    pub fn connect(port: u16, timeout: Duration) -> io::Result<Qmp> {
+        let start = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(_) if start.elapsed() < timeout => {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let writer = stream.try_clone()?;
+        stream.set_read_timeout(Some(READ_POLL))?;
+        let mut qmp = Qmp { reader: BufReader::new(stream), writer, pending: Vec::new(), events: EventLog::default() };
+        qmp.wait_message(REPLY_TIMEOUT)?;
+        qmp.execute("qmp_capabilities", json!({}))
+            .map_err(io::Error::other)?;
+        Ok(qmp)
+    }
+
+    fn read_message(&mut self) -> io::Result<Value> {
+        if self.reader.read_until(b'\n', &mut self.pending)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "QMP connection closed"));
+        }
+        if !self.pending.ends_with(b"\n") {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let message = serde_json::from_slice(&self.pending).map_err(io::Error::other);
+        self.pending.clear();
+        message
+    }
+
+    fn wait_message(&mut self, timeout: Duration) -> io::Result<Value> {
+        let start = Instant::now();
+        loop {
+            match self.read_message() {
+                Err(e) if would_block(&e) && start.elapsed() < timeout => continue,
+                result => return result,
+            }
+        }
+    }
+
+    pub fn poll_events(&mut self) -> io::Result<()> {
+        loop {
+            match self.read_message() {
+                Ok(message) if message.get("event").is_some() => self.events.lock().unwrap().push(message),
+                Ok(_) => {}
+                Err(e) if would_block(&e) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub fn execute(&mut self, command: &str, arguments: Value) -> Result<Value, String> {
+        let request = json!({ "execute": command, "arguments": arguments });
+        writeln!(self.writer, "{}", request)
+            .map_err(|e| format!("Failed to send QMP {}: {}", command, e))?;
+        loop {
+            let message = self.wait_message(REPLY_TIMEOUT)
+                .map_err(|e| format!("Failed to read QMP reply to {}: {}", command, e))?;
+            if let Some(ret) = message.get("return") {
+                return Ok(ret.clone());
+            }
+            if let Some(err) = message.get("error") {
+                return Err(format!("QMP {} failed: {}", command, err));
+            }
+            if message.get("event").is_some() {
+                self.events.lock().unwrap().push(message);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Trigger {
+    After(Duration),
+    Serial(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ScheduledCommand {
+    pub trigger: Trigger,
+    pub command: String,
+    pub arguments: Value,
+}
+
+fn capture_state(qmp: &mut Qmp, dir: &Path, prefix: &str, registers: bool) -> bool {
+    if let Err(e) = fs::create_dir_all(dir) {
+        warn!("Failed to create {}: {}", dir.display(), e);
+        return false;
+    }
+    if registers {
+        match qmp.execute("human-monitor-command", json!({ "command-line": "info registers -a" })) {
+            Ok(registers) => {
+                let path = dir.join(format!("{}-registers.txt", prefix));
+                if let Err(e) = fs::write(&path, registers.as_str().unwrap_or_default()) {
+                    warn!("Failed to write {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => warn!("{}", e),
+        }
+    }
+    let screen = dir.join(format!("{}-screen.ppm", prefix));
+    if let Err(e) = qmp.execute("screendump", json!({ "filename": screen.display().to_string() })) {
+        warn!("{}", e);
+    }
+    true
+}
+
+fn monitor(qmp: &mut Qmp, arguments: &Value) -> Result<(), String> {
+    let command = arguments["command-line"].as_str().unwrap_or_default();
+    let reply = qmp.execute("human-monitor-command", json!({ "command-line": command }))?;
+    let log = arguments["log"].as_str().unwrap_or("monitor.log");
+    let mut file = OpenOptions::new().create(true).append(true).open(log)
+        .map_err(|e| format!("Failed to open {}: {}", log, e))?;
+    writeln!(file, "(qemu) {}\n{}", command, reply.as_str().unwrap_or_default())
+        .map_err(|e| format!("Failed to write {}: {}", log, e))
+}
+
+fn capture_panic(qmp: &mut Qmp, dir: &Path, serial_log: Option<&Path>) {
+    error!("Guest panicked, capturing state into {}", dir.display());
+    if !capture_state(qmp, dir, "panic", true) {
+        return;
+    }
+    if let Some(log) = serial_log {
+        if let Err(e) = fs::copy(log, dir.join("panic-serial.log")) {
+            warn!("Failed to copy serial log: {}", e);
+        }
+    }
+    if let Err(e) = qmp.execute("quit", json!({})) {
+        warn!("{}", e);
+    }
+}
+
+pub fn spawn_controller(port: u16, mut commands: Vec<ScheduledCommand>, serial_log: Option<PathBuf>,
+                        events: EventLog, panic_dir: Option<PathBuf>,
+                        captured: Arc<AtomicUsize>) -> (Sender<ScheduledCommand>, JoinHandle<()>) {
+    let (sender, receiver) = mpsc::channel::<ScheduledCommand>();
+    let handle = thread::spawn(move || {
+        let start = Instant::now();
+        let mut qmp = match Qmp::connect(port, Duration::from_secs(10)) {
+            Ok(qmp) => qmp,
+            Err(e) => {
+                error!("Failed to connect to QMP: {}", e);
+                return;
+            }
+        };
+        events.lock().unwrap().append(&mut qmp.events.lock().unwrap());
+        qmp.events = events;
+        let mut follower = serial_log.clone().map(LogFollower::new);
+        // Markers seen so far, each serial line is only scanned for the commands pending when it arrives.
+        let mut seen = HashSet::new();
+        loop {
+            match receiver.try_recv() {
+                Ok(command) => commands.push(command),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) if commands.is_empty() => return,
+                Err(TryRecvError::Disconnected) => {}
+            }
+            if qmp.poll_events().is_err() {
+                return;
+            }
+            if let Some(dir) = &panic_dir {
+                let panicked = qmp.events.lock().unwrap().iter().any(|event| event["event"] == "GUEST_PANICKED");
+                if panicked {
+                    capture_panic(&mut qmp, dir, serial_log.as_deref());
+                    return;
+                }
+            }
+            let mut partial = "";
+            if let Some(follower) = &mut follower {
+                for line in follower.poll() {
+                    for command in &commands {
+                        if let Trigger::Serial(marker) = &command.trigger {
+                            if !seen.contains(marker) && line.contains(marker.as_str()) {
+                                seen.insert(marker.clone());
+                            }
+                        }
+                    }
+                }
+                partial = follower.partial();
+            }
+            let (due, rest) = commands.into_iter().partition(|command| match &command.trigger {
+                Trigger::After(after) => start.elapsed() >= *after,
+                Trigger::Serial(marker) => seen.contains(marker) || partial.contains(marker.as_str()),
+            });
+            commands = rest;
+            for command in due {
+                info!("QMP {} {}", command.command, command.arguments);
+                let result = match command.command.as_str() {
+                    clock::RTC_COMMAND => clock::apply(&mut qmp, &command.arguments),
+                    MONITOR_COMMAND => monitor(&mut qmp, &command.arguments),
+                    CAPTURE_COMMAND => {
+                        let arguments = &command.arguments;
+                        let dir = Path::new(arguments["dir"].as_str().unwrap_or("."));
+                        let prefix = arguments["prefix"].as_str().unwrap_or("capture");
+                        let registers = arguments["registers"].as_bool().unwrap_or(false);
+                        if let Some(every) = arguments["every"].as_u64() {
+                            let elapsed = start.elapsed();
+                            capture_state(&mut qmp, dir, &format!("{}-{}s", prefix, elapsed.as_secs()), registers);
+                            let next = Trigger::After(elapsed + Duration::from_secs(every));
+                            commands.push(ScheduledCommand { trigger: next, ..command.clone() });
+                        } else if arguments["pause"].as_bool().unwrap_or(false) {
+                            let running = qmp.execute("query-status", json!({}))
+                                .is_ok_and(|status| status["running"] == true);
+                            if running {
+                                let _ = qmp.execute("stop", json!({}));
+                            }
+                            capture_state(&mut qmp, dir, prefix, registers);
+                            if running {
+                                let _ = qmp.execute("cont", json!({}));
+                            }
+                        } else {
+                            capture_state(&mut qmp, dir, prefix, registers);
+                            captured.fetch_add(1, Ordering::AcqRel);
+                        }
+                        Ok(())
+                    }
+                    _ => qmp.execute(&command.command, command.arguments).map(|_| ()),
+                };
+                if let Err(e) = result {
+                    warn!("{}", e);
+                }
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    });
+    (sender, handle)
+}
+
+impl ScheduledCommand {
+    pub fn now(command: &str, arguments: Value) -> Self {
+        ScheduledCommand {
+            trigger: Trigger::After(Duration::ZERO),
+            command: command.to_string(),
+            arguments,
+        }
+    }
+}
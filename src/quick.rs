@@ -0,0 +1,146 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use log::{error, info};
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::pipeline::{Context, Pipeline};
+use crate::{load_config, RunnerConfig};
+
+const MAX_DIFF_CELLS: usize = 16 * 1024 * 1024;
+
+fn run_one(config: RunnerConfig) -> bool {
+    let mut ctx = Context::new(config, Arc::new(Hooks::default()), Arc::new(Control::default()));
+    match Pipeline::standard().run(&mut ctx) {
+        Ok(()) => ctx.outcome.is_some_and(|outcome| {
+            info!("{} exited with {}", ctx.config.binary_path, outcome.describe());
+            outcome.success
+        }),
+        Err(e) => {
+            error!("{}: {}", ctx.config.binary_path, e);
+            false
+        }
+    }
+}
+
+pub fn diff(old: &[String], new: &[String]) -> Vec<String> {
+    if old.len() * new.len() > MAX_DIFF_CELLS {
+        let index = old.iter().zip(new).position(|(a, b)| a != b)
+            .unwrap_or(old.len().min(new.len()));
+        return vec![format!("@@ transcripts first differ at line {} (too long for a full diff)", index + 1)];
+    }
+    let mut lcs = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push(format!("+{}", new[j]));
+            j += 1;
+        } else {
+            out.push(format!("-{}", old[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+fn transcript(path: &Path) -> Vec<String> {
+    fs::read_to_string(path).unwrap_or_default().lines().map(|line| line.to_string()).collect()
+}
+
+pub fn run(inputs: &[String], compare: bool, parallel: bool) -> bool {
+    let base = if Path::new("uefapi-runner.toml").exists() {
+        load_config("uefapi-runner.toml")
+    } else {
+        RunnerConfig::default()
+    };
+    let dir = PathBuf::from(&base.artifacts_dir).join("quick");
+    fs::create_dir_all(&dir).expect("Failed to create quick run dir");
+    let configs = inputs.iter().enumerate().map(|(index, input)| {
+        let stem = Path::new(input).file_stem().map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let log = dir.join(format!("{}-{}.log", index, stem));
+        let _ = fs::remove_file(&log);
+        RunnerConfig {
+            binary_path: input.clone(),
+            auto_build: false,
+            move_binary: false,
+            stdio_serial: !parallel && !compare,
+            log_serial: true,
+            log_path: log.display().to_string(),
+            matrix: None,
+            ..base.clone()
+        }
+    }).collect::<Vec<_>>();
+    let logs = configs.iter().map(|config| PathBuf::from(&config.log_path)).collect::<Vec<_>>();
+    let results = if parallel {
+        let handles = configs.into_iter().map(|config| thread::spawn(move || run_one(config)))
+            .collect::<Vec<_>>();
+        handles.into_iter().map(|handle| handle.join().expect("Run thread panicked")).collect::<Vec<_>>()
+    } else {
+        configs.into_iter().map(run_one).collect::<Vec<_>>()
+    };
+    info!("Quick run results (serial logs in {}):", dir.display());
+    for (input, passed) in inputs.iter().zip(&results) {
+        info!("    {} {}", if *passed { "PASS" } else { "FAIL" }, input);
+    }
+    if compare && inputs.len() > 1 {
+        let baseline = transcript(&logs[0]);
+        for (input, log) in inputs.iter().zip(&logs).skip(1) {
+            let changes = diff(&baseline, &transcript(log));
+            if changes.is_empty() {
+                info!("Serial output of {} matches {}", input, inputs[0]);
+                continue;
+            }
+            println!("--- {}", inputs[0]);
+            println!("+++ {}", input);
+            for line in changes {
+                println!("{}", line);
+            }
+        }
+    }
+    results.iter().all(|passed| *passed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn identical_transcripts_have_no_diff() {
+        assert!(diff(&lines("a b c"), &lines("a b c")).is_empty());
+        assert!(diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn changed_lines_show_as_added_and_removed() {
+        assert_eq!(diff(&lines("a b c"), &lines("a x c")), ["+x", "-b"]);
+        assert_eq!(diff(&lines("a b"), &lines("a b c")), ["+c"]);
+        assert_eq!(diff(&lines("a b c"), &lines("b c")), ["-a"]);
+    }
+
+    #[test]
+    fn long_transcripts_report_the_first_difference() {
+        let old = (0..5000).map(|line| line.to_string()).collect::<Vec<_>>();
+        let mut new = old.clone();
+        new[1234] = "changed".to_string();
+        assert_eq!(diff(&old, &new), ["@@ transcripts first differ at line 1235 (too long for a full diff)"]);
+    }
+}
@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use crate::firmware::cache_root;
+
+const TERM_WAIT: Duration = Duration::from_secs(3);
+
+static HELPERS: Mutex<Vec<Helper>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Helper {
+    pid: u32,
+    start: u64,
+    program: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    runner: u32,
+    runner_start: u64,
+    helpers: Vec<Helper>,
+}
+
+fn dir() -> PathBuf {
+    cache_root().join("processes")
+}
+
+fn start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.parse().ok()
+}
+
+fn alive(pid: u32, start: u64) -> bool {
+    start_time(pid) == Some(start)
+}
+
+fn save(helpers: &[Helper]) {
+    let runner = process::id();
+    let path = dir().join(format!("{}.json", runner));
+    if helpers.is_empty() {
+        let _ = fs::remove_file(&path);
+        return;
+    }
+    // Without our own start time a later reap could not tell this runner from a reused pid.
+    let Some(runner_start) = start_time(runner) else {
+        debug!("Not recording helper processes, the runner start time is unknown");
+        return;
+    };
+    let state = State { runner, runner_start, helpers: helpers.to_vec() };
+    let json = serde_json::to_string(&state).expect("Failed to serialize process state");
+    let partial = path.with_extension("tmp");
+    let written = fs::create_dir_all(dir())
+        .and_then(|_| fs::write(&partial, json))
+        .and_then(|_| fs::rename(&partial, &path));
+    if let Err(e) = written {
+        debug!("Failed to record helper processes in {}: {}", path.display(), e);
+    }
+}
+
+pub fn track(pid: u32, program: &str) {
+    let Some(start) = start_time(pid) else {
+        return;
+    };
+    let mut helpers = HELPERS.lock().unwrap();
+    helpers.push(Helper { pid, start, program: program.to_string() });
+    save(&helpers);
+}
+
+pub fn untrack(pid: u32) {
+    let mut helpers = HELPERS.lock().unwrap();
+    helpers.retain(|helper| helper.pid != pid);
+    save(&helpers);
+}
+
+fn signal(pid: u32, signal: &str) {
+    let _ = Command::new("kill").args([signal, &pid.to_string()]).output();
+}
+
+fn stop(helper: &Helper) -> bool {
+    if !alive(helper.pid, helper.start) {
+        return false;
+    }
+    warn!("Stopping orphaned {} (pid {}) left behind by a crashed run", helper.program, helper.pid);
+    signal(helper.pid, "-TERM");
+    let start = Instant::now();
+    while alive(helper.pid, helper.start) && start.elapsed() < TERM_WAIT {
+        thread::sleep(Duration::from_millis(100));
+    }
+    if alive(helper.pid, helper.start) {
+        signal(helper.pid, "-KILL");
+    }
+    true
+}
+
+fn reap_file(path: &Path) -> usize {
+    let Some(state) = fs::read_to_string(path).ok()
+        .and_then(|text| serde_json::from_str::<State>(&text).ok()) else {
+        debug!("Skipping unreadable process state {}", path.display());
+        return 0;
+    };
+    if state.runner == process::id() || alive(state.runner, state.runner_start) {
+        return 0;
+    }
+    let reaped = state.helpers.iter().filter(|helper| stop(helper)).count();
+    let _ = fs::remove_file(path);
+    reaped
+}
+
+pub fn reap() -> usize {
+    let Ok(entries) = fs::read_dir(dir()) else {
+        return 0;
+    };
+    let reaped = entries.flatten().map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .map(|path| reap_file(&path))
+        .sum::<usize>();
+    if reaped > 0 {
+        info!("Reaped {} orphaned helper process(es)", reaped);
+    }
+    reaped
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+use std::time::Duration;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::disk::escape;
+use crate::privilege;
+use crate::qmp::{ScheduledCommand, Trigger};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemovableKind {
+    SdCard,
+    UsbStorage,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct RemovableConfig {
+    pub id: String,
+    pub kind: RemovableKind,
+    #[serde(default)]
+    pub media: Option<String>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub changes: Vec<MediaChange>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MediaChange {
+    pub after_secs: u64,
+    #[serde(default)]
+    pub media: Option<String>,
+}
+
+fn check_media(media: &str) {
+    let path = Path::new(media);
+    if path.starts_with("/dev") && !privilege::device_accessible(path, true) {
+        warn!("No read/write access to host device {}", media);
+        privilege::print_device_guidance(path);
+    }
+}
+
+pub fn controller_args(removables: &[RemovableConfig]) -> Vec<String> {
+    let mut args = Vec::new();
+    if removables.iter().any(|r| r.kind == RemovableKind::SdCard) {
+        args.extend(["-device".to_string(), "sdhci-pci,id=sdhci0".to_string()]);
+    }
+    if removables.iter().any(|r| r.kind == RemovableKind::UsbStorage) {
+        args.extend(["-device".to_string(), "qemu-xhci,id=xhci0".to_string()]);
+    }
+    args
+}
+
+impl RemovableConfig {
+    fn format(&self) -> &str {
+        self.format.as_deref().unwrap_or("raw")
+    }
+
+    pub fn qemu_args(&self) -> Vec<String> {
+        let drive_id = format!("{}-drive", self.id);
+        let mut drive = format!("if=none,id={}", drive_id);
+        if let Some(media) = &self.media {
+            check_media(media);
+            info!("Inserting {} into {}", media, self.id);
+            drive.push_str(&format!(",file={},format={}", escape(media), self.format()));
+        }
+        let device = match self.kind {
+            RemovableKind::SdCard => format!("sd-card,drive={},id={}", drive_id, self.id),
+            RemovableKind::UsbStorage =>
+                format!("usb-storage,drive={},id={},removable=on", drive_id, self.id),
+        };
+        vec!["-drive".to_string(), drive, "-device".to_string(), device]
+    }
+
+    pub fn qmp_commands(&self) -> Vec<ScheduledCommand> {
+        self.changes.iter().map(|change| {
+            let trigger = Trigger::After(Duration::from_secs(change.after_secs));
+            match &change.media {
+                Some(media) => {
+                    check_media(media);
+                    ScheduledCommand {
+                        trigger,
+                        command: "blockdev-change-medium".to_string(),
+                        arguments: json!({
+                            "id": self.id, "filename": media, "format": self.format(),
+                        }),
+                    }
+                }
+                None => ScheduledCommand {
+                    trigger,
+                    command: "eject".to_string(),
+                    arguments: json!({ "id": self.id, "force": true }),
+                },
+            }
+        }).collect()
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::outcome::{PhaseTimings, RunOutcome, SCHEMA_VERSION};
+use crate::pipeline::Context;
+
+const INLINE_SERIAL_LIMIT: u64 = 1 << 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    #[serde(default)]
+    pub label: Option<String>,
+    pub success: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub failed_step: Option<String>,
+    #[serde(default)]
+    pub qemu_cmdline: Vec<String>,
+    #[serde(default)]
+    pub timings: PhaseTimings,
+    #[serde(default)]
+    pub serial_log: Option<PathBuf>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    #[serde(default)]
+    pub outcome: Option<RunOutcome>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub schema_version: u32,
+    pub success: bool,
+    pub runs: Vec<RunReport>,
+}
+
+fn tail(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(INLINE_SERIAL_LIMIT))).ok()?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl RunReport {
+    pub fn from_context(label: Option<&str>, ctx: &Context, error: Option<&str>) -> RunReport {
+        let serial_log = ctx.keep_serial_log().unwrap_or_else(|e| {
+            warn!("{}", e);
+            None
+        }).filter(|log| log.exists());
+        RunReport {
+            label: label.map(str::to_string),
+            success: error.is_none() && ctx.outcome.as_ref().is_none_or(|outcome| outcome.success),
+            error: error.map(str::to_string),
+            failed_step: ctx.failed_step.map(str::to_string),
+            qemu_cmdline: ctx.qemu_cmdline.clone(),
+            timings: ctx.outcome.as_ref().map(|outcome| outcome.timings.clone()).unwrap_or_else(|| ctx.timings.clone()),
+            serial: serial_log.as_deref().filter(|_| ctx.config.report_inline_serial).and_then(tail),
+            serial_log,
+            outcome: ctx.outcome.clone(),
+        }
+    }
+}
+
+pub fn write(path: &Path, runs: Vec<RunReport>) -> Result<(), String> {
+    let report = Report { schema_version: SCHEMA_VERSION, success: runs.iter().all(|run| run.success), runs };
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let json = serde_json::to_string_pretty(&report).expect("Failed to serialize run report");
+    fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    info!("Run report written to {}", path.display());
+    Ok(())
+}
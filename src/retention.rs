@@ -0,0 +1,52 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Retention {
+    #[serde(default)]
+    pub keep: Option<usize>,
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+}
+
+impl Retention {
+    pub fn is_unlimited(&self) -> bool {
+        self.keep.is_none() && self.max_age_days.is_none()
+    }
+
+    pub fn prune(&self, dir: &Path) -> usize {
+        if self.is_unlimited() {
+            return 0;
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return 0;
+        };
+        let mut entries = entries.flatten()
+            .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(modified, _)| Reverse(*modified));
+        let max_age = self.max_age_days.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+        let now = SystemTime::now();
+        let mut removed = 0;
+        for (index, (modified, path)) in entries.into_iter().enumerate() {
+            let too_many = self.keep.is_some_and(|keep| index >= keep);
+            let too_old = max_age.is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+            if !too_many && !too_old {
+                continue;
+            }
+            let result = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+            match result {
+                Ok(()) => {
+                    debug!("Pruned {}", path.display());
+                    removed += 1;
+                }
+                Err(e) => warn!("Failed to prune {}: {}", path.display(), e),
+            }
+        }
+        removed
+    }
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::firmware::Firmware;
+use crate::handle::Control;
+use crate::hooks::Hooks;
+use crate::outcome::RunOutcome;
+use crate::pipeline::{Context, Pipeline};
+use crate::{read_config, read_profile, RunnerConfig};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RunError {
+    pub step: Option<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            Some(step) => write!(f, "{} step failed: {}", step, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Error for RunError {}
+
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    pub config: RunnerConfig,
+    pub outcome: Option<RunOutcome>,
+    pub serial_log: Option<PathBuf>,
+    pub artifacts_dir: PathBuf,
+    pub firmware: Option<Firmware>,
+    pub stage_timings: Vec<(&'static str, Duration)>,
+}
+
+impl RunReport {
+    pub fn success(&self) -> bool {
+        self.outcome.as_ref().is_some_and(|outcome| outcome.success)
+    }
+}
+
+pub struct Runner {
+    config: RunnerConfig,
+    hooks: Hooks,
+    control: Arc<Control>,
+    dry_run: bool,
+    extra_args: Vec<String>,
+}
+
+impl Runner {
+    pub fn new(config: RunnerConfig) -> Self {
+        Runner { config, hooks: Hooks::default(), control: Arc::new(Control::default()), dry_run: false,
+                 extra_args: Vec::new() }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, RunError> {
+        read_config(path).map(Runner::new).map_err(|message| RunError { step: None, message })
+    }
+
+    pub fn from_profile(path: &str, profile: &str) -> Result<Self, RunError> {
+        read_profile(path, Some(profile)).map(Runner::new).map_err(|message| RunError { step: None, message })
+    }
+
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn extra_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn control(&self) -> Arc<Control> {
+        self.control.clone()
+    }
+
+    pub fn run(self) -> Result<RunReport, RunError> {
+        let mut ctx = Context::new(self.config, Arc::new(self.hooks), self.control);
+        ctx.dry_run = self.dry_run;
+        ctx.extra_args = self.extra_args;
+        Pipeline::standard().run(&mut ctx)
+            .map_err(|message| RunError { step: ctx.failed_step, message })?;
+        let artifacts_dir = PathBuf::from(&ctx.config.artifacts_dir);
+        let serial_log = ctx.keep_serial_log().map_err(|message| RunError { step: None, message })?;
+        Ok(RunReport {
+            artifacts_dir,
+            outcome: ctx.outcome.take(),
+            serial_log,
+            firmware: ctx.firmware.take(),
+            stage_timings: ctx.stage_timings,
+            config: ctx.config,
+        })
+    }
+}
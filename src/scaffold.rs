@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::Path;
+use log::info;
+use crate::arch::Arch;
+use crate::{version, RunnerConfig};
+
+const MAIN_RS: &str = r#"#![no_main]
+#![no_std]
+
+use log::info;
+use uefi::prelude::*;
+
+#[entry]
+fn main() -> Status {
+    uefi::helpers::init().unwrap();
+    info!("Hello from {}!", env!("CARGO_PKG_NAME"));
+    Status::SUCCESS
+}
+"#;
+
+const GUEST_LIB_RS: &str = r###"#![no_std]
+
+use core::fmt::{self, Write};
+use uefi::runtime::{self, ResetType};
+use uefi::{system, Status};
+
+const PREFIX: &str = "##UEFAPI:";
+const TEST_SELECTION: &[u8] = b"opt/uefapi/test";
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn emit(args: fmt::Arguments) {
+    system::with_stdout(|stdout| {
+        let _ = write!(stdout, "\r\n{}{}\r\n", PREFIX, args);
+    });
+}
+
+pub fn heartbeat() {
+    emit(format_args!("HEARTBEAT"));
+}
+
+pub fn screenshot(name: &str) {
+    emit(format_args!("SCREENSHOT={}", name));
+}
+
+pub fn mark(label: &str) {
+    emit(format_args!("MARK={}", label));
+}
+
+pub fn report_artifact(name: &str, data: &[u8]) {
+    for chunk in data.chunks(48) {
+        let mut encoded = [0u8; 64];
+        let mut len = 0;
+        for triple in chunk.chunks(3) {
+            let n = (triple[0] as u32) << 16
+                | (*triple.get(1).unwrap_or(&0) as u32) << 8
+                | *triple.get(2).unwrap_or(&0) as u32;
+            for i in 0..4 {
+                encoded[len] = if i <= triple.len() { ALPHABET[(n >> (18 - 6 * i) & 63) as usize] } else { b'=' };
+                len += 1;
+            }
+        }
+        let encoded = core::str::from_utf8(&encoded[..len]).unwrap_or("");
+        emit(format_args!("ARTIFACT={}:{}", name, encoded));
+    }
+}
+
+pub fn exit(code: i32) -> ! {
+    emit(format_args!("EXIT={}", code));
+    let status = if code == 0 { Status::SUCCESS } else { Status::ABORTED };
+    runtime::reset(ResetType::SHUTDOWN, status, None)
+}
+
+#[used]
+#[no_mangle]
+pub static UEFAPI_TEST_HARNESS: [u8; 21] = *b"UEFAPI-TEST-HARNESS-1";
+
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn() -> Result<(), &'static str>,
+}
+
+#[cfg(target_arch = "x86_64")]
+fn fw_cfg_read(selector: Option<u16>, buf: &mut [u8]) {
+    if let Some(selector) = selector {
+        unsafe { core::arch::asm!("out dx, ax", in("dx") 0x510u16, in("ax") selector) };
+    }
+    for byte in buf {
+        unsafe { core::arch::asm!("in al, dx", out("al") *byte, in("dx") 0x511u16) };
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn test_selection(buf: &mut [u8; 256]) -> Option<&str> {
+    let mut count = [0u8; 4];
+    fw_cfg_read(Some(0x19), &mut count);
+    for _ in 0..u32::from_be_bytes(count) {
+        let mut entry = [0u8; 64];
+        fw_cfg_read(None, &mut entry);
+        let name = &entry[8..];
+        let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+        if name == TEST_SELECTION {
+            let size = (u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]) as usize).min(buf.len());
+            fw_cfg_read(Some(u16::from_be_bytes([entry[4], entry[5]])), &mut buf[..size]);
+            return core::str::from_utf8(&buf[..size]).ok();
+        }
+    }
+    None
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn test_selection(_buf: &mut [u8; 256]) -> Option<&str> {
+    None
+}
+
+pub fn run_tests(tests: &[&Test]) -> ! {
+    let mut buf = [0u8; 256];
+    let selection = test_selection(&mut buf);
+    if selection == Some("list") {
+        for test in tests {
+            emit(format_args!("TEST_LIST={}", test.name));
+        }
+        exit(0);
+    }
+    let only = selection.and_then(|selection| selection.strip_prefix("run:"));
+    let mut failed = 0;
+    for test in tests.iter().filter(|test| only.is_none_or(|name| name == test.name)) {
+        emit(format_args!("TEST_START={}", test.name));
+        match (test.run)() {
+            Ok(()) => emit(format_args!("TEST_PASS={}", test.name)),
+            Err(message) => {
+                failed += 1;
+                emit(format_args!("TEST_FAIL={} {}", test.name, message));
+            }
+        }
+    }
+    exit(failed)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn pvpanic() -> ! {
+    unsafe { core::arch::asm!("out dx, al", in("dx") 0x505u16, in("al") 1u8) };
+    loop {
+        core::hint::spin_loop();
+    }
+}
+"###;
+
+fn guest_cargo_toml() -> String {
+    r#"[package]
+name = "uefapi-guest"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+uefi = "0.35"
+"#.to_string()
+}
+
+fn cargo_toml(name: &str) -> String {
+    format!(r#"[package]
+name = "{}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+log = "0.4"
+uefi = {{ version = "0.35", features = ["logger", "panic_handler"] }}
+"#, name)
+}
+
+fn cargo_config(arch: Arch) -> String {
+    format!(r#"[build]
+target = "{0}"
+
+[target.{0}]
+runner = ["uefapi-runner", "run", "uefapi-runner.toml"]
+"#, arch.target())
+}
+
+pub fn runner_config(name: &str, arch: Arch) -> RunnerConfig {
+    let mut config = RunnerConfig::builder()
+        .arch(arch)
+        .binary_path(format!("target/{}/debug/{}.efi", arch.target(), name))
+        .build()
+        .expect("Generated config is invalid");
+    config.required_version = Some(version::CURRENT.to_string());
+    config
+}
+
+fn valid_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn write_files(root: &Path, files: &[(&str, String)]) -> Result<(), String> {
+    for (file, contents) in files {
+        let path = root.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+pub fn create_guest(dir: &str) -> Result<(), String> {
+    let root = Path::new(dir);
+    if root.exists() {
+        return Err(format!("{} already exists", root.display()));
+    }
+    write_files(root, &[("Cargo.toml", guest_cargo_toml()), ("src/lib.rs", GUEST_LIB_RS.to_string())])?;
+    info!("Created guest helper crate in {}, enable guest_protocol in the runner config to use it",
+          root.display());
+    Ok(())
+}
+
+pub fn create(name: &str) -> Result<(), String> {
+    if !valid_name(name) {
+        return Err(format!("{} is not a valid crate name", name));
+    }
+    let root = Path::new(name);
+    if root.exists() {
+        return Err(format!("{} already exists", root.display()));
+    }
+    let arch = Arch::default();
+    let config = toml::to_string_pretty(&runner_config(name, arch))
+        .map_err(|e| format!("Failed to serialize runner config: {}", e))?;
+    let files = [
+        ("Cargo.toml", cargo_toml(name)),
+        (".cargo/config.toml", cargo_config(arch)),
+        ("src/main.rs", MAIN_RS.to_string()),
+        ("uefapi-runner.toml", config),
+        (".gitignore", "/target\n".to_string()),
+    ];
+    write_files(root, &files)?;
+    info!("Created {}, run it with `cargo run` inside {}", name, root.display());
+    Ok(())
+}
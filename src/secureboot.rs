@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::arch::Arch;
+
+const DEFAULT_OWNER: &str = "5b2e8f1c-6c3a-4e39-9d0c-75656661706b";
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SignConfig {
+    pub key: String,
+    pub cert: String,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SecureBootConfig {
+    #[serde(default)]
+    pub enrolled_vars: Option<String>,
+    #[serde(default)]
+    pub pk: Option<String>,
+    #[serde(default)]
+    pub kek: Vec<String>,
+    #[serde(default)]
+    pub db: Vec<String>,
+    #[serde(default)]
+    pub owner_guid: Option<String>,
+    #[serde(default)]
+    pub sign: Option<SignConfig>,
+}
+
+fn run(program: &str, cmd: &mut Command) -> Result<(), String> {
+    let output = cmd.output().map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+impl SecureBootConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enrolled_vars.is_some() && (self.pk.is_some() || !self.kek.is_empty() || !self.db.is_empty()) {
+            return Err("secure_boot enrolled_vars conflicts with pk, kek and db".to_string());
+        }
+        if self.pk.is_none() && (!self.kek.is_empty() || !self.db.is_empty()) {
+            return Err("secure_boot kek and db need a pk to enroll".to_string());
+        }
+        if let Some(path) = self.enrolled_vars.iter().find(|path| !Path::new(path).is_file()) {
+            return Err(format!("secure_boot enrolled_vars {} does not exist", path));
+        }
+        Ok(())
+    }
+
+    pub fn enrolls(&self) -> bool {
+        self.pk.is_some()
+    }
+
+    pub fn qemu_args(&self, arch: Arch) -> Vec<String> {
+        if arch != Arch::X86_64 {
+            return Vec::new();
+        }
+        ["-machine", "smm=on", "-global", "driver=cfi.pflash01,property=secure,value=on"]
+            .map(str::to_string).to_vec()
+    }
+
+    pub fn enroll(&self, vars: &Path, dry_run: bool) -> Result<(), String> {
+        let Some(pk) = &self.pk else {
+            return Ok(());
+        };
+        let owner = self.owner_guid.as_deref().unwrap_or(DEFAULT_OWNER);
+        let enrolled = vars.with_extension("enrolled");
+        let mut cmd = Command::new("virt-fw-vars");
+        cmd.arg("--input").arg(vars).arg("--output").arg(&enrolled)
+            .args(["--set-pk", owner, pk]);
+        for kek in &self.kek {
+            cmd.args(["--add-kek", owner, kek]);
+        }
+        for db in &self.db {
+            cmd.args(["--add-db", owner, db]);
+        }
+        cmd.arg("--secure-boot");
+        if dry_run {
+            info!("[dry-run] would enroll PK {}, {} KEK(s) and {} db cert(s) into {}",
+                  pk, self.kek.len(), self.db.len(), vars.display());
+            return Ok(());
+        }
+        run("virt-fw-vars", &mut cmd)?;
+        fs::rename(&enrolled, vars).map_err(|e| format!("Failed to replace {}: {}", vars.display(), e))?;
+        info!("Enrolled PK, {} KEK(s) and {} db cert(s) into {}", self.kek.len(), self.db.len(), vars.display());
+        Ok(())
+    }
+
+    pub fn sign(&self, binary: &Path, run_dir: &Path, dry_run: bool) -> Result<Option<PathBuf>, String> {
+        let Some(sign) = &self.sign else {
+            return Ok(None);
+        };
+        let signed = run_dir.join(binary.file_name().unwrap_or_default());
+        if dry_run {
+            info!("[dry-run] would sign {} with {}", binary.display(), sign.cert);
+            return Ok(Some(signed));
+        }
+        run("sbsign", Command::new("sbsign").args(["--key", &sign.key, "--cert", &sign.cert, "--output"])
+            .arg(&signed).arg(binary))?;
+        info!("Signed {} with {}", binary.display(), sign.cert);
+        Ok(Some(signed))
+    }
+}
@@ -0,0 +1,32 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::rng::SplitMix64;
+
+const FW_CFG_NAME: &str = "opt/uefapi/seed";
+const ENTROPY_BYTES: usize = 1 << 20;
+
+pub fn generate() -> u64 {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    SplitMix64::new(nanos ^ (std::process::id() as u64) << 32).next_u64()
+}
+
+pub fn entropy(seed: u64, path: &Path) -> io::Result<()> {
+    let mut rng = SplitMix64::new(seed);
+    let bytes = (0..ENTROPY_BYTES / 8).flat_map(|_| rng.next_u64().to_le_bytes()).collect::<Vec<_>>();
+    fs::write(path, bytes)
+}
+
+pub fn qemu_args(seed: u64, virtio_rng: bool, run_dir: &Path) -> io::Result<Vec<String>> {
+    let mut args = vec!["-fw_cfg".to_string(), format!("name={},string={}", FW_CFG_NAME, seed)];
+    if virtio_rng {
+        let path = run_dir.join("entropy.bin");
+        entropy(seed, &path)?;
+        args.extend([
+            "-object".to_string(), format!("rng-random,id=rng0,filename={}", path.display()),
+            "-device".to_string(), "virtio-rng-pci,rng=rng0".to_string(),
+        ]);
+    }
+    Ok(args)
+}
@@ -0,0 +1,306 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use log::{error, info};
+use crate::arch::Arch;
+use crate::handle::Control;
+use crate::harness::TestConfig;
+use crate::hooks::Hooks;
+use crate::outcome::TestStatus;
+use crate::pipeline::{Context, Pipeline};
+use crate::RunnerConfig;
+
+pub const MARKER: &str = "UEFAPI-RUNNER-SELFTEST-OK";
+const DEFAULT_TIMEOUT: u64 = 60;
+const FILE_ALIGNMENT: usize = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x1000;
+const TEXT_RVA: u32 = 0x1000;
+const RELOC_RVA: u32 = 0x2000;
+
+// entry(image, system_table): system_table->ConOut->OutputString(ConOut, msg), then
+// system_table->RuntimeServices->ResetSystem(EfiResetShutdown, EFI_SUCCESS, 0, NULL). The message follows the code.
+const X86_64_CODE: &[u8] = &[
+    0x53,                                      // push rbx
+    0x48, 0x83, 0xec, 0x20,                    // sub rsp, 32
+    0x48, 0x89, 0xd3,                          // mov rbx, rdx
+    0x48, 0x8b, 0x4b, 0x40,                    // mov rcx, [rbx+64]
+    0x48, 0x8d, 0x15, 0x1f, 0x00, 0x00, 0x00,  // lea rdx, [rip+msg]
+    0xff, 0x51, 0x08,                          // call [rcx+8]
+    0x48, 0x8b, 0x43, 0x58,                    // mov rax, [rbx+88]
+    0xb9, 0x02, 0x00, 0x00, 0x00,              // mov ecx, 2
+    0x31, 0xd2,                                // xor edx, edx
+    0x45, 0x31, 0xc0,                          // xor r8d, r8d
+    0x45, 0x31, 0xc9,                          // xor r9d, r9d
+    0xff, 0x50, 0x68,                          // call [rax+104]
+    0x48, 0x83, 0xc4, 0x20,                    // add rsp, 32
+    0x5b,                                      // pop rbx
+    0x31, 0xc0,                                // xor eax, eax
+    0xc3,                                      // ret
+];
+
+const AARCH64_CODE: &[u8] = &[
+    0xfd, 0x7b, 0xbe, 0xa9,  // stp x29, x30, [sp, #-32]!
+    0xfd, 0x03, 0x00, 0x91,  // mov x29, sp
+    0xf3, 0x0b, 0x00, 0xf9,  // str x19, [sp, #16]
+    0xf3, 0x03, 0x01, 0xaa,  // mov x19, x1
+    0x60, 0x22, 0x40, 0xf9,  // ldr x0, [x19, #64]
+    0x02, 0x04, 0x40, 0xf9,  // ldr x2, [x0, #8]
+    0xa1, 0x01, 0x00, 0x10,  // adr x1, msg
+    0x40, 0x00, 0x3f, 0xd6,  // blr x2
+    0x63, 0x2e, 0x40, 0xf9,  // ldr x3, [x19, #88]
+    0x64, 0x34, 0x40, 0xf9,  // ldr x4, [x3, #104]
+    0x40, 0x00, 0x80, 0xd2,  // mov x0, #2
+    0x01, 0x00, 0x80, 0xd2,  // mov x1, #0
+    0x02, 0x00, 0x80, 0xd2,  // mov x2, #0
+    0x03, 0x00, 0x80, 0xd2,  // mov x3, #0
+    0x80, 0x00, 0x3f, 0xd6,  // blr x4
+    0xf3, 0x0b, 0x40, 0xf9,  // ldr x19, [sp, #16]
+    0xfd, 0x7b, 0xc2, 0xa8,  // ldp x29, x30, [sp], #32
+    0x00, 0x00, 0x80, 0xd2,  // mov x0, #0
+    0xc0, 0x03, 0x5f, 0xd6,  // ret
+];
+
+const RISCV64_CODE: &[u8] = &[
+    0x13, 0x01, 0x01, 0xff,  // addi sp, sp, -16
+    0x23, 0x34, 0x11, 0x00,  // sd ra, 8(sp)
+    0x23, 0x30, 0x81, 0x00,  // sd s0, 0(sp)
+    0x13, 0x84, 0x05, 0x00,  // mv s0, a1
+    0x03, 0x35, 0x04, 0x04,  // ld a0, 64(s0)
+    0x83, 0x32, 0x85, 0x00,  // ld t0, 8(a0)
+    0x97, 0x05, 0x00, 0x00,  // auipc a1, 0
+    0x93, 0x85, 0xc5, 0x03,  // addi a1, a1, 60 (msg)
+    0xe7, 0x80, 0x02, 0x00,  // jalr t0
+    0x03, 0x33, 0x84, 0x05,  // ld t1, 88(s0)
+    0x83, 0x32, 0x83, 0x06,  // ld t0, 104(t1)
+    0x13, 0x05, 0x20, 0x00,  // li a0, 2
+    0x93, 0x05, 0x00, 0x00,  // li a1, 0
+    0x13, 0x06, 0x00, 0x00,  // li a2, 0
+    0x93, 0x06, 0x00, 0x00,  // li a3, 0
+    0xe7, 0x80, 0x02, 0x00,  // jalr t0
+    0x83, 0x30, 0x81, 0x00,  // ld ra, 8(sp)
+    0x03, 0x34, 0x01, 0x00,  // ld s0, 0(sp)
+    0x13, 0x01, 0x01, 0x01,  // addi sp, sp, 16
+    0x13, 0x05, 0x00, 0x00,  // li a0, 0
+    0x67, 0x80, 0x00, 0x00,  // ret
+];
+
+fn code(arch: Arch) -> &'static [u8] {
+    match arch {
+        Arch::X86_64 => X86_64_CODE,
+        Arch::Aarch64 => AARCH64_CODE,
+        Arch::Riscv64 => RISCV64_CODE,
+    }
+}
+
+fn pad(bytes: &mut Vec<u8>, alignment: usize) {
+    bytes.resize(bytes.len().div_ceil(alignment) * alignment, 0);
+}
+
+fn section(out: &mut Vec<u8>, name: &[u8], size: usize, rva: u32, offset: usize, characteristics: u32) {
+    let mut name = name.to_vec();
+    name.resize(8, 0);
+    out.extend(name);
+    out.extend((size as u32).to_le_bytes());
+    out.extend(rva.to_le_bytes());
+    out.extend(((size.div_ceil(FILE_ALIGNMENT) * FILE_ALIGNMENT) as u32).to_le_bytes());
+    out.extend((offset as u32).to_le_bytes());
+    out.extend([0u8; 12]);
+    out.extend(characteristics.to_le_bytes());
+}
+
+pub fn image(arch: Arch) -> Vec<u8> {
+    let mut text = code(arch).to_vec();
+    text.extend(format!("\r\n{}\r\n\0", MARKER).encode_utf16().flat_map(u16::to_le_bytes));
+    // One empty block of IMAGE_REL_BASED_ABSOLUTE entries so the loader may place the image anywhere.
+    let reloc = [TEXT_RVA.to_le_bytes(), 12u32.to_le_bytes(), [0; 4]].concat();
+    let mut out = b"MZ".to_vec();
+    out.resize(0x3c, 0);
+    out.extend(0x40u32.to_le_bytes());
+    out.extend(b"PE\0\0");
+    out.extend(arch.pe_machine().to_le_bytes());
+    out.extend(2u16.to_le_bytes());
+    out.extend([0u8; 12]);
+    out.extend(240u16.to_le_bytes());
+    out.extend(0x22u16.to_le_bytes());
+    out.extend(0x20bu16.to_le_bytes());
+    out.extend([0u8; 2]);
+    out.extend((FILE_ALIGNMENT as u32).to_le_bytes());
+    out.extend((FILE_ALIGNMENT as u32).to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    out.extend(TEXT_RVA.to_le_bytes());
+    out.extend(TEXT_RVA.to_le_bytes());
+    out.extend(0x1000_0000u64.to_le_bytes());
+    out.extend(SECTION_ALIGNMENT.to_le_bytes());
+    out.extend((FILE_ALIGNMENT as u32).to_le_bytes());
+    out.extend([0u8; 16]);
+    out.extend((RELOC_RVA + SECTION_ALIGNMENT).to_le_bytes());
+    out.extend((FILE_ALIGNMENT as u32).to_le_bytes());
+    out.extend(0u32.to_le_bytes());
+    out.extend(10u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    for size in [0x10000u64, 0x10000, 0x10000, 0x10000] {
+        out.extend(size.to_le_bytes());
+    }
+    out.extend(0u32.to_le_bytes());
+    out.extend(16u32.to_le_bytes());
+    for directory in 0..16 {
+        let (rva, size) = if directory == 5 { (RELOC_RVA, reloc.len() as u32) } else { (0, 0) };
+        out.extend(rva.to_le_bytes());
+        out.extend(size.to_le_bytes());
+    }
+    section(&mut out, b".text", text.len(), TEXT_RVA, FILE_ALIGNMENT, 0x6000_0020);
+    section(&mut out, b".reloc", reloc.len(), RELOC_RVA, 2 * FILE_ALIGNMENT, 0x4200_0040);
+    pad(&mut out, FILE_ALIGNMENT);
+    out.extend(&text);
+    pad(&mut out, FILE_ALIGNMENT);
+    out.extend(&reloc);
+    pad(&mut out, FILE_ALIGNMENT);
+    out
+}
+
+pub fn config(config: &RunnerConfig) -> RunnerConfig {
+    let arch = config.arch();
+    let dir = Path::new(&config.artifacts_dir).join("selftest");
+    let timeout = config.timeout_secs.or(config.test.as_ref().and_then(|test| test.timeout_secs))
+        .unwrap_or(DEFAULT_TIMEOUT);
+    RunnerConfig {
+        tags: config.tags.clone(),
+        arch: config.arch,
+        auto_build: false,
+        binary_path: dir.join("selftest.efi").display().to_string(),
+        efi_name: arch.efi_name().to_string(),
+        work_dir: config.work_dir.clone(),
+        qemu_cmd: config.qemu_cmd.clone(),
+        accel: config.accel.clone(),
+        ovmf_path: config.ovmf_path.clone(),
+        firmware_search: config.firmware_search,
+        firmware_download: config.firmware_download.clone(),
+        firmware_build: config.firmware_build.clone(),
+        firmware_mode: config.firmware_mode,
+        preset: config.preset,
+        cpu: config.cpu.clone(),
+        memory: config.memory.clone(),
+        smp: config.smp,
+        escalation: config.escalation,
+        log_serial: true,
+        log_path: dir.join("serial.log").display().to_string(),
+        output: config.output,
+        artifacts_dir: dir.display().to_string(),
+        test: Some(TestConfig {
+            success_marker: Some(MARKER.to_string()),
+            timeout_secs: Some(timeout),
+            debug_exit: false,
+            ..TestConfig::default()
+        }),
+        ..RunnerConfig::default()
+    }
+}
+
+pub fn run(config: &RunnerConfig) -> bool {
+    let config = self::config(config);
+    let arch = config.arch();
+    if let Err(e) = Path::new(&config.binary_path).parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&config.binary_path, image(arch))) {
+        error!("Failed to write the self-test binary {}: {}", config.binary_path, e);
+        return false;
+    }
+    info!("Booting the built-in {:?} self-test binary", arch);
+    let mut ctx = Context::new(config, Arc::new(Hooks::default()), Arc::new(Control::default()));
+    let result = Pipeline::standard().run(&mut ctx);
+    let firmware = ctx.firmware.as_ref().map(|firmware| firmware.code.display().to_string())
+        .unwrap_or_else(|| "no firmware".to_string());
+    let qemu = ctx.qemu_cmdline.first().cloned().unwrap_or_else(|| ctx.config.qemu_cmd.clone());
+    let harness = ctx.outcome.as_ref()
+        .and_then(|outcome| outcome.tests.iter().find(|test| test.name == "harness"));
+    match (&result, harness) {
+        (Ok(()), Some(harness)) if harness.status == TestStatus::Passed => {
+            let secs = harness.duration_ms.unwrap_or(0) as f64 / 1000.0;
+            println!("Self-test passed: {} booted {} and ran the {:?} self-test binary in {:.2}s",
+                     qemu, firmware, arch, secs);
+            true
+        }
+        _ => {
+            let reason = match (&result, harness) {
+                (Err(e), _) => e.clone(),
+                (_, Some(harness)) => harness.message.clone().unwrap_or_else(|| "test failed".to_string()),
+                _ => "no verdict".to_string(),
+            };
+            println!("Self-test failed: {} with {} did not boot the {:?} self-test binary: {}", qemu, firmware,
+                     arch, reason);
+            println!("The serial log is in {}; a failure here is in QEMU, the firmware or the runner, \
+                      not in your application", ctx.config.log_path);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn image_has_pe32_plus_headers() {
+        for arch in Arch::all() {
+            let image = image(arch);
+            assert_eq!(&image[..2], b"MZ");
+            let pe = u32_at(&image, 0x3c) as usize;
+            assert_eq!(&image[pe..pe + 4], b"PE\0\0");
+            assert_eq!(u16_at(&image, pe + 4), arch.pe_machine());
+            assert_eq!(u16_at(&image, pe + 6), 2, "section count");
+            let optional = pe + 24;
+            assert_eq!(u16_at(&image, pe + 20), 240, "optional header size");
+            assert_eq!(u16_at(&image, optional), 0x20b, "PE32+ magic");
+            assert_eq!(u32_at(&image, optional + 16), TEXT_RVA, "entry point");
+            assert_eq!(u32_at(&image, optional + 56), RELOC_RVA + SECTION_ALIGNMENT, "image size");
+            assert_eq!(u16_at(&image, optional + 68), 10, "EFI application subsystem");
+            let reloc = optional + 112 + 5 * 8;
+            assert_eq!((u32_at(&image, reloc), u32_at(&image, reloc + 4)), (RELOC_RVA, 12));
+            assert_eq!(image.len(), 3 * FILE_ALIGNMENT);
+        }
+    }
+
+    #[test]
+    fn image_sections_point_at_the_code_and_relocations() {
+        for arch in Arch::all() {
+            let image = image(arch);
+            let sections = u32_at(&image, 0x3c) as usize + 24 + 240;
+            assert_eq!(&image[sections..sections + 8], b".text\0\0\0");
+            assert_eq!(u32_at(&image, sections + 12), TEXT_RVA);
+            assert_eq!(u32_at(&image, sections + 20) as usize, FILE_ALIGNMENT);
+            assert_eq!(&image[sections + 40..sections + 48], b".reloc\0\0");
+            assert_eq!(u32_at(&image, sections + 52), RELOC_RVA);
+            assert_eq!(u32_at(&image, sections + 60) as usize, 2 * FILE_ALIGNMENT);
+            let text = &image[FILE_ALIGNMENT..2 * FILE_ALIGNMENT];
+            assert_eq!(&text[..code(arch).len()], code(arch));
+            assert_eq!(u32_at(&image, 2 * FILE_ALIGNMENT), TEXT_RVA, "relocation block page");
+        }
+    }
+
+    #[test]
+    fn message_follows_the_code() {
+        let message = |arch| {
+            let text = &image(arch)[FILE_ALIGNMENT + code(arch).len()..];
+            let units = text.chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .take_while(|unit| *unit != 0).collect::<Vec<_>>();
+            String::from_utf16(&units).unwrap()
+        };
+        for arch in Arch::all() {
+            assert_eq!(message(arch), format!("\r\n{}\r\n", MARKER));
+        }
+        // lea rdx, [rip+disp32] ends at offset 19.
+        assert_eq!(19 + u32_at(X86_64_CODE, 15) as usize, X86_64_CODE.len());
+        // adr x1 at offset 24, immhi in bits 5..24 and immlo in bits 29..31.
+        let adr = u32_at(AARCH64_CODE, 24);
+        assert_eq!(24 + ((adr >> 5 & 0x7ffff) << 2 | adr >> 29 & 3) as usize, AARCH64_CODE.len());
+        // auipc a1, 0 at offset 24 followed by addi a1, a1, imm12.
+        assert_eq!(24 + (u32_at(RISCV64_CODE, 28) >> 20) as usize, RISCV64_CODE.len());
+    }
+}
@@ -0,0 +1,39 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+pub struct LogFollower {
+    pub path: PathBuf,
+    offset: u64,
+    pending: String,
+}
+
+impl LogFollower {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LogFollower { path: path.into(), offset: 0, pending: String::new() }
+    }
+
+    pub fn poll(&mut self) -> Vec<String> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Vec::new();
+        };
+        let mut buf = Vec::new();
+        if file.seek(SeekFrom::Start(self.offset)).is_err()
+            || file.read_to_end(&mut buf).is_err() {
+            return Vec::new();
+        }
+        self.offset += buf.len() as u64;
+        self.pending.push_str(&String::from_utf8_lossy(&buf));
+        let mut lines = Vec::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line = self.pending[..end].trim_end_matches('\r').to_string();
+            self.pending.drain(..=end);
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn partial(&self) -> &str {
+        &self.pending
+    }
+}
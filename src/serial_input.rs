@@ -0,0 +1,126 @@
+// QEMU's socket chardevs are reached over Unix domain sockets, which other hosts lack.
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{debug, info, warn};
+use crate::output::Stream;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct SerialInput {
+    pub socket: PathBuf,
+    pub script: String,
+    pub prompt: Option<String>,
+    pub echo: Option<Stream>,
+}
+
+#[cfg(unix)]
+pub fn connect(socket: &PathBuf) -> io::Result<UnixStream> {
+    let start = Instant::now();
+    loop {
+        match UnixStream::connect(socket) {
+            Ok(stream) => return Ok(stream),
+            Err(_) if start.elapsed() < CONNECT_TIMEOUT => thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Console {
+    text: String,
+    waiting: bool,
+    closed: bool,
+}
+
+type Shared = Arc<(Mutex<Console>, Condvar)>;
+
+#[cfg(unix)]
+fn relay(mut stream: UnixStream, echo: Option<Stream>, shared: Shared) {
+    let (console, changed) = &*shared;
+    let mut buf = [0u8; 4096];
+    while let Ok(len @ 1..) = stream.read(&mut buf) {
+        let _ = match echo {
+            Some(Stream::Stdout) => io::stdout().write_all(&buf[..len]).and_then(|_| io::stdout().flush()),
+            Some(Stream::Stderr) => io::stderr().write_all(&buf[..len]),
+            None => Ok(()),
+        };
+        let mut console = console.lock().unwrap();
+        if console.waiting {
+            console.text.push_str(&String::from_utf8_lossy(&buf[..len]));
+            changed.notify_all();
+        }
+    }
+    console.lock().unwrap().closed = true;
+    changed.notify_all();
+}
+
+#[cfg(unix)]
+fn feed(mut stream: UnixStream, script: &str, prompt: Option<&str>, shared: &Shared) -> io::Result<()> {
+    let (console, changed) = &**shared;
+    for line in script.lines() {
+        if let Some(prompt) = prompt {
+            let mut console = console.lock().unwrap();
+            loop {
+                if let Some(found) = console.text.find(prompt) {
+                    console.text.drain(..found + prompt.len());
+                    break;
+                }
+                if console.closed {
+                    warn!("Guest console closed before prompt {:?} appeared", prompt);
+                    return Ok(());
+                }
+                console = changed.wait(console).unwrap();
+            }
+        }
+        debug!("Serial input: {}", line);
+        stream.write_all(line.as_bytes())?;
+        stream.write_all(b"\r")?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+impl SerialInput {
+    pub fn spawn(self) {
+        warn!("serial_input needs Unix domain sockets, which this host does not support");
+    }
+}
+
+#[cfg(unix)]
+impl SerialInput {
+    pub fn spawn(self) {
+        thread::spawn(move || {
+            let stream = match connect(&self.socket) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to connect to the guest console: {}", e);
+                    return;
+                }
+            };
+            let shared = Shared::default();
+            shared.0.lock().unwrap().waiting = self.prompt.is_some();
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(e) => {
+                    warn!("Failed to clone the guest console socket: {}", e);
+                    return;
+                }
+            };
+            let relayed = shared.clone();
+            thread::spawn(move || relay(reader, self.echo, relayed));
+            info!("Feeding {} line(s) of serial input", self.script.lines().count());
+            if let Err(e) = feed(stream, &self.script, self.prompt.as_deref(), &shared) {
+                warn!("Failed to write serial input: {}", e);
+            }
+            let mut console = shared.0.lock().unwrap();
+            console.waiting = false;
+            console.text.clear();
+        });
+    }
+}
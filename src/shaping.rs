@@ -0,0 +1,210 @@
+// The shaped console sits between Unix domain sockets, which other hosts lack.
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::output::Stream;
+use crate::rng::SplitMix64;
+use crate::serial_input;
+
+const INPUT_SALT: u64 = 0x696e707574;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SerialShaping {
+    #[serde(default)]
+    pub bytes_per_sec: Option<u64>,
+    #[serde(default)]
+    pub corrupt_one_in: Option<u64>,
+    #[serde(default)]
+    pub drop_one_in: Option<u64>,
+    #[serde(default)]
+    pub input: bool,
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl SerialShaping {
+    pub fn validate(&self) -> Result<(), String> {
+        if [self.bytes_per_sec, self.corrupt_one_in, self.drop_one_in].contains(&Some(0)) {
+            return Err("serial_shaping rates must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    bytes: u64,
+    corrupted: u64,
+    dropped: u64,
+}
+
+struct Shaper {
+    shaping: SerialShaping,
+    rng: SplitMix64,
+    started: Instant,
+    counters: Counters,
+}
+
+impl Shaper {
+    fn new(shaping: &SerialShaping, seed: u64) -> Self {
+        Shaper { shaping: shaping.clone(), rng: SplitMix64::new(seed), started: Instant::now(),
+                 counters: Counters::default() }
+    }
+
+    fn hit(&mut self, one_in: Option<u64>) -> bool {
+        one_in.is_some_and(|one_in| self.rng.below(one_in) == 0)
+    }
+
+    fn shape(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for &byte in data {
+            if self.hit(self.shaping.drop_one_in) {
+                self.counters.dropped += 1;
+                continue;
+            }
+            if self.hit(self.shaping.corrupt_one_in) {
+                self.counters.corrupted += 1;
+                out.push(byte ^ 1 << self.rng.below(8));
+            } else {
+                out.push(byte);
+            }
+        }
+        out
+    }
+
+    fn pace(&mut self, len: usize) {
+        self.counters.bytes += len as u64;
+        let Some(rate) = self.shaping.bytes_per_sec else {
+            return;
+        };
+        let due = Duration::from_secs_f64(self.counters.bytes as f64 / rate as f64);
+        if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+
+    fn chunk(&self) -> usize {
+        self.shaping.bytes_per_sec.map_or(4096, |rate| (rate / 50).clamp(1, 4096) as usize)
+    }
+
+    fn pump(&mut self, mut from: impl Read, mut deliver: impl FnMut(&[u8]) -> io::Result<()>) {
+        let mut buf = vec![0u8; self.chunk()];
+        while let Ok(len @ 1..) = from.read(&mut buf) {
+            let shaped = self.shape(&buf[..len]);
+            self.pace(len);
+            if deliver(&shaped).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+pub struct SerialShaper {
+    pub socket: PathBuf,
+    pub shaping: SerialShaping,
+    pub seed: u64,
+    pub log: Option<PathBuf>,
+    pub echo: Option<Stream>,
+    pub console: Option<PathBuf>,
+}
+
+#[cfg(unix)]
+type Client = Arc<Mutex<Option<UnixStream>>>;
+
+#[cfg(unix)]
+fn accept(listener: UnixListener, mut qemu: UnixStream, client: Client, input: Option<Shaper>) {
+    let mut stream = match listener.accept() {
+        Ok((stream, _)) => stream,
+        Err(e) => {
+            warn!("Failed to accept the shaped serial console: {}", e);
+            return;
+        }
+    };
+    match stream.try_clone() {
+        Ok(writer) => *client.lock().unwrap() = Some(writer),
+        Err(e) => warn!("Failed to clone the shaped serial console: {}", e),
+    }
+    match input {
+        Some(mut shaper) => shaper.pump(stream, |data| qemu.write_all(data)),
+        None => {
+            let _ = io::copy(&mut stream, &mut qemu);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl SerialShaper {
+    pub fn spawn(self) -> Result<(), String> {
+        Err("serial_shaping needs Unix domain sockets, which this host does not support".to_string())
+    }
+}
+
+#[cfg(unix)]
+impl SerialShaper {
+    pub fn spawn(self) -> Result<(), String> {
+        let listener = match &self.console {
+            Some(console) => {
+                let _ = fs::remove_file(console);
+                Some(UnixListener::bind(console)
+                    .map_err(|e| format!("Failed to listen on {}: {}", console.display(), e))?)
+            }
+            None => None,
+        };
+        thread::spawn(move || {
+            let qemu = match serial_input::connect(&self.socket) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to connect to the guest serial port: {}", e);
+                    return;
+                }
+            };
+            let mut log = match self.log.as_ref().map(File::create).transpose() {
+                Ok(log) => log,
+                Err(e) => {
+                    warn!("Failed to create the serial log: {}", e);
+                    None
+                }
+            };
+            let client = Client::default();
+            if let Some(listener) = listener {
+                match qemu.try_clone() {
+                    Ok(writer) => {
+                        let input = self.shaping.input.then(|| Shaper::new(&self.shaping, self.seed ^ INPUT_SALT));
+                        let client = client.clone();
+                        thread::spawn(move || accept(listener, writer, client, input));
+                    }
+                    Err(e) => warn!("Failed to clone the guest serial port: {}", e),
+                }
+            }
+            info!("Shaping guest serial output with seed {}", self.seed);
+            let mut shaper = Shaper::new(&self.shaping, self.seed);
+            shaper.pump(&qemu, |data| {
+                if let Some(log) = &mut log {
+                    log.write_all(data)?;
+                    log.flush()?;
+                }
+                match self.echo {
+                    Some(Stream::Stdout) => io::stdout().write_all(data).and_then(|_| io::stdout().flush())?,
+                    Some(Stream::Stderr) => io::stderr().write_all(data)?,
+                    None => {}
+                }
+                if let Some(client) = client.lock().unwrap().as_mut() {
+                    let _ = client.write_all(data);
+                }
+                Ok(())
+            });
+            let counters = &shaper.counters;
+            info!("Serial shaping passed {} byte(s), corrupted {}, dropped {}",
+                  counters.bytes, counters.corrupted, counters.dropped);
+        });
+        Ok(())
+    }
+}
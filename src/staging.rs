@@ -0,0 +1,340 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ExtraFile {
+    pub source: String,
+    pub dest: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BinaryConfig {
+    pub path: String,
+    pub dest: String,
+    #[serde(default)]
+    pub package: Option<String>,
+    #[serde(default)]
+    pub bin: Option<String>,
+}
+
+impl BinaryConfig {
+    pub fn dest(&self) -> &str {
+        self.dest.trim_start_matches('/')
+    }
+}
+
+pub const VOLUME_TAG: &str = "uefapi-runner.tag";
+pub const MANIFEST: &str = "uefapi-runner.manifest";
+const SHELL_MAPPINGS: u32 = 16;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StartupNsh {
+    Generate(bool),
+    Script(String),
+}
+
+impl StartupNsh {
+    pub fn generates(&self) -> bool {
+        *self == StartupNsh::Generate(true)
+    }
+
+    fn launch(boot: &str, mapping: Option<&str>) -> String {
+        match mapping {
+            Some(mapping) => format!("{}:\r\n{}\r\n", mapping.trim_end_matches(':'), boot),
+            None => format!("map -r\r\nfor %a run (0 {})\r\n  if exist fs%a:\\{} then\r\n    fs%a:\r\n    {}\r\n    \
+                             goto UEFAPI_DONE\r\n  endif\r\nendfor\r\n\
+                             echo \"uefapi-runner: no fsN: mapping holds the staged ESP\"\r\n:UEFAPI_DONE\r\n",
+                            SHELL_MAPPINGS - 1, VOLUME_TAG, boot),
+        }
+    }
+
+    pub fn render(&self, boot_path: &str, mapping: Option<&str>) -> Option<String> {
+        let boot = format!("\\{}", boot_path.replace('/', "\\"));
+        match self {
+            StartupNsh::Generate(false) => None,
+            StartupNsh::Generate(true) => Some(format!("@echo -off\r\n{}", Self::launch(&boot, mapping))),
+            StartupNsh::Script(script) => Some(script.replace("{{launch}}", &Self::launch(&boot, mapping))
+                .replace("{{boot}}", &boot)),
+        }
+    }
+
+    pub fn tags_volume(&self, mapping: Option<&str>) -> bool {
+        mapping.is_none() && match self {
+            StartupNsh::Generate(generate) => *generate,
+            StartupNsh::Script(script) => script.contains("{{launch}}"),
+        }
+    }
+
+    pub fn stage(&self, boot_path: &str, mapping: Option<&str>, esp: &Path,
+                 staged: &mut Vec<PathBuf>) -> Result<u64, String> {
+        let Some(script) = self.render(boot_path, mapping) else {
+            return Ok(0);
+        };
+        let path = esp.join("startup.nsh");
+        fs::write(&path, &script).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        if self.tags_volume(mapping) {
+            let tag = esp.join(VOLUME_TAG);
+            fs::write(&tag, b"").map_err(|e| format!("Failed to write {}: {}", tag.display(), e))?;
+            staged.push(tag);
+        }
+        info!("Generated {}", path.display());
+        staged.push(path);
+        Ok(script.len() as u64)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncStats {
+    pub copied: u64,
+    pub skipped: u64,
+    pub bytes: u64,
+    pub files: Vec<PathBuf>,
+}
+
+pub fn tmpfs_root() -> Option<PathBuf> {
+    let shm = PathBuf::from("/dev/shm");
+    if shm.is_dir() {
+        return Some(shm);
+    }
+    let runtime = PathBuf::from(std::env::var_os("XDG_RUNTIME_DIR")?);
+    runtime.is_dir().then_some(runtime)
+}
+
+pub fn staging_root(work_dir: Option<&str>) -> Option<PathBuf> {
+    match work_dir {
+        Some("tmpfs") => {
+            let root = tmpfs_root();
+            if root.is_none() {
+                warn!("No tmpfs found for work_dir = \"tmpfs\", staging on disk");
+            }
+            root
+        }
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => None,
+    }
+}
+
+fn write_speed(dir: &Path, data: &[u8]) -> io::Result<f64> {
+    let file = tempfile::NamedTempFile::new_in(dir)?;
+    let start = Instant::now();
+    file.as_file().write_all(data)?;
+    file.as_file().sync_all()?;
+    Ok(data.len() as f64 / 1048576.0 / start.elapsed().as_secs_f64().max(1e-6))
+}
+
+// Synced write throughput of the tmpfs root and the default temp dir, sampled at the size of the staged tree.
+pub fn benchmark(bytes: u64) -> Vec<(PathBuf, io::Result<f64>)> {
+    let data = vec![0xa5; bytes.clamp(1 << 20, 64 << 20) as usize];
+    let mut dirs = tmpfs_root().into_iter().collect::<Vec<_>>();
+    dirs.push(env::temp_dir());
+    dirs.dedup();
+    dirs.into_iter().map(|dir| {
+        let speed = write_speed(&dir, &data);
+        (dir, speed)
+    }).collect()
+}
+
+pub fn is_persistent(work_dir: Option<&str>) -> bool {
+    work_dir.is_some_and(|dir| dir != "tmpfs")
+}
+
+fn unchanged(from: &Path, to: &Path) -> io::Result<bool> {
+    let (source, dest) = match (fs::metadata(from), fs::metadata(to)) {
+        (Ok(source), Ok(dest)) => (source, dest),
+        (Err(e), _) => return Err(e),
+        (Ok(_), Err(_)) => return Ok(false),
+    };
+    Ok(source.len() == dest.len() && source.modified()? == dest.modified()?)
+}
+
+fn sync_file(from: &Path, to: &Path, stats: &mut SyncStats) -> io::Result<()> {
+    stats.files.push(to.to_path_buf());
+    if unchanged(from, to)? {
+        stats.skipped += 1;
+        return Ok(());
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    stats.bytes += fs::copy(from, to)?;
+    File::options().write(true).open(to)?.set_modified(fs::metadata(from)?.modified()?)?;
+    stats.copied += 1;
+    Ok(())
+}
+
+pub fn sync(from: &Path, to: &Path, stats: &mut SyncStats) -> io::Result<()> {
+    if from.is_dir() {
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            sync(&entry.path(), &to.join(entry.file_name()), stats)?;
+        }
+        Ok(())
+    } else {
+        sync_file(from, to, stats)
+    }
+}
+
+pub fn stage_extra_files(files: &[ExtraFile], esp: &Path) -> Result<SyncStats, String> {
+    let mut stats = SyncStats::default();
+    for file in files {
+        sync(Path::new(&file.source), &esp.join(file.dest.trim_start_matches('/')), &mut stats)
+            .map_err(|e| format!("Failed to stage {}: {}", file.source, e))?;
+    }
+    if !files.is_empty() {
+        info!("Staged {} extra file(s) ({} bytes), skipped {} unchanged",
+              stats.copied, stats.bytes, stats.skipped);
+    }
+    Ok(stats)
+}
+
+pub fn stage_binaries(binaries: &[BinaryConfig], esp: &Path, staged: &mut Vec<PathBuf>) -> Result<u64, String> {
+    let mut bytes = 0;
+    for binary in binaries {
+        let dest = esp.join(binary.dest());
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        bytes += fs::copy(&binary.path, &dest).map_err(|e| format!("Failed to stage {}: {}", binary.path, e))?;
+        staged.push(dest);
+    }
+    if !binaries.is_empty() {
+        info!("Staged {} extra binary file(s) ({} bytes)", binaries.len(), bytes);
+    }
+    Ok(bytes)
+}
+
+fn remove_stale(esp: &Path, stale: &str) -> io::Result<bool> {
+    let file = esp.join(stale);
+    match fs::remove_file(&file) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    debug!("Removed {}, nothing stages it any more", file.display());
+    let mut dir = file.parent();
+    while let Some(parent) = dir.filter(|dir| *dir != esp) {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+    Ok(true)
+}
+
+// A persistent work dir keeps everything earlier runs staged, the manifest lists those files so the ones no
+// source provides any more can be dropped instead of lingering on the ESP.
+pub fn prune(esp: &Path, staged: &[PathBuf]) -> Result<usize, String> {
+    let path = esp.join(MANIFEST);
+    let staged = staged.iter()
+        .filter_map(|file| file.strip_prefix(esp).ok())
+        .map(|file| file.components().filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        }).collect::<Vec<_>>().join("/"))
+        .collect::<BTreeSet<_>>();
+    let previous = fs::read_to_string(&path).unwrap_or_default();
+    let mut removed = 0;
+    for stale in previous.lines().filter(|line| !line.is_empty() && !staged.contains(*line)) {
+        if !Path::new(stale).components().all(|component| matches!(component, Component::Normal(_))) {
+            warn!("Ignoring {} in {}, it points outside the work dir", stale, path.display());
+            continue;
+        }
+        if remove_stale(esp, stale).map_err(|e| format!("Failed to remove stale {}: {}", stale, e))? {
+            removed += 1;
+        }
+    }
+    let manifest = staged.into_iter().map(|file| file + "\n").collect::<String>();
+    fs::write(&path, manifest).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if removed > 0 {
+        info!("Removed {} file(s) earlier runs left in {}", removed, esp.display());
+    }
+    Ok(removed)
+}
+
+pub fn move_file(from: &Path, to: &Path) -> io::Result<u64> {
+    match fs::rename(from, to) {
+        Ok(()) => fs::metadata(to).map(|m| m.len()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            debug!("{} is on another filesystem, copying instead of renaming", from.display());
+            let bytes = fs::copy(from, to)?;
+            fs::remove_file(from)?;
+            Ok(bytes)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_script_searches_the_tagged_volume() {
+        let script = StartupNsh::Generate(true).render("EFI/BOOT/BOOTX64.EFI", None).unwrap();
+        assert!(script.starts_with("@echo -off\r\nmap -r\r\n"));
+        assert!(script.contains("for %a run (0 15)\r\n"));
+        assert!(script.contains(&format!("if exist fs%a:\\{} then\r\n", VOLUME_TAG)));
+        assert!(script.contains("    \\EFI\\BOOT\\BOOTX64.EFI\r\n    goto UEFAPI_DONE\r\n"));
+        assert!(script.ends_with(":UEFAPI_DONE\r\n"));
+        assert!(StartupNsh::Generate(true).tags_volume(None));
+    }
+
+    #[test]
+    fn mapping_skips_the_search() {
+        let nsh = StartupNsh::Generate(true);
+        assert_eq!(nsh.render("EFI/BOOT/BOOTX64.EFI", Some("fs1:")).unwrap(),
+                   "@echo -off\r\nfs1:\r\n\\EFI\\BOOT\\BOOTX64.EFI\r\n");
+        assert!(!nsh.tags_volume(Some("fs1")));
+    }
+
+    #[test]
+    fn script_placeholders_expand() {
+        let nsh = StartupNsh::Script("echo start\r\n{{boot}} -v\r\n".to_string());
+        assert_eq!(nsh.render("app/test.efi", None).unwrap(), "echo start\r\n\\app\\test.efi -v\r\n");
+        assert!(!nsh.tags_volume(None));
+        let nsh = StartupNsh::Script("echo start\r\n{{launch}}".to_string());
+        assert_eq!(nsh.render("app/test.efi", Some("fs0")).unwrap(), "echo start\r\nfs0:\r\n\\app\\test.efi\r\n");
+        assert!(nsh.tags_volume(None));
+    }
+
+    #[test]
+    fn disabled_script_renders_nothing() {
+        assert_eq!(StartupNsh::Generate(false).render("EFI/BOOT/BOOTX64.EFI", None), None);
+        assert!(!StartupNsh::Generate(false).tags_volume(None));
+    }
+
+    #[test]
+    fn prune_drops_files_no_longer_staged() {
+        let esp = tempfile::tempdir().unwrap();
+        let (kept, stale) = (esp.path().join("EFI/BOOT/BOOTX64.EFI"), esp.path().join("old/data.bin"));
+        for file in [&kept, &stale] {
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, b"x").unwrap();
+        }
+        fs::write(esp.path().join(VOLUME_TAG), b"").unwrap();
+        assert_eq!(prune(esp.path(), &[kept.clone(), stale.clone(), esp.path().join(VOLUME_TAG)]).unwrap(), 0);
+        assert_eq!(prune(esp.path(), std::slice::from_ref(&kept)).unwrap(), 2);
+        assert!(kept.exists());
+        assert!(!stale.exists() && !esp.path().join("old").exists());
+        assert!(!esp.path().join(VOLUME_TAG).exists());
+        assert_eq!(fs::read_to_string(esp.path().join(MANIFEST)).unwrap(), "EFI/BOOT/BOOTX64.EFI\n");
+    }
+
+    #[test]
+    fn prune_ignores_paths_outside_the_work_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let esp = root.path().join("esp");
+        fs::create_dir_all(&esp).unwrap();
+        fs::write(root.path().join("outside"), b"x").unwrap();
+        fs::write(esp.join(MANIFEST), "../outside\n").unwrap();
+        assert_eq!(prune(&esp, &[]).unwrap(), 0);
+        assert!(root.path().join("outside").exists());
+    }
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use log::{info, warn};
+use crate::arch::Arch;
+use crate::pipeline::{Context, Pipeline};
+use crate::RunnerConfig;
+
+pub const MARKER: &[u8] = b"UEFAPI-TEST-HARNESS-1";
+pub const FW_CFG_NAME: &str = "opt/uefapi/test";
+
+type Runs = Vec<(String, RunnerConfig)>;
+
+pub fn detect(ctx: &Context) -> bool {
+    if ctx.config.boot_image.is_some() {
+        return false;
+    }
+    let staged = ctx.workspace.as_ref().map(|workspace| workspace.work_dir().join(ctx.config.boot_path()));
+    let binary = staged.filter(|path| path.is_file()).unwrap_or_else(|| ctx.config.binary_path.clone().into());
+    fs::read(&binary).is_ok_and(|bytes| bytes.windows(MARKER.len()).any(|window| window == MARKER))
+}
+
+fn select(config: &mut RunnerConfig, value: &str) {
+    config.guest_protocol.get_or_insert_default();
+    config.extra_qemu_args.extend(["-fw_cfg".to_string(), format!("name={},string={}", FW_CFG_NAME, value)]);
+}
+
+fn listing(config: &RunnerConfig) -> RunnerConfig {
+    let mut config = config.clone();
+    let artifacts_dir = Path::new(&config.artifacts_dir).join("list");
+    config.log_serial = true;
+    config.log_path = artifacts_dir.join("serial.log").display().to_string();
+    config.artifacts_dir = artifacts_dir.display().to_string();
+    config.report_path = None;
+    config.digest = None;
+    config.provenance = None;
+    config.verdict_cmd = None;
+    select(&mut config, "list");
+    config
+}
+
+pub fn list(ctx: &mut Context) -> Result<Vec<String>, String> {
+    let base = ctx.config.clone();
+    info!("Listing the tests in the guest harness");
+    ctx.reset_run(listing(&base));
+    let result = Pipeline::execution().run(ctx);
+    let cases = ctx.guest_tests.clone();
+    ctx.reset_run(base);
+    result?;
+    if cases.is_empty() {
+        return Err("The guest test harness listed no tests".to_string());
+    }
+    info!("Guest harness has {} test(s)", cases.len());
+    Ok(cases)
+}
+
+pub fn selectable(ctx: &Context) -> bool {
+    if !detect(ctx) {
+        return false;
+    }
+    if ctx.config.arch() != Arch::X86_64 {
+        warn!("The guest test harness reads its selection over x86 fw_cfg ports, running all tests in one boot");
+        return false;
+    }
+    true
+}
+
+pub fn enabled(ctx: &Context) -> bool {
+    ctx.config.test.as_ref().is_some_and(|test| test.isolate) && !ctx.dry_run && selectable(ctx)
+}
+
+pub fn expand(runs: Runs, cases: &[String]) -> Runs {
+    let mut expanded = Vec::new();
+    for (label, config) in runs {
+        for case in cases {
+            let mut config = config.clone();
+            select(&mut config, &format!("run:{}", case));
+            let part = format!("test={}", case);
+            expanded.push((if label.is_empty() { part } else { format!("{} {}", label, part) }, config));
+        }
+    }
+    expanded
+}
@@ -0,0 +1,111 @@
+use log::warn;
+use crate::privilege::which;
+use crate::qemu::Display;
+use crate::{overlay, RunnerConfig};
+
+const VNC_VIEWERS: &[&str] = &["vncviewer", "gvncviewer", "remmina", "vinagre", "krdc"];
+
+pub struct Tool {
+    pub name: &'static str,
+    pub feature: &'static str,
+    pub needed: bool,
+    pub path: Option<String>,
+    pub fallback: Option<&'static str>,
+    pub advisory: bool,
+    disable: fn(&mut RunnerConfig),
+}
+
+fn find(candidates: &[String]) -> Option<String> {
+    candidates.iter().filter_map(|candidate| which(candidate)).find(|path| path.is_file())
+        .map(|path| path.display().to_string())
+}
+
+impl Tool {
+    fn new(name: &'static str, candidates: &[String], feature: &'static str, needed: bool) -> Tool {
+        Tool { name, feature, needed, path: find(candidates), fallback: None, advisory: false, disable: |_| {} }
+    }
+
+    fn or_else(self, fallback: &'static str, disable: fn(&mut RunnerConfig)) -> Tool {
+        Tool { fallback: Some(fallback), disable, ..self }
+    }
+
+    fn advice(self, fallback: &'static str) -> Tool {
+        Tool { fallback: Some(fallback), advisory: true, ..self }
+    }
+
+    fn fatal(&self, strict: bool) -> bool {
+        self.missing() && !self.advisory && (strict || self.fallback.is_none())
+    }
+
+    pub fn missing(&self) -> bool {
+        self.needed && self.path.is_none()
+    }
+}
+
+pub fn survey(config: &RunnerConfig) -> Vec<Tool> {
+    let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+    let secure_boot = config.secure_boot.as_ref();
+    let swtpm = config.tpm.as_ref().map(|tpm| tpm.swtpm.clone()).unwrap_or_else(|| "swtpm".to_string());
+    vec![
+        Tool::new("swtpm", &[swtpm], "tpm", config.tpm.is_some())
+            .or_else("booting without a TPM", |config| config.tpm = None),
+        Tool::new("sbsign", &names(&["sbsign"]), "secure_boot.sign",
+                  secure_boot.is_some_and(|secure_boot| secure_boot.sign.is_some()))
+            .or_else("booting the unsigned binary", |config| {
+                if let Some(secure_boot) = &mut config.secure_boot {
+                    secure_boot.sign = None;
+                }
+            }),
+        Tool::new("virt-fw-vars", &names(&["virt-fw-vars"]), "secure_boot key enrollment",
+                  secure_boot.is_some_and(|secure_boot| secure_boot.enrolls()))
+            .or_else("leaving Secure Boot in setup mode", |config| {
+                if let Some(secure_boot) = &mut config.secure_boot {
+                    secure_boot.pk = None;
+                    secure_boot.kek.clear();
+                    secure_boot.db.clear();
+                }
+            }),
+        Tool::new("qemu-img", &[overlay::qemu_img(config).display().to_string()], "base_overlays",
+                  config.base_overlays),
+        Tool::new("gdb", &names(&["gdb", "gdb-multiarch", "lldb"]), "gdb",
+                  config.gdb.as_ref().is_some_and(|gdb| gdb.wait))
+            .or_else("booting without waiting for a debugger", |config| {
+                if let Some(gdb) = &mut config.gdb {
+                    gdb.wait = false;
+                }
+            }),
+        Tool::new("vncviewer", &names(VNC_VIEWERS), "display = \"vnc\"", config.display == Some(Display::Vnc))
+            .advice("connect any VNC client to localhost:5900"),
+    ]
+}
+
+pub fn degrade(config: &mut RunnerConfig) -> Result<(), String> {
+    let missing = survey(config).into_iter().filter(Tool::missing).collect::<Vec<_>>();
+    let fatal = missing.iter().filter(|tool| tool.fatal(config.strict_tools))
+        .map(|tool| format!("{} (for {})", tool.name, tool.feature))
+        .collect::<Vec<_>>();
+    if !fatal.is_empty() {
+        return Err(format!("Missing required tool(s): {}", fatal.join(", ")));
+    }
+    for tool in missing {
+        warn!("{} not found, {}", tool.name, tool.fallback.unwrap_or_default());
+        (tool.disable)(config);
+    }
+    Ok(())
+}
+
+pub fn report(config: &RunnerConfig) -> bool {
+    let tools = survey(config);
+    println!("Optional tools:");
+    for tool in &tools {
+        let status = match (&tool.path, tool.needed) {
+            (Some(path), _) => path.clone(),
+            (None, _) if tool.fatal(config.strict_tools) => "missing, run fails".to_string(),
+            (None, true) => format!("missing, {}", tool.fallback.unwrap_or_default()),
+            (None, false) => "missing, not needed".to_string(),
+        };
+        println!("    {:<14} {:<28} {}", tool.name, tool.feature, status);
+    }
+    println!("    {:<14} {:<28} built in", "fatfs", "esp_image");
+    !tools.iter().any(|tool| tool.fatal(config.strict_tools))
+}
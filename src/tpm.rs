@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::info;
+use serde::{Deserialize, Serialize};
+use crate::arch::Arch;
+use crate::reaper;
+
+const SOCKET_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct TpmConfig {
+    #[serde(default = "default_swtpm")]
+    pub swtpm: String,
+    #[serde(default)]
+    pub state_dir: Option<String>,
+}
+
+fn default_swtpm() -> String {
+    "swtpm".to_string()
+}
+
+pub struct Swtpm {
+    child: Child,
+}
+
+impl Drop for Swtpm {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        reaper::untrack(self.child.id());
+    }
+}
+
+impl TpmConfig {
+    pub fn validate(&self, arch: Arch) -> Result<(), String> {
+        if arch == Arch::Riscv64 {
+            return Err("QEMU has no TPM device for riscv64 virt machines".to_string());
+        }
+        Ok(())
+    }
+
+    fn socket(run_dir: &Path) -> PathBuf {
+        run_dir.join("swtpm.sock")
+    }
+
+    pub fn state(&self, run_dir: &Path) -> PathBuf {
+        self.state_dir.as_ref().map(PathBuf::from).unwrap_or_else(|| run_dir.join("tpm"))
+    }
+
+    pub fn command(&self, run_dir: &Path, artifacts_dir: &str) -> Command {
+        let mut cmd = Command::new(&self.swtpm);
+        cmd.args(["socket", "--tpm2", "--terminate", "--tpmstate"])
+            .arg(format!("dir={}", self.state(run_dir).display()))
+            .arg("--ctrl")
+            .arg(format!("type=unixio,path={}", Self::socket(run_dir).display()))
+            .arg("--log")
+            .arg(format!("file={},level=20", Path::new(artifacts_dir).join("swtpm.log").display()));
+        cmd
+    }
+
+    pub fn spawn(&self, run_dir: &Path, artifacts_dir: &str) -> Result<Swtpm, String> {
+        let state = self.state(run_dir);
+        fs::create_dir_all(&state).map_err(|e| format!("Failed to create {}: {}", state.display(), e))?;
+        fs::create_dir_all(artifacts_dir).map_err(|e| format!("Failed to create {}: {}", artifacts_dir, e))?;
+        let socket = Self::socket(run_dir);
+        let _ = fs::remove_file(&socket);
+        let child = self.command(run_dir, artifacts_dir).stdin(Stdio::null()).spawn()
+            .map_err(|e| format!("Failed to start {}: {}", self.swtpm, e))?;
+        reaper::track(child.id(), "swtpm");
+        let mut swtpm = Swtpm { child };
+        let start = Instant::now();
+        while !socket.exists() {
+            if let Ok(Some(status)) = swtpm.child.try_wait() {
+                return Err(format!("{} exited with {} before creating its socket", self.swtpm, status));
+            }
+            if start.elapsed() > SOCKET_WAIT {
+                return Err(format!("{} did not create {} in time", self.swtpm, socket.display()));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        info!("TPM 2.0 emulator listening on {}, state in {}", socket.display(), state.display());
+        Ok(swtpm)
+    }
+
+    pub fn qemu_args(&self, run_dir: &Path, arch: Arch) -> Vec<String> {
+        let device = if arch == Arch::Aarch64 { "tpm-tis-device" } else { "tpm-tis" };
+        vec![
+            "-chardev".to_string(), format!("socket,id=chrtpm,path={}", Self::socket(run_dir).display()),
+            "-tpmdev".to_string(), "emulator,id=tpm0,chardev=chrtpm".to_string(),
+            "-device".to_string(), format!("{},tpmdev=tpm0", device),
+        ]
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::serial::LogFollower;
+use crate::RunnerConfig;
+
+const POLL: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Builtin {
+    StripAnsi,
+    Timestamp,
+    DedupeProgressLines,
+    JsonlConvert,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TransformStep {
+    Builtin(Builtin),
+    Command { cmd: String },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct LogTransform {
+    pub output: String,
+    pub steps: Vec<TransformStep>,
+}
+
+#[derive(Clone)]
+struct Line {
+    t_ms: Option<u64>,
+    text: String,
+}
+
+impl LogTransform {
+    pub fn validate(&self) -> Result<(), String> {
+        let output = Path::new(&self.output);
+        if self.output.is_empty() || output.components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(format!("log_transforms output {} must be a relative path in artifacts_dir", self.output));
+        }
+        if self.steps.is_empty() {
+            return Err(format!("log_transforms {} has no steps", self.output));
+        }
+        if self.steps.iter().any(|step| matches!(step, TransformStep::Command { cmd } if cmd.trim().is_empty())) {
+            return Err(format!("log_transforms {} has an empty cmd", self.output));
+        }
+        Ok(())
+    }
+
+    fn run(&self, lines: Vec<Line>, artifacts_dir: &Path) -> Result<Vec<Line>, String> {
+        let mut lines = lines;
+        for step in &self.steps {
+            lines = match step {
+                TransformStep::Builtin(Builtin::StripAnsi) => lines.into_iter()
+                    .map(|line| Line { text: strip_ansi(&line.text), ..line })
+                    .collect(),
+                TransformStep::Builtin(Builtin::Timestamp) => lines.into_iter()
+                    .map(|line| Line { text: format!("[{}] {}", stamp(line.t_ms), line.text), ..line })
+                    .collect(),
+                TransformStep::Builtin(Builtin::DedupeProgressLines) => dedupe(lines),
+                TransformStep::Builtin(Builtin::JsonlConvert) => lines.into_iter().enumerate()
+                    .map(|(index, line)| Line {
+                        text: json!({"line": index + 1, "t_ms": line.t_ms, "text": line.text}).to_string(),
+                        ..line
+                    })
+                    .collect(),
+                TransformStep::Command { cmd } => filter(cmd, &lines, artifacts_dir)?,
+            };
+        }
+        Ok(lines)
+    }
+}
+
+fn stamp(t_ms: Option<u64>) -> String {
+    match t_ms {
+        Some(t_ms) => format!("{:>5}.{:03}", t_ms / 1000, t_ms % 1000),
+        None => format!("{:>9}", "?"),
+    }
+}
+
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('[') => {
+                while chars.next().is_some_and(|c| !('@'..='~').contains(&c)) {}
+            }
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+fn progress_key(text: &str) -> String {
+    text.chars().filter(|c| !c.is_ascii_digit()).collect::<String>().trim().to_string()
+}
+
+fn dedupe(lines: Vec<Line>) -> Vec<Line> {
+    let mut out: Vec<Line> = Vec::new();
+    for line in lines {
+        let text = line.text.rsplit('\r').find(|part| !part.is_empty()).unwrap_or_default().to_string();
+        let line = Line { text, ..line };
+        match out.last_mut() {
+            Some(last) if !line.text.is_empty() && progress_key(&last.text) == progress_key(&line.text)
+                && last.text.chars().any(|c| c.is_ascii_digit()) => *last = line,
+            _ => out.push(line),
+        }
+    }
+    out
+}
+
+fn filter(cmd: &str, lines: &[Line], artifacts_dir: &Path) -> Result<Vec<Line>, String> {
+    let mut parts = cmd.split_whitespace();
+    let program = parts.next().unwrap_or_default();
+    let mut child = Command::new(program).args(parts)
+        .env("UEFAPI_ARTIFACTS_DIR", artifacts_dir)
+        .stdin(Stdio::piped()).stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run log filter {}: {}", program, e))?;
+    let input = lines.iter().map(|line| format!("{}\n", line.text)).collect::<String>();
+    let mut stdin = child.stdin.take().expect("Failed to open log filter stdin");
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| format!("Failed to run log filter {}: {}", program, e))?;
+    let _ = writer.join();
+    if !output.status.success() {
+        return Err(format!("Log filter {} exited with {}", cmd, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).lines().map(|text| Line { t_ms: None, text: text.to_string() })
+        .collect())
+}
+
+pub fn timed(config: &RunnerConfig) -> bool {
+    config.log_transforms.iter().flat_map(|transform| &transform.steps)
+        .any(|step| matches!(step, TransformStep::Builtin(Builtin::Timestamp | Builtin::JsonlConvert)))
+}
+
+pub fn watch(log: &Path, started: Instant, done: &AtomicBool) -> Vec<u64> {
+    let mut follower = LogFollower::new(log);
+    let mut times = Vec::new();
+    loop {
+        let finished = done.load(Ordering::Acquire);
+        let now = started.elapsed().as_millis() as u64;
+        times.extend(follower.poll().iter().map(|_| now));
+        if finished {
+            break;
+        }
+        thread::sleep(POLL);
+    }
+    times
+}
+
+pub fn apply(config: &RunnerConfig, log: &Path, times: &[u64]) {
+    if config.log_transforms.is_empty() {
+        return;
+    }
+    let Ok(text) = fs::read(log) else {
+        return;
+    };
+    let text = String::from_utf8_lossy(&text);
+    let lines = text.lines().enumerate()
+        .map(|(index, text)| Line { t_ms: times.get(index).copied(), text: text.to_string() })
+        .collect::<Vec<_>>();
+    let artifacts_dir = Path::new(&config.artifacts_dir);
+    for transform in &config.log_transforms {
+        let path = artifacts_dir.join(&transform.output);
+        let written = transform.run(lines.clone(), artifacts_dir).and_then(|lines| {
+            let out = lines.iter().map(|line| format!("{}\n", line.text)).collect::<String>();
+            path.parent().map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, out))
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        });
+        match written {
+            Ok(()) => info!("Serial log transformed into {}", path.display()),
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(texts: &[&str]) -> Vec<Line> {
+        texts.iter().map(|text| Line { t_ms: None, text: text.to_string() }).collect()
+    }
+
+    fn texts(lines: &[Line]) -> Vec<&str> {
+        lines.iter().map(|line| line.text.as_str()).collect()
+    }
+
+    #[test]
+    fn strip_ansi_removes_csi_and_osc_sequences() {
+        assert_eq!(strip_ansi("\x1b[1;32mPASS\x1b[0m done"), "PASS done");
+        assert_eq!(strip_ansi("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_ansi("\x1b]8;;https://example.com\x1b\\link"), "link");
+        assert_eq!(strip_ansi("\x1b[2Jplain"), "plain");
+        assert_eq!(strip_ansi("no escapes"), "no escapes");
+    }
+
+    #[test]
+    fn dedupe_keeps_the_last_progress_line() {
+        let out = dedupe(lines(&["Loading 10%", "Loading 50%", "Loading 100%", "done"]));
+        assert_eq!(texts(&out), ["Loading 100%", "done"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_the_last_carriage_return_frame() {
+        let out = dedupe(lines(&["copy 1/3\rcopy 2/3\rcopy 3/3\r", "next"]));
+        assert_eq!(texts(&out), ["copy 3/3", "next"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_repeated_lines_without_digits() {
+        let out = dedupe(lines(&["same", "same", "", ""]));
+        assert_eq!(texts(&out), ["same", "same", "", ""]);
+    }
+}
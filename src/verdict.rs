@@ -0,0 +1,60 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+use log::info;
+use crate::outcome::{RunOutcome, TestResult, TestStatus};
+
+const SKIP_CODE: i32 = 77;
+
+fn status(code: Option<i32>) -> TestStatus {
+    match code {
+        Some(0) => TestStatus::Passed,
+        Some(SKIP_CODE) => TestStatus::Skipped,
+        _ => TestStatus::Failed,
+    }
+}
+
+pub fn run(cmd: &str, outcome: &RunOutcome, serial_log: Option<&Path>, artifacts_dir: &Path,
+           run_dir: &Path) -> TestResult {
+    let failed = |message: String| TestResult {
+        name: "verdict".to_string(),
+        status: TestStatus::Failed,
+        duration_ms: None,
+        message: Some(message),
+    };
+    let report = run_dir.join("outcome.json");
+    let json = serde_json::to_string_pretty(outcome).expect("Failed to serialize outcome");
+    if let Err(e) = fs::write(&report, json) {
+        return failed(format!("Failed to write {}: {}", report.display(), e));
+    }
+    let mut parts = cmd.split_whitespace();
+    let Some(program) = parts.next() else {
+        return failed("verdict_cmd is empty".to_string());
+    };
+    let serial_log = serial_log.map(|log| log.display().to_string()).unwrap_or_default();
+    info!("Running verdict command {}", cmd);
+    let start = Instant::now();
+    let output = match Command::new(program).args(parts).arg(&serial_log).arg(&report).arg(artifacts_dir)
+        .env("UEFAPI_SERIAL_LOG", &serial_log)
+        .env("UEFAPI_REPORT", &report)
+        .env("UEFAPI_ARTIFACTS_DIR", artifacts_dir)
+        .output() {
+        Ok(output) => output,
+        Err(e) => return failed(format!("Failed to run verdict command {}: {}", program, e)),
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let message = stdout.lines().map(str::trim).rfind(|line| !line.is_empty()).map(str::to_string);
+    let status = status(output.status.code());
+    info!("Verdict command decided {:?}{}", status,
+          message.as_deref().map(|message| format!(": {}", message)).unwrap_or_default());
+    TestResult {
+        name: "verdict".to_string(),
+        status,
+        duration_ms: Some(start.elapsed().as_millis() as u64),
+        message: message.or_else(|| (status == TestStatus::Failed).then(|| match output.status.code() {
+            Some(code) => format!("verdict command exited with {}", code),
+            None => "verdict command was killed".to_string(),
+        })),
+    }
+}
@@ -0,0 +1,39 @@
+use std::process::Command;
+use log::info;
+
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+const PACKAGE: &str = env!("CARGO_PKG_NAME");
+
+fn parse(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches(">=").trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+fn install_hint(version: &str) -> String {
+    format!("run `{0} self-update` or `cargo install {0} --version {1}`", PACKAGE, version.trim_start_matches(">="))
+}
+
+pub fn check_required(required: &str) -> Result<(), String> {
+    let wanted = parse(required).ok_or_else(|| format!("invalid required_version {}", required))?;
+    let installed = parse(CURRENT).expect("Invalid package version");
+    if installed < wanted {
+        return Err(format!("this project requires {} {} but {} is installed, {}",
+                           PACKAGE, required, CURRENT, install_hint(required)));
+    }
+    Ok(())
+}
+
+pub fn self_update(version: Option<&str>) -> Result<(), String> {
+    let mut cmd = Command::new("cargo");
+    cmd.args(["install", PACKAGE, "--locked", "--force"]);
+    if let Some(version) = version {
+        cmd.args(["--version", version]);
+    }
+    info!("Updating {} {} with cargo install", PACKAGE, CURRENT);
+    let status = cmd.status().map_err(|e| format!("Failed to run cargo install: {}", e))?;
+    if !status.success() {
+        return Err(format!("cargo install failed with {}", status));
+    }
+    Ok(())
+}
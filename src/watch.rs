@@ -0,0 +1,110 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use log::{error, info, warn};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use crate::handle::{self, RunHandle, RunState};
+use crate::hooks::Hooks;
+use crate::RunnerConfig;
+
+const DEBOUNCE: Duration = Duration::from_millis(300);
+const POLL: Duration = Duration::from_millis(200);
+
+fn absolute(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let path = if path.is_absolute() { path.to_path_buf() } else { env::current_dir().unwrap_or_default().join(path) };
+    path.canonicalize().unwrap_or(path)
+}
+
+struct Filter {
+    ignored: Vec<PathBuf>,
+}
+
+impl Filter {
+    fn new(config: &RunnerConfig, project: &Path) -> Self {
+        let mut ignored = vec![project.join("target"), project.join(".git"), absolute(&config.artifacts_dir),
+                               absolute(&config.log_path), absolute(&config.binary_path)];
+        ignored.extend(config.work_dir.as_deref().filter(|dir| *dir != "tmpfs").map(absolute));
+        Filter { ignored }
+    }
+
+    fn relevant(&self, path: &Path) -> bool {
+        let hidden = path.file_name().map(|name| name.to_string_lossy())
+            .is_some_and(|name| name.starts_with('.') || name.ends_with('~'));
+        !hidden && !self.ignored.iter().any(|ignored| path.starts_with(ignored))
+    }
+
+    fn changed(&self, event: &Event) -> Option<PathBuf> {
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            return None;
+        }
+        event.paths.iter().find(|path| self.relevant(path)).cloned()
+    }
+}
+
+fn wait_for_change(events: &Receiver<notify::Result<Event>>, filter: &Filter, run: &RunHandle) -> Option<PathBuf> {
+    let mut reported = false;
+    loop {
+        match events.recv_timeout(POLL) {
+            Ok(Ok(event)) => {
+                if let Some(path) = filter.changed(&event) {
+                    while events.recv_timeout(DEBOUNCE).is_ok() {}
+                    return Some(path);
+                }
+            }
+            Ok(Err(e)) => warn!("File watch error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+        if handle::interrupted() {
+            return None;
+        }
+        if !reported && run.is_finished() {
+            info!("Waiting for changes in the project");
+            reported = true;
+        }
+    }
+}
+
+fn report(run: RunHandle) {
+    match run.wait() {
+        Ok(outcome) => info!("QEMU exited with {}", outcome.describe()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn run(config: &RunnerConfig, kill: bool) -> bool {
+    let project = absolute(&config.project_path);
+    let (sender, events) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Failed to start the file watcher: {}", e);
+            return false;
+        }
+    };
+    if let Err(e) = watcher.watch(&project, RecursiveMode::Recursive) {
+        error!("Failed to watch {}: {}", project.display(), e);
+        return false;
+    }
+    let filter = Filter::new(config, &project);
+    info!("Watching {} for changes", project.display());
+    loop {
+        let run = RunHandle::spawn(config.clone(), Hooks::default());
+        let Some(path) = wait_for_change(&events, &filter, &run) else {
+            report(run);
+            return true;
+        };
+        info!("{} changed, restarting", path.display());
+        if !run.is_finished() {
+            if kill || run.status() == RunState::Preparing {
+                run.kill();
+            } else {
+                run.shutdown();
+            }
+        }
+        report(run);
+        while events.try_recv().is_ok() {}
+    }
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogModel {
+    #[default]
+    I6300esb,
+    Ib700,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WatchdogAction {
+    #[default]
+    Reset,
+    Shutdown,
+    Poweroff,
+    Pause,
+    Debug,
+    None,
+    InjectNmi,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    #[serde(default)]
+    pub model: WatchdogModel,
+    #[serde(default)]
+    pub action: WatchdogAction,
+}
+
+impl WatchdogModel {
+    pub fn device(&self) -> &'static str {
+        match self {
+            WatchdogModel::I6300esb => "i6300esb",
+            WatchdogModel::Ib700 => "ib700",
+        }
+    }
+}
+
+impl WatchdogAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatchdogAction::Reset => "reset",
+            WatchdogAction::Shutdown => "shutdown",
+            WatchdogAction::Poweroff => "poweroff",
+            WatchdogAction::Pause => "pause",
+            WatchdogAction::Debug => "debug",
+            WatchdogAction::None => "none",
+            WatchdogAction::InjectNmi => "inject-nmi",
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn qemu_args(&self) -> Vec<String> {
+        vec![
+            "-device".to_string(), format!("{},id=watchdog0", self.model.device()),
+            "-watchdog-action".to_string(), self.action.name().to_string(),
+        ]
+    }
+}